@@ -39,6 +39,7 @@ jq --arg word "$WORD" \
      "interval": 1,
      "repetitions": 0,
      "next_review": $date,
+     "added": $date,
      "notes": ""
    }]' "$VOCAB_FILE" > "$VOCAB_FILE.tmp" && mv "$VOCAB_FILE.tmp" "$VOCAB_FILE"
 
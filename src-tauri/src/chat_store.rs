@@ -0,0 +1,214 @@
+//! Per-language chat log in SQLite.
+//!
+//! Claude's own session files change format, rotate, and disappear with a
+//! reinstall, so every exchange is also recorded here. History written before
+//! the log existed is imported from the session files once.
+
+use std::path::Path;
+use std::time::Duration;
+
+use rusqlite::{params, Connection, OptionalExtension, TransactionBehavior};
+
+use crate::ChatMessage;
+
+/// Database file inside each language dir.
+pub const CHAT_DB_FILE: &str = "chat.db";
+
+/// `meta` key set once the session-file history has been imported.
+const BACKFILLED_KEY: &str = "jsonl_backfilled";
+
+/// How long to wait for another connection's write to finish.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS messages (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    role TEXT NOT NULL,
+    content TEXT NOT NULL,
+    timestamp TEXT
+);
+CREATE TABLE IF NOT EXISTS meta (
+    key TEXT PRIMARY KEY,
+    value TEXT NOT NULL
+);";
+
+pub struct ChatStore {
+    conn: Connection,
+}
+
+impl ChatStore {
+    /// Opens (creating if needed) the chat log for a language directory.
+    pub fn open(lang_dir: &Path) -> Result<Self, String> {
+        let conn = Connection::open(lang_dir.join(CHAT_DB_FILE))
+            .map_err(|e| format!("Failed to open chat database: {}", e))?;
+        conn.busy_timeout(BUSY_TIMEOUT)
+            .map_err(|e| format!("Failed to configure chat database: {}", e))?;
+        conn.execute_batch(SCHEMA)
+            .map_err(|e| format!("Failed to create chat tables: {}", e))?;
+        Ok(ChatStore { conn })
+    }
+
+    /// Imports pre-existing history from `load` the first time it's called for
+    /// this database; later calls do nothing. Runs in one transaction, so
+    /// concurrent callers can't import twice.
+    pub fn backfill(
+        &mut self,
+        load: impl FnOnce() -> Result<Vec<ChatMessage>, String>,
+    ) -> Result<(), String> {
+        let tx = self
+            .conn
+            .transaction_with_behavior(TransactionBehavior::Immediate)
+            .map_err(|e| format!("Failed to start chat import: {}", e))?;
+        let done: Option<String> = tx
+            .query_row(
+                "SELECT value FROM meta WHERE key = ?1",
+                params![BACKFILLED_KEY],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(|e| format!("Failed to read chat database: {}", e))?;
+        if done.is_some() {
+            return Ok(());
+        }
+
+        insert_messages(&tx, &load()?)?;
+        tx.execute(
+            "INSERT INTO meta (key, value) VALUES (?1, ?2)",
+            params![BACKFILLED_KEY, "1"],
+        )
+        .map_err(|e| format!("Failed to record chat import: {}", e))?;
+        tx.commit()
+            .map_err(|e| format!("Failed to save chat import: {}", e))
+    }
+
+    /// Appends messages in order.
+    pub fn append(&mut self, messages: &[ChatMessage]) -> Result<(), String> {
+        let tx = self
+            .conn
+            .transaction()
+            .map_err(|e| format!("Failed to start chat write: {}", e))?;
+        insert_messages(&tx, messages)?;
+        tx.commit()
+            .map_err(|e| format!("Failed to save chat messages: {}", e))
+    }
+
+    /// Replaces the tutor's reply to the latest learner message, provided that
+    /// message reads `message` and has a reply. Returns false, changing
+    /// nothing, otherwise.
+    pub fn replace_last_reply(
+        &mut self,
+        message: &str,
+        reply: &ChatMessage,
+    ) -> Result<bool, String> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT id, role, content FROM messages ORDER BY id DESC LIMIT 2")
+            .map_err(|e| format!("Failed to read chat database: {}", e))?;
+        let last: Vec<(i64, String, String)> = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .and_then(|rows| rows.collect())
+            .map_err(|e| format!("Failed to read chat database: {}", e))?;
+
+        let [(reply_id, reply_role, _), (_, message_role, content)] = last.as_slice() else {
+            return Ok(false);
+        };
+        if reply_role != "assistant" || message_role != "user" || content != message {
+            return Ok(false);
+        }
+        self.conn
+            .execute(
+                "UPDATE messages SET content = ?1, timestamp = ?2 WHERE id = ?3",
+                params![reply.content, reply.timestamp, reply_id],
+            )
+            .map_err(|e| format!("Failed to write chat message: {}", e))?;
+        Ok(true)
+    }
+
+    /// Deletes every message after the first `keep`. Returns how many went.
+    pub fn truncate(&mut self, keep: usize) -> Result<usize, String> {
+        self.conn
+            .execute(
+                "DELETE FROM messages WHERE id NOT IN \
+                 (SELECT id FROM messages ORDER BY id LIMIT ?1)",
+                params![keep as i64],
+            )
+            .map_err(|e| format!("Failed to delete chat messages: {}", e))
+    }
+
+    /// Whether no message has been recorded yet.
+    pub fn is_empty(&self) -> Result<bool, String> {
+        self.conn
+            .query_row("SELECT NOT EXISTS (SELECT 1 FROM messages)", [], |row| {
+                row.get(0)
+            })
+            .map_err(|e| format!("Failed to read chat database: {}", e))
+    }
+
+    /// Every message, oldest first, with its row id as `id`.
+    pub fn messages(&self) -> Result<Vec<ChatMessage>, String> {
+        self.query_messages(
+            "SELECT id, role, content, timestamp FROM messages ORDER BY id",
+            [],
+        )
+    }
+
+    /// Number of messages recorded.
+    pub fn count(&self) -> Result<usize, String> {
+        self.conn
+            .query_row("SELECT COUNT(*) FROM messages", [], |row| {
+                row.get::<_, i64>(0)
+            })
+            .map(|n| n as usize)
+            .map_err(|e| format!("Failed to read chat database: {}", e))
+    }
+
+    /// Up to `limit` messages, skipping the `offset` most recent, oldest first.
+    /// Only the requested rows are read.
+    pub fn page(&self, offset: usize, limit: usize) -> Result<Vec<ChatMessage>, String> {
+        let mut messages = self.query_messages(
+            "SELECT id, role, content, timestamp FROM messages ORDER BY id DESC LIMIT ?1 OFFSET ?2",
+            params![to_sql_count(limit), to_sql_count(offset)],
+        )?;
+        messages.reverse();
+        Ok(messages)
+    }
+
+    fn query_messages(
+        &self,
+        sql: &str,
+        params: impl rusqlite::Params,
+    ) -> Result<Vec<ChatMessage>, String> {
+        let mut stmt = self
+            .conn
+            .prepare(sql)
+            .map_err(|e| format!("Failed to read chat database: {}", e))?;
+        let rows = stmt
+            .query_map(params, |row| {
+                Ok(ChatMessage {
+                    id: Some(row.get::<_, i64>(0)?.to_string()),
+                    role: row.get(1)?,
+                    content: row.get(2)?,
+                    timestamp: row.get(3)?,
+                })
+            })
+            .map_err(|e| format!("Failed to read chat database: {}", e))?;
+        rows.collect::<Result<_, _>>()
+            .map_err(|e| format!("Failed to read chat message: {}", e))
+    }
+}
+
+/// SQLite integers are signed; `usize::MAX` (meaning "no limit") saturates.
+fn to_sql_count(n: usize) -> i64 {
+    i64::try_from(n).unwrap_or(i64::MAX)
+}
+
+fn insert_messages(conn: &Connection, messages: &[ChatMessage]) -> Result<(), String> {
+    let mut stmt = conn
+        .prepare_cached("INSERT INTO messages (role, content, timestamp) VALUES (?1, ?2, ?3)")
+        .map_err(|e| format!("Failed to write chat database: {}", e))?;
+    for msg in messages {
+        stmt.execute(params![msg.role, msg.content, msg.timestamp])
+            .map_err(|e| format!("Failed to write chat message: {}", e))?;
+    }
+    Ok(())
+}
@@ -1,13 +1,26 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::env;
 use std::fs::{self, File};
-use std::io::{BufRead, BufReader};
+use std::future::Future;
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
-use std::process::Command;
-use std::time::Duration;
+use std::pin::Pin;
+use std::process::{Child, ChildStderr, ChildStdout, Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+use std::time::{Duration, SystemTime};
 
-use chrono::Local;
+use chrono::{DateTime, Datelike, Days, Local, NaiveDate, NaiveDateTime};
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
+use serde_json::{json, Value};
+use tauri::{AppHandle, Emitter, State};
+use tracing::{error, info, warn};
+
+mod chat_store;
+mod sm2;
+
+use chat_store::ChatStore;
 
 // ============================================================================
 // Embedded Templates
@@ -30,7 +43,9 @@ const USER_OVERRIDES_TEMPLATE: &str = r#"{
   "mode": "learning",
   "preferences": {
     "new_vocab_per_exchange": 2,
-    "show_romanization": true
+    "show_romanization": true,
+    "session_stale_after_days": 14,
+    "never_reviewed_after_days": 7
   },
   "notes": ""
 }"#;
@@ -40,96 +55,301 @@ const USER_OVERRIDES_TEMPLATE: &str = r#"{
 /// process the message, and write updates. Shorter timeouts may cause incomplete updates.
 const TRACKER_TIMEOUT_SECS: u64 = 60;
 
+/// Default timeout for the responder agent, covering the whole streamed reply.
+const RESPONDER_TIMEOUT_SECS: u64 = 120;
+
+/// Environment variable overriding `RESPONDER_TIMEOUT_SECS`, for slow connections.
+const RESPONDER_TIMEOUT_ENV: &str = "YSFL_RESPONDER_TIMEOUT_SECS";
+
+/// The responder timeout, read once from the environment at first use.
+fn responder_timeout_secs() -> u64 {
+    static TIMEOUT: OnceLock<u64> = OnceLock::new();
+    *TIMEOUT.get_or_init(|| {
+        let Ok(value) = env::var(RESPONDER_TIMEOUT_ENV) else {
+            return RESPONDER_TIMEOUT_SECS;
+        };
+        match value.trim().parse::<u64>() {
+            Ok(secs) if secs > 0 => secs,
+            _ => {
+                warn!(
+                    "[Responder] Ignoring invalid {}={:?}, using {}s",
+                    RESPONDER_TIMEOUT_ENV, value, RESPONDER_TIMEOUT_SECS
+                );
+                RESPONDER_TIMEOUT_SECS
+            }
+        }
+    })
+}
+
+/// Default number of retries after a transient claude failure.
+const CLAUDE_RETRIES: u32 = 2;
+
+/// Environment variable overriding `CLAUDE_RETRIES`; 0 disables retrying.
+const CLAUDE_RETRIES_ENV: &str = "YSFL_CLAUDE_RETRIES";
+
+/// Delay before the first retry; each further retry doubles it.
+const CLAUDE_RETRY_BASE_DELAY_MS: u64 = 1000;
+
+/// The retry count, read once from the environment at first use.
+fn claude_retries() -> u32 {
+    static RETRIES: OnceLock<u32> = OnceLock::new();
+    *RETRIES.get_or_init(|| {
+        let Ok(value) = env::var(CLAUDE_RETRIES_ENV) else {
+            return CLAUDE_RETRIES;
+        };
+        value.trim().parse::<u32>().unwrap_or_else(|_| {
+            warn!(
+                "[Claude] Ignoring invalid {}={:?}, using {}",
+                CLAUDE_RETRIES_ENV, value, CLAUDE_RETRIES
+            );
+            CLAUDE_RETRIES
+        })
+    })
+}
+
 // ============================================================================
 // Language-specific configuration
 // ============================================================================
 
+/// Built-in language table, keyed by lowercase name. Entries may list `aliases`.
+const LANGUAGES_TABLE: &str = include_str!("../../templates/languages.json");
+
+/// Optional file in the data dir with learner-defined or overridden languages,
+/// in the same format as the built-in table. Fields left out keep their
+/// built-in (or default) values.
+const CUSTOM_LANGUAGES_FILE: &str = "languages.json";
+
+#[derive(Clone)]
 struct LanguageInfo {
-    native_script: &'static str,
-    romanization: &'static str,
-    notes: &'static str,
+    native_script: String,
+    romanization: String,
+    greeting: String,
+    notes: String,
 }
 
-const DEFAULT_LANGUAGE_INFO: LanguageInfo = LanguageInfo {
-    native_script: "Native Script",
-    romanization: "none",
-    notes: r#"## Language-Specific Considerations
+const DEFAULT_LANGUAGE_NOTES: &str = r#"## Language-Specific Considerations
 
 - Research and add language-specific grammar patterns as you encounter them
 - Pay attention to any unique features of this language
 - Adapt greeting and teaching style to cultural norms
-- Start with the simplest possible greeting and self-introduction"#,
-};
+- Start with the simplest possible greeting and self-introduction"#;
+
+/// Fallback for languages with no table entry.
+impl Default for LanguageInfo {
+    fn default() -> Self {
+        Self {
+            native_script: "Native Script".to_string(),
+            romanization: "none".to_string(),
+            greeting: "👋".to_string(),
+            notes: DEFAULT_LANGUAGE_NOTES.to_string(),
+        }
+    }
+}
+
+/// One entry of a languages table. Every field is optional so custom entries
+/// can override just part of a built-in language.
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct LanguageEntry {
+    aliases: Vec<String>,
+    native_script: Option<String>,
+    romanization: Option<String>,
+    greeting: Option<String>,
+    notes: Option<String>,
+}
+
+impl LanguageEntry {
+    fn apply_to(self, info: &mut LanguageInfo) {
+        if let Some(v) = self.native_script {
+            info.native_script = v;
+        }
+        if let Some(v) = self.romanization {
+            info.romanization = v;
+        }
+        if let Some(v) = self.greeting {
+            info.greeting = v;
+        }
+        if let Some(v) = self.notes {
+            info.notes = v;
+        }
+    }
+}
+
+/// Merges `entries` into `table`, registering each entry under its name and aliases.
+fn merge_language_entries(
+    table: &mut HashMap<String, LanguageInfo>,
+    entries: HashMap<String, LanguageEntry>,
+) {
+    for (name, entry) in entries {
+        let name = name.to_lowercase();
+        let aliases: Vec<String> = entry.aliases.iter().map(|a| a.to_lowercase()).collect();
+        let mut info = table.get(&name).cloned().unwrap_or_default();
+        entry.apply_to(&mut info);
+        for alias in aliases {
+            table.insert(alias, info.clone());
+        }
+        table.insert(name, info);
+    }
+}
+
+fn builtin_languages() -> &'static HashMap<String, LanguageInfo> {
+    static BUILTIN: OnceLock<HashMap<String, LanguageInfo>> = OnceLock::new();
+    BUILTIN.get_or_init(|| {
+        let entries =
+            serde_json::from_str(LANGUAGES_TABLE).expect("embedded languages.json should be valid");
+        let mut table = HashMap::new();
+        merge_language_entries(&mut table, entries);
+        table
+    })
+}
+
+fn read_custom_languages() -> Result<Option<HashMap<String, LanguageEntry>>, String> {
+    let path = get_data_dir()?.join(CUSTOM_LANGUAGES_FILE);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let content = fs::read_to_string(&path)
+        .map_err(|e| format!("Failed to read {}: {}", CUSTOM_LANGUAGES_FILE, e))?;
+    serde_json::from_str(&content)
+        .map(Some)
+        .map_err(|e| format!("Failed to parse {}: {}", CUSTOM_LANGUAGES_FILE, e))
+}
+
+/// The built-in table with the learner's custom languages merged on top.
+/// The custom file is re-read each time so edits apply without a restart.
+fn language_table() -> HashMap<String, LanguageInfo> {
+    let mut table = builtin_languages().clone();
+    match read_custom_languages() {
+        Ok(Some(custom)) => merge_language_entries(&mut table, custom),
+        Ok(None) => {}
+        Err(e) => warn!("[Languages] Ignoring custom languages: {}", e),
+    }
+    table
+}
 
 fn get_language_info(language: &str) -> LanguageInfo {
-    match language.to_lowercase().as_str() {
-        "chinese" | "mandarin" => LanguageInfo {
-            native_script: "汉字",
-            romanization: "pinyin",
-            notes: r#"## Chinese-Specific Considerations
-
-- **Tones**: Pay attention to tone usage in learner's pinyin (if provided)
-- **Characters vs Pinyin**: Track if learner uses characters or pinyin
-- **Measure words (量词)**: Track these as grammar constructs
-- **Common structures**: 是...的, 把-sentences, 被-passive, 了/过/着 aspects
-- **Cold start**: Use "👋 你好 (nǐ hǎo)" - one word with emoji and pinyin"#,
-        },
-        "korean" => LanguageInfo {
-            native_script: "한글",
-            romanization: "none",
-            notes: r#"## Korean-Specific Considerations
-
-- **Politeness levels**: Track which speech levels the learner knows (합쇼체, 해요체, 해체, etc.)
-- **Particles**: Track particles (은/는, 이/가, 을/를, etc.) as grammar
-- **Verb conjugation**: Track tense and politeness conjugation patterns
-- **Honorifics**: Note when learner uses/should use honorific forms
-- **Cold start**: Use "👋 안녕 (annyeong)" - one word with emoji and romanization"#,
-        },
-        "japanese" => LanguageInfo {
-            native_script: "日本語",
-            romanization: "romaji",
-            notes: r#"## Japanese-Specific Considerations
-
-- **Politeness levels**: Track です/ます vs casual forms
-- **Particles**: Track particles (は, が, を, に, で, etc.) as grammar
-- **Verb groups**: Note which verb conjugation patterns learner knows
-- **Kanji vs Kana**: Track which kanji the learner knows
-- **Cold start**: Use "👋 こんにちは (konnichiwa)" - one word with emoji and romaji"#,
-        },
-        "spanish" => LanguageInfo {
-            native_script: "Español",
-            romanization: "none",
-            notes: r#"## Spanish-Specific Considerations
-
-- **Verb conjugation**: Track which tenses and moods learner knows
-- **Ser vs Estar**: Track as separate grammar constructs
-- **Subjunctive**: Introduce gradually, it's complex
-- **Gender agreement**: Track as grammar construct
-- **Cold start**: Use "👋 Hola" - one word with emoji"#,
-        },
-        "french" => LanguageInfo {
-            native_script: "Français",
-            romanization: "none",
-            notes: r#"## French-Specific Considerations
-
-- **Verb conjugation**: Track which tenses and moods learner knows
-- **Gender and articles**: Track as grammar constructs
-- **Liaisons**: Note pronunciation patterns
-- **Formal vs informal (tu/vous)**: Track which the learner uses
-- **Cold start**: Use "👋 Bonjour" - one word with emoji"#,
-        },
-        "german" => LanguageInfo {
-            native_script: "Deutsch",
-            romanization: "none",
-            notes: r#"## German-Specific Considerations
-
-- **Cases**: Track nominative, accusative, dative, genitive separately
-- **Verb position**: Track V2 rule, subordinate clause order
-- **Gender and articles**: Track der/die/das patterns
-- **Formal vs informal (Sie/du)**: Track which the learner uses
-- **Cold start**: Use "👋 Hallo" - one word with emoji"#,
-        },
-        _ => DEFAULT_LANGUAGE_INFO,
+    language_table()
+        .remove(&language.to_lowercase())
+        .unwrap_or_default()
+}
+
+// ============================================================================
+// Errors
+// ============================================================================
+
+/// Error returned by every command. Serialized as `{ "code": ..., "message": ... }`
+/// so the frontend can branch on `code` without matching English text.
+#[derive(Debug, Clone)]
+enum AppError {
+    NotFound(String),
+    AlreadyExists(String),
+    InvalidName(String),
+    /// The name contains a path separator or a character file systems reject.
+    InvalidNameChars(String),
+    /// The name starts with a dot or ends with a dot or space.
+    InvalidNameEdges(String),
+    /// The name is a Windows device name such as CON or NUL.
+    ReservedName(String),
+    /// The data directory can't be created or written to.
+    DataDirNotWritable(String),
+    /// Another message to the same language is still being answered.
+    Busy(String),
+    InvalidInput(String),
+    ClaudeUnavailable(String),
+    /// The Claude CLI ran but failed.
+    Claude(String),
+    Timeout(String),
+    /// The request was stopped by `cancel_message`.
+    Cancelled(String),
+    Io(String),
+    /// A tracking file doesn't match its schema. `entry` is the index of the
+    /// offending word or rule, if the problem is confined to one.
+    InvalidData {
+        message: String,
+        entry: Option<usize>,
+    },
+}
+
+impl AppError {
+    fn code(&self) -> &'static str {
+        match self {
+            AppError::NotFound(_) => "not_found",
+            AppError::AlreadyExists(_) => "already_exists",
+            AppError::InvalidName(_) => "invalid_name",
+            AppError::InvalidNameChars(_) => "invalid_name_chars",
+            AppError::InvalidNameEdges(_) => "invalid_name_edges",
+            AppError::ReservedName(_) => "reserved_name",
+            AppError::DataDirNotWritable(_) => "data_dir_not_writable",
+            AppError::Busy(_) => "busy",
+            AppError::InvalidInput(_) => "invalid_input",
+            AppError::ClaudeUnavailable(_) => "claude_unavailable",
+            AppError::Claude(_) => "claude_error",
+            AppError::Timeout(_) => "timeout",
+            AppError::Cancelled(_) => "cancelled",
+            AppError::Io(_) => "io",
+            AppError::InvalidData { .. } => "invalid_data",
+        }
+    }
+
+    fn message(&self) -> &str {
+        match self {
+            AppError::NotFound(m)
+            | AppError::AlreadyExists(m)
+            | AppError::InvalidName(m)
+            | AppError::InvalidNameChars(m)
+            | AppError::InvalidNameEdges(m)
+            | AppError::ReservedName(m)
+            | AppError::DataDirNotWritable(m)
+            | AppError::Busy(m)
+            | AppError::InvalidInput(m)
+            | AppError::ClaudeUnavailable(m)
+            | AppError::Claude(m)
+            | AppError::Timeout(m)
+            | AppError::Cancelled(m)
+            | AppError::Io(m)
+            | AppError::InvalidData { message: m, .. } => m,
+        }
+    }
+}
+
+impl std::fmt::Display for AppError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.message())
+    }
+}
+
+impl Serialize for AppError {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+        let mut state = serializer.serialize_struct("AppError", 3)?;
+        state.serialize_field("code", self.code())?;
+        state.serialize_field("message", self.message())?;
+        if let AppError::InvalidData {
+            entry: Some(index), ..
+        } = self
+        {
+            state.serialize_field("entry", index)?;
+        }
+        state.end()
+    }
+}
+
+/// Internal helpers report plain strings; those surface as I/O errors.
+impl From<String> for AppError {
+    fn from(message: String) -> Self {
+        AppError::Io(message)
+    }
+}
+
+impl From<&str> for AppError {
+    fn from(message: &str) -> Self {
+        AppError::Io(message.to_string())
+    }
+}
+
+impl From<AppError> for String {
+    fn from(error: AppError) -> Self {
+        error.to_string()
     }
 }
 
@@ -143,7 +363,7 @@ fn find_latest_jsonl_file(dir: &Path) -> Option<PathBuf> {
         .filter_map(|entry_result| match entry_result {
             Ok(e) => Some(e),
             Err(e) => {
-                eprintln!("[find_latest_jsonl] Error reading directory entry: {}", e);
+                warn!("[find_latest_jsonl] Error reading directory entry: {}", e);
                 None
             }
         })
@@ -163,50 +383,528 @@ fn find_latest_jsonl_file(dir: &Path) -> Option<PathBuf> {
     jsonl_files.first().map(|e| e.path())
 }
 
-fn parse_chat_messages_from_jsonl(path: &Path) -> Result<Vec<ChatMessage>, String> {
+/// A chat message plus the bookkeeping needed to merge session files.
+#[derive(Clone)]
+struct SessionMessage {
+    message: ChatMessage,
+    timestamp: Option<DateTime<Local>>,
+    /// Claude's per-line id, repeated when a resumed session copies old lines.
+    uuid: Option<String>,
+}
+
+impl SessionMessage {
+    /// Identifies the same message appearing in more than one session file.
+    fn dedup_key(&self) -> String {
+        match &self.uuid {
+            Some(uuid) => format!("{}:{}", self.message.role, uuid),
+            None => format!(
+                "{}:{:?}:{}",
+                self.message.role, self.timestamp, self.message.content
+            ),
+        }
+    }
+}
+
+/// Messages read from session files, with a count of the lines that couldn't
+/// be parsed, so "no history" can be told apart from "nothing readable".
+struct ParsedMessages<T> {
+    messages: Vec<T>,
+    skipped_lines: usize,
+}
+
+// Derived `Default` would require `T: Default`.
+impl<T> Default for ParsedMessages<T> {
+    fn default() -> Self {
+        ParsedMessages {
+            messages: Vec::new(),
+            skipped_lines: 0,
+        }
+    }
+}
+
+/// Parses a session file. Lines that don't parse, including a truncated last
+/// line, are left out and counted in `skipped_lines`.
+fn parse_chat_messages_from_jsonl(path: &Path) -> Result<ParsedMessages<SessionMessage>, String> {
     let file = File::open(path).map_err(|e| format!("Failed to open JSONL: {}", e))?;
-    let reader = BufReader::new(file);
-    let mut messages = Vec::new();
+    let mut reader = BufReader::new(file);
+    let mut parsed = ParsedMessages::default();
+    let mut buf = Vec::new();
+    let mut line_num = 0;
 
-    for (line_num, line_result) in reader.lines().enumerate() {
-        let line = match line_result {
-            Ok(l) => l,
+    loop {
+        buf.clear();
+        match reader.read_until(b'\n', &mut buf) {
+            Ok(0) => break,
+            Ok(_) => line_num += 1,
             Err(e) => {
-                eprintln!("[Chat history] IO error reading line {}: {}", line_num + 1, e);
-                continue;
+                warn!("[Chat history] IO error reading line {}: {}", line_num + 1, e);
+                parsed.skipped_lines += 1;
+                break;
             }
-        };
+        }
+        let line = String::from_utf8_lossy(&buf);
+        if line.trim().is_empty() {
+            continue;
+        }
 
         let json: Value = match serde_json::from_str(&line) {
             Ok(v) => v,
             Err(e) => {
-                eprintln!("[Chat history] Skipping malformed JSON at line {}: {}", line_num + 1, e);
+                warn!("[Chat history] Skipping malformed JSON at line {}: {}", line_num, e);
+                parsed.skipped_lines += 1;
                 continue;
             }
         };
 
-        if let Some(text) = extract_user_message(&json) {
-            messages.push(ChatMessage {
+        parsed.messages.extend(parse_chat_line(&json));
+    }
+
+    if parsed.skipped_lines > 0 {
+        warn!(
+            "[Chat history] Skipped {} unreadable line(s) in {}",
+            parsed.skipped_lines,
+            path.display()
+        );
+    }
+    Ok(parsed)
+}
+
+/// The user and/or assistant message recorded on one JSONL line, in that order.
+fn parse_chat_line(json: &Value) -> Vec<SessionMessage> {
+    let timestamp = message_timestamp(json);
+    let raw_timestamp = json
+        .get("timestamp")
+        .and_then(|v| v.as_str())
+        .map(str::to_string);
+    let uuid = json
+        .get("uuid")
+        .and_then(|v| v.as_str())
+        .map(str::to_string);
+    let mut messages = Vec::new();
+
+    if let Some(text) = extract_user_message(json) {
+        messages.push(SessionMessage {
+            message: ChatMessage {
                 role: "user".to_string(),
                 content: text,
-            });
-        }
+                timestamp: raw_timestamp.clone(),
+                id: uuid.clone(),
+            },
+            timestamp,
+            uuid: uuid.clone(),
+        });
+    }
 
-        if let Some(text) = extract_assistant_message(&json) {
-            messages.push(ChatMessage {
+    if let Some(text) = extract_assistant_message(json) {
+        messages.push(SessionMessage {
+            message: ChatMessage {
                 role: "assistant".to_string(),
                 content: text,
-            });
+                timestamp: raw_timestamp,
+                id: uuid.clone(),
+            },
+            timestamp,
+            uuid,
+        });
+    }
+
+    messages
+}
+
+/// Files up to this size are parsed front to back; larger ones are read from the end.
+const TAIL_MIN_FILE_BYTES: u64 = 1024 * 1024;
+/// Bytes read per step when scanning a session file backwards.
+const TAIL_CHUNK_BYTES: u64 = 64 * 1024;
+
+/// Returns the last `max_messages` messages of a session file, oldest first.
+/// Large files are read backwards in chunks and only the lines needed are
+/// parsed, so the cost follows `max_messages` rather than the file size.
+fn tail_chat_messages(path: &Path, max_messages: usize) -> Result<Vec<SessionMessage>, String> {
+    let mut file = File::open(path).map_err(|e| format!("Failed to open JSONL: {}", e))?;
+    let len = file
+        .metadata()
+        .map_err(|e| format!("Failed to read JSONL metadata: {}", e))?
+        .len();
+
+    if len <= TAIL_MIN_FILE_BYTES {
+        let mut messages = parse_chat_messages_from_jsonl(path)?.messages;
+        let excess = messages.len().saturating_sub(max_messages);
+        messages.drain(..excess);
+        return Ok(messages);
+    }
+
+    let mut newest_first = Vec::new();
+    // Start of a line whose beginning lies in an earlier, not yet read chunk.
+    let mut partial: Vec<u8> = Vec::new();
+    let mut pos = len;
+    // The first line seen is the file's last, which may still be being written.
+    let mut at_last_line = true;
+
+    while pos > 0 && newest_first.len() < max_messages {
+        let start = pos.saturating_sub(TAIL_CHUNK_BYTES);
+        let mut chunk = vec![0; (pos - start) as usize];
+        file.seek(SeekFrom::Start(start))
+            .and_then(|_| file.read_exact(&mut chunk))
+            .map_err(|e| format!("Failed to read JSONL: {}", e))?;
+        chunk.extend_from_slice(&partial);
+        pos = start;
+
+        // Everything after the first newline is made of whole lines; what
+        // comes before it continues into the previous chunk.
+        let complete_from = if start == 0 {
+            0
+        } else {
+            match chunk.iter().position(|&b| b == b'\n') {
+                Some(i) => i + 1,
+                None => {
+                    partial = chunk;
+                    continue;
+                }
+            }
+        };
+        partial = chunk[..complete_from].to_vec();
+
+        for line in chunk[complete_from..].split(|&b| b == b'\n').rev() {
+            let is_last_line = std::mem::replace(&mut at_last_line, false);
+            let Ok(line) = std::str::from_utf8(line) else {
+                continue;
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            let json: Value = match serde_json::from_str(line) {
+                Ok(v) => v,
+                // Only reachable when the file doesn't end with a newline.
+                Err(_) if is_last_line => continue,
+                Err(e) => {
+                    warn!(
+                        "[Chat history] Skipping malformed JSON near the end of {}: {}",
+                        path.display(),
+                        e
+                    );
+                    continue;
+                }
+            };
+            newest_first.extend(parse_chat_line(&json).into_iter().rev());
+            if newest_first.len() >= max_messages {
+                break;
+            }
         }
     }
 
-    Ok(messages)
+    newest_first.truncate(max_messages);
+    newest_first.reverse();
+    Ok(newest_first)
+}
+
+/// Session files in `dir` with their modification times, oldest first.
+fn list_jsonl_files(dir: &Path) -> Vec<(PathBuf, SystemTime)> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+
+    let mut files: Vec<_> = entries
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.extension().is_some_and(|ext| ext == "jsonl"))
+        .map(|p| {
+            let modified = p
+                .metadata()
+                .and_then(|m| m.modified())
+                .unwrap_or(SystemTime::UNIX_EPOCH);
+            (p, modified)
+        })
+        .collect();
+    files.sort_by_key(|(_, modified)| *modified);
+    files
+}
+
+/// Parsed session files, kept in managed state and reused while a file's
+/// modification time and size are unchanged.
+#[derive(Default)]
+struct ChatHistoryCache(Mutex<HashMap<PathBuf, CachedSession>>);
+
+struct CachedSession {
+    modified: SystemTime,
+    len: u64,
+    parsed: Arc<ParsedMessages<SessionMessage>>,
+}
+
+impl ChatHistoryCache {
+    /// Returns the parsed messages of `path`, reparsing only if the file changed
+    /// since it was cached.
+    fn messages(&self, path: &Path) -> Result<Arc<ParsedMessages<SessionMessage>>, String> {
+        let metadata =
+            fs::metadata(path).map_err(|e| format!("Failed to read JSONL metadata: {}", e))?;
+        let modified = metadata
+            .modified()
+            .map_err(|e| format!("Failed to read JSONL mtime: {}", e))?;
+        let len = metadata.len();
+
+        if let Some(cached) = self.lock().get(path) {
+            if cached.modified == modified && cached.len == len {
+                return Ok(Arc::clone(&cached.parsed));
+            }
+        }
+
+        // Parse without holding the lock; a concurrent parse of the same file
+        // just overwrites an identical entry.
+        let parsed = Arc::new(parse_chat_messages_from_jsonl(path)?);
+        self.lock().insert(
+            path.to_path_buf(),
+            CachedSession {
+                modified,
+                len,
+                parsed: Arc::clone(&parsed),
+            },
+        );
+        Ok(parsed)
+    }
+
+    /// Drops entries for session files in `dir` that no longer exist.
+    fn prune(&self, dir: &Path) {
+        self.lock()
+            .retain(|path, _| path.parent() != Some(dir) || path.exists());
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<PathBuf, CachedSession>> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// A chat message and the session it was first recorded in.
+#[derive(Serialize)]
+struct TaggedChatMessage {
+    #[serde(flatten)]
+    message: ChatMessage,
+    session_id: String,
+}
+
+/// Reads every session file in the Claude project directory and merges the
+/// messages into one chronological list. Messages are ordered by their own
+/// timestamps, falling back to the file's modification time and line order;
+/// lines copied between sessions are only counted once.
+fn read_all_chat_messages(
+    project_dir: &Path,
+    cache: &ChatHistoryCache,
+) -> ParsedMessages<TaggedChatMessage> {
+    let mut seen = HashSet::new();
+    let mut merged = Vec::new();
+    let mut skipped_lines = 0;
+    cache.prune(project_dir);
+
+    for (rank, (path, modified)) in list_jsonl_files(project_dir).into_iter().enumerate() {
+        let parsed = match cache.messages(&path) {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                warn!("[Chat history] Skipping {}: {}", path.display(), e);
+                continue;
+            }
+        };
+        skipped_lines += parsed.skipped_lines;
+        let fallback: DateTime<Local> = modified.into();
+        let session_id = path
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        for (seq, msg) in parsed.messages.iter().enumerate() {
+            if !seen.insert(msg.dedup_key()) {
+                continue;
+            }
+            merged.push((
+                msg.timestamp.unwrap_or(fallback),
+                rank,
+                seq,
+                TaggedChatMessage {
+                    message: msg.message.clone(),
+                    session_id: session_id.clone(),
+                },
+            ));
+        }
+    }
+
+    merged.sort_by_key(|(time, rank, seq, _)| (*time, *rank, *seq));
+    ParsedMessages {
+        messages: merged
+            .into_iter()
+            .map(|(_, _, _, message)| message)
+            .collect(),
+        skipped_lines,
+    }
+}
+
+/// Every message in the Claude session files for a language, merged.
+fn read_session_messages(
+    lang_dir: &Path,
+    cache: &ChatHistoryCache,
+) -> Result<ParsedMessages<ChatMessage>, String> {
+    let project_dir = get_claude_project_dir(lang_dir)?;
+    if !project_dir.exists() {
+        return Ok(ParsedMessages::default());
+    }
+    let parsed = read_all_chat_messages(&project_dir, cache);
+    Ok(ParsedMessages {
+        messages: parsed.messages.into_iter().map(|m| m.message).collect(),
+        skipped_lines: parsed.skipped_lines,
+    })
+}
+
+/// Opens a language's chat log, importing its session-file history the first time.
+fn open_chat_store(lang_dir: &Path, cache: &ChatHistoryCache) -> Result<ChatStore, String> {
+    let mut store = ChatStore::open(lang_dir)?;
+    store.backfill(|| {
+        let parsed = read_session_messages(lang_dir, cache)?;
+        // Leave the import for a later call rather than recording an empty history.
+        if parsed.messages.is_empty() && parsed.skipped_lines > 0 {
+            return Err(format!(
+                "None of the {} session lines could be parsed",
+                parsed.skipped_lines
+            ));
+        }
+        Ok(parsed.messages)
+    })?;
+    Ok(store)
+}
+
+/// A language's chat history, oldest first. Comes from the chat log, or from
+/// the session files if the log can't be opened.
+fn load_chat_messages(
+    lang_dir: &Path,
+    cache: &ChatHistoryCache,
+) -> Result<ParsedMessages<ChatMessage>, String> {
+    match open_chat_store(lang_dir, cache).and_then(|store| store.messages()) {
+        Ok(messages) => Ok(ParsedMessages {
+            messages,
+            skipped_lines: 0,
+        }),
+        Err(e) => {
+            warn!("[Chat store] {}; reading session files instead", e);
+            read_session_messages(lang_dir, cache)
+        }
+    }
+}
+
+/// True until the learner has sent a first message, judged from the chat log
+/// (or the session files if the log can't be opened).
+fn is_first_session_at(lang_dir: &Path, cache: &ChatHistoryCache) -> Result<bool, String> {
+    match open_chat_store(lang_dir, cache) {
+        Ok(store) => store.is_empty(),
+        Err(e) => {
+            warn!("[Chat store] {}; reading session files instead", e);
+            Ok(read_session_messages(lang_dir, cache)?.messages.is_empty())
+        }
+    }
+}
+
+/// Records one learner message and the tutor's reply in the chat log.
+/// Failures are only logged: the reply has already been produced.
+fn record_exchange(lang_dir: &Path, message: &str, sent_at: String, reply: &str) {
+    let messages = [
+        ChatMessage {
+            role: "user".to_string(),
+            content: message.to_string(),
+            timestamp: Some(sent_at),
+            id: None,
+        },
+        ChatMessage {
+            role: "assistant".to_string(),
+            content: reply.to_string(),
+            timestamp: Some(Local::now().to_rfc3339()),
+            id: None,
+        },
+    ];
+    if let Err(e) = ChatStore::open(lang_dir).and_then(|mut store| store.append(&messages)) {
+        error!("[Chat store] Failed to record exchange: {}", e);
+    }
+}
+
+/// Records a regenerated reply in place of the previous reply to `message`,
+/// or as a new exchange if the log doesn't end with one for it.
+fn record_regenerated_reply(lang_dir: &Path, message: &str, reply: &str) {
+    let reply_message = ChatMessage {
+        role: "assistant".to_string(),
+        content: reply.to_string(),
+        timestamp: Some(Local::now().to_rfc3339()),
+        id: None,
+    };
+    match ChatStore::open(lang_dir)
+        .and_then(|mut store| store.replace_last_reply(message, &reply_message))
+    {
+        Ok(true) => {}
+        Ok(false) => record_exchange(lang_dir, message, Local::now().to_rfc3339(), reply),
+        Err(e) => error!("[Chat store] Failed to record regenerated reply: {}", e),
+    }
+}
+
+/// Truncates `content` to at most `max_chars` characters, appending an ellipsis
+/// when anything was cut. Slices on char boundaries so multi-byte scripts are safe.
+fn truncate_preview(content: &str, max_chars: usize) -> String {
+    match content.char_indices().nth(max_chars) {
+        Some((byte_idx, _)) => format!("{}…", &content[..byte_idx]),
+        None => content.to_string(),
+    }
+}
+
+/// Returns the timestamp of the most recent user or assistant message in a
+/// session file, or None if its last message has no timestamp.
+fn last_message_timestamp(path: &Path) -> Option<DateTime<Local>> {
+    tail_chat_messages(path, 1).ok()?.pop()?.timestamp
+}
+
+/// Collects the timestamps of every learner message across all session files
+/// in the Claude project directory, oldest first.
+fn collect_user_message_timestamps(project_dir: &Path) -> Vec<DateTime<Local>> {
+    let Ok(entries) = fs::read_dir(project_dir) else {
+        return Vec::new();
+    };
+
+    let mut timestamps = Vec::new();
+    for path in entries.filter_map(|e| e.ok()).map(|e| e.path()) {
+        if !path.extension().is_some_and(|ext| ext == "jsonl") {
+            continue;
+        }
+        let Ok(file) = File::open(&path) else {
+            continue;
+        };
+        for line in BufReader::new(file).lines().map_while(Result::ok) {
+            let Ok(json) = serde_json::from_str::<Value>(&line) else {
+                continue;
+            };
+            if extract_user_message(&json).is_none() {
+                continue;
+            }
+            if let Some(timestamp) = message_timestamp(&json) {
+                timestamps.push(timestamp);
+            }
+        }
+    }
+    timestamps.sort();
+    timestamps
+}
+
+/// Collects the local dates on which the learner sent a message.
+fn collect_activity_dates(project_dir: &Path) -> BTreeSet<NaiveDate> {
+    collect_user_message_timestamps(project_dir)
+        .into_iter()
+        .map(|ts| ts.date_naive())
+        .collect()
 }
 
 // ============================================================================
 // JSON message extraction helpers
 // ============================================================================
 
+fn parse_local_timestamp(ts: &str) -> Option<DateTime<Local>> {
+    DateTime::parse_from_rfc3339(ts)
+        .ok()
+        .map(|dt| dt.with_timezone(&Local))
+}
+
+fn message_timestamp(json: &Value) -> Option<DateTime<Local>> {
+    parse_local_timestamp(json.get("timestamp")?.as_str()?)
+}
+
 fn get_message_content<'a>(json: &'a Value, role: &str) -> Option<&'a Value> {
     if json.get("type")?.as_str()? != role {
         return None;
@@ -225,30 +923,37 @@ fn extract_user_message(json: &Value) -> Option<String> {
         return Some(s.to_string());
     }
 
-    // If content is an array, skip tool results
-    if let Some(arr) = content.as_array() {
-        if arr.iter().any(|item| {
-            item.get("type").and_then(|v| v.as_str()) == Some("tool_result")
-        }) {
-            return None;
-        }
-    }
+    // Arrays mix learner text with tool results; keep only the text blocks
+    let texts: Vec<&str> = content
+        .as_array()?
+        .iter()
+        .filter(|item| item.get("type").and_then(|v| v.as_str()) == Some("text"))
+        .filter_map(|item| item.get("text")?.as_str())
+        .filter(|text| !text.trim().is_empty())
+        .collect();
 
-    None
+    if texts.is_empty() {
+        None
+    } else {
+        Some(texts.join("\n"))
+    }
 }
 
 fn extract_assistant_message(json: &Value) -> Option<String> {
     let content = get_message_content(json, "assistant")?.as_array()?;
 
-    for item in content {
-        if item.get("type")?.as_str()? == "text" {
-            if let Some(text) = item.get("text")?.as_str() {
-                return Some(text.to_string());
-            }
-        }
-    }
+    // Text can be split into several blocks around tool use; keep them all in order
+    let texts: Vec<&str> = content
+        .iter()
+        .filter(|item| item.get("type").and_then(|v| v.as_str()) == Some("text"))
+        .filter_map(|item| item.get("text")?.as_str())
+        .collect();
 
-    None
+    if texts.is_empty() {
+        None
+    } else {
+        Some(texts.join("\n"))
+    }
 }
 
 // ============================================================================
@@ -261,364 +966,7779 @@ struct LanguageConfig {
     native_script: String,
     romanization: String,
     started: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    greeting: Option<String>,
+    /// Hidden from language lists unless they ask for archived languages.
+    #[serde(default)]
+    archived: bool,
 }
 
 #[derive(Serialize, Deserialize, Clone)]
 struct ChatMessage {
     role: String,  // "user" or "assistant"
     content: String,
+    /// ISO-8601 time the message was recorded, when the session file has one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    timestamp: Option<String>,
+    /// Stable id for telling messages apart across reloads: the chat log's row
+    /// id, or Claude's `uuid` for messages read from session files. Unset on
+    /// messages not yet recorded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    id: Option<String>,
+}
+
+#[derive(Serialize, Default)]
+struct ChatHistoryPage {
+    messages: Vec<ChatMessage>,
+    /// Messages in the whole history, for sizing the pager.
+    total: usize,
+    /// Session-file lines that couldn't be parsed; non-zero with no messages
+    /// means the history exists but is unreadable.
+    skipped_lines: usize,
 }
 
 // ============================================================================
-// Platform helpers
+// Vocabulary helpers
 // ============================================================================
 
-/// Configures Command to hide the console window on Windows
-#[cfg(windows)]
-fn hide_console_window(cmd: &mut Command) {
-    use std::os::windows::process::CommandExt;
-    // Windows API flag to prevent spawning a visible console window
-    const CREATE_NO_WINDOW: u32 = 0x08000000;
-    cmd.creation_flags(CREATE_NO_WINDOW);
+/// Date format used for all dates stored in the tracking files.
+const DATE_FORMAT: &str = "%Y-%m-%d";
+
+fn parse_date(s: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(s, DATE_FORMAT)
+        .map_err(|_| format!("Invalid date '{}'. Expected YYYY-MM-DD", s))
 }
 
-#[cfg(not(windows))]
-fn hide_console_window(_cmd: &mut Command) {
-    // No-op on non-Windows platforms
+fn read_vocabulary(lang_dir: &Path) -> Result<Value, String> {
+    let content = fs::read_to_string(lang_dir.join("vocabulary.json"))
+        .map_err(|e| format!("Failed to read vocabulary: {}", e))?;
+    serde_json::from_str(&content).map_err(|e| format!("Failed to parse vocabulary: {}", e))
 }
 
-// ============================================================================
-// Path helpers
-// ============================================================================
+/// Writes `bytes` to a temp file next to `path`, flushes it to disk and renames
+/// it into place, so a crash or kill mid-write leaves either the old file or
+/// the new one, never a truncated mix. The rename is atomic because both are
+/// on the same filesystem.
+fn write_atomic(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+    tmp_name.push(".tmp");
+    let tmp_path = path.with_file_name(tmp_name);
 
-fn get_exe_dir() -> Result<PathBuf, String> {
-    env::current_exe()
-        .map_err(|e| format!("Failed to get exe path: {}", e))?
-        .parent()
-        .map(|p| p.to_path_buf())
-        .ok_or_else(|| "Failed to get exe directory".to_string())
+    let written = File::create(&tmp_path).and_then(|mut file| {
+        file.write_all(bytes)?;
+        file.sync_all()
+    });
+    let result = written.and_then(|_| fs::rename(&tmp_path, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+    result
 }
 
-fn get_data_dir() -> Result<PathBuf, String> {
-    Ok(get_exe_dir()?.join("data"))
+/// Serializes `value` and writes it with `write_atomic`.
+fn write_json_atomic<T: Serialize>(path: &Path, value: &T) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(value)
+        .map_err(|e| format!("Failed to serialize JSON: {}", e))?;
+    write_atomic(path, json.as_bytes()).map_err(|e| {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        format!("Failed to write {}: {}", name, e)
+    })
 }
 
-fn validate_language_name(language: &str) -> Result<(), String> {
-    if language.is_empty() {
-        return Err("Language name cannot be empty".to_string());
-    }
-    if language.contains("..") || language.contains('/') || language.contains('\\') {
-        return Err("Language name contains invalid characters".to_string());
-    }
-    if !language.chars().all(|c| c.is_alphanumeric() || c == ' ' || c == '-') {
-        return Err("Language name can only contain letters, numbers, spaces, and hyphens".to_string());
+/// Optional read-only deck distributed alongside the learner's own vocabulary.
+const SHARED_VOCABULARY_FILE: &str = "shared-vocabulary.json";
+
+fn read_shared_vocabulary(lang_dir: &Path) -> Result<Option<Value>, String> {
+    let path = lang_dir.join(SHARED_VOCABULARY_FILE);
+    if !path.exists() {
+        return Ok(None);
     }
-    Ok(())
+    let content = fs::read_to_string(&path)
+        .map_err(|e| format!("Failed to read shared vocabulary: {}", e))?;
+    serde_json::from_str(&content)
+        .map(Some)
+        .map_err(|e| format!("Failed to parse shared vocabulary: {}", e))
 }
 
-fn get_language_dir(language: &str) -> Result<PathBuf, String> {
-    validate_language_name(language)?;
-    Ok(get_data_dir()?.join(language.to_lowercase()))
-}
+/// Merges the shared deck with the learner's vocabulary, keyed by `word`.
+///
+/// Precedence: shared entries supply the base fields; any field present in the
+/// learner's entry for the same word (progress, notes, corrections) wins. Shared
+/// words come first in deck order, followed by words only the learner has.
+/// Entries originating from the deck are marked with `"shared": true`.
+fn merge_vocabulary(shared: &[Value], user: &[Value]) -> Vec<Value> {
+    let word_of = |entry: &Value| {
+        entry
+            .get("word")
+            .and_then(|w| w.as_str())
+            .map(str::to_string)
+    };
+    let user_by_word: HashMap<String, &Value> = user
+        .iter()
+        .filter_map(|entry| Some((word_of(entry)?, entry)))
+        .collect();
 
-fn capitalize_first(s: &str) -> String {
-    let mut chars = s.chars();
-    match chars.next() {
-        Some(c) => c.to_uppercase().chain(chars).collect(),
-        None => String::new(),
+    let mut merged = Vec::with_capacity(shared.len() + user.len());
+    let mut shared_words = BTreeSet::new();
+
+    for entry in shared {
+        let Some(word) = word_of(entry) else {
+            continue;
+        };
+        if !shared_words.insert(word.clone()) {
+            continue;
+        }
+        let mut combined = entry.clone();
+        if let (Some(base), Some(overlay)) = (
+            combined.as_object_mut(),
+            user_by_word.get(&word).and_then(|u| u.as_object()),
+        ) {
+            for (key, value) in overlay {
+                base.insert(key.clone(), value.clone());
+            }
+        }
+        combined["shared"] = json!(true);
+        merged.push(combined);
     }
+
+    merged.extend(
+        user.iter()
+            .filter(|entry| word_of(entry).is_none_or(|w| !shared_words.contains(&w)))
+            .cloned(),
+    );
+    merged
 }
 
-/// Derives the Claude CLI project path from a directory.
-/// E.g., C:\Users\wongp\Desktop\lang\data\korean -> ~/.claude/projects/C--Users-wongp-Desktop-lang-data-korean
-fn get_claude_project_dir(dir: &Path) -> Result<PathBuf, String> {
-    let canonical = dir
-        .canonicalize()
-        .map_err(|e| format!("Failed to canonicalize path: {}", e))?;
+/// Starts a progress entry in the learner's vocabulary for a word that so far
+/// only exists in the shared deck. The deck itself is never modified.
+fn track_shared_word(lang_dir: &Path, vocab: &mut Value, word: &str) -> Result<(), String> {
+    if find_word_mut(vocab, word).is_some() {
+        return Ok(());
+    }
+    let Some(shared) = read_shared_vocabulary(lang_dir)? else {
+        return Ok(());
+    };
+    let in_deck = vocabulary_words(&shared)
+        .iter()
+        .any(|w| w.get("word").and_then(|v| v.as_str()) == Some(word));
+    if !in_deck {
+        return Ok(());
+    }
 
-    let mut path_str = canonical.to_string_lossy().to_string();
+    if let Some(words) = vocab.get_mut("words").and_then(|w| w.as_array_mut()) {
+        words.push(json!({ "word": word }));
+    }
+    Ok(())
+}
 
-    // Remove Windows extended path prefix \\?\ BEFORE any replacements
-    if path_str.starts_with(r"\\?\") {
-        path_str = path_str[4..].to_string();
+/// Reads the learner's vocabulary merged with the shared deck, if there is one.
+/// Use this for read-only views; writes always go to vocabulary.json.
+fn read_merged_vocabulary(lang_dir: &Path) -> Result<Value, String> {
+    let mut vocab = read_vocabulary(lang_dir)?;
+    if let Some(shared) = read_shared_vocabulary(lang_dir)? {
+        let merged = merge_vocabulary(vocabulary_words(&shared), vocabulary_words(&vocab));
+        vocab["words"] = Value::Array(merged);
     }
+    Ok(vocab)
+}
 
-    // Convert path to Claude's project folder format:
-    // C:\Users\foo\bar -> C--Users-foo-bar
-    let encoded = path_str
-        .replace(":\\", "--")  // C:\ -> C--
-        .replace("\\", "-")    // remaining backslashes
-        .replace("/", "-");    // forward slashes (just in case)
+fn vocabulary_words(vocab: &Value) -> &[Value] {
+    vocab
+        .get("words")
+        .and_then(|w| w.as_array())
+        .map(|w| w.as_slice())
+        .unwrap_or(&[])
+}
 
-    let home = dirs::home_dir().ok_or("Failed to get home directory")?;
-    Ok(home.join(".claude").join("projects").join(encoded))
+/// Reads the `added` date of a word entry. Entries recorded before the
+/// tracker started stamping dates have no `added` field and yield None.
+fn word_added_date(word: &Value) -> Option<NaiveDate> {
+    parse_date(word.get("added")?.as_str()?).ok()
 }
 
-// ============================================================================
-// Bootstrap helpers
-// ============================================================================
+/// A word is due when its `next_review` date is today or earlier.
+/// Words without a readable `next_review` have never been scheduled and count as due.
+fn is_word_due(word: &Value, today: NaiveDate) -> bool {
+    word.get("next_review")
+        .and_then(|v| v.as_str())
+        .and_then(|s| parse_date(s).ok())
+        .is_none_or(|d| d <= today)
+}
 
-fn write_language_file(dir: &Path, filename: &str, content: &str) -> Result<(), String> {
-    fs::write(dir.join(filename), content)
-        .map_err(|e| format!("Failed to write {}: {}", filename, e))
+fn count_due_words(vocab: &Value, today: NaiveDate) -> usize {
+    vocabulary_words(vocab)
+        .iter()
+        .filter(|w| is_word_due(w, today))
+        .count()
 }
 
-fn generate_language_files(lang_dir: &Path, language: &str) -> Result<(), String> {
-    let info = get_language_info(language);
+/// Days a word may sit unreinforced before it's reported as never reviewed.
+/// Overridable via `preferences.never_reviewed_after_days`.
+const DEFAULT_NEVER_REVIEWED_DAYS: u64 = 7;
 
-    let claude_md = TUTOR_TEMPLATE
-        .replace("{{LANGUAGE_NAME}}", language)
-        .replace("{{LANGUAGE_NATIVE}}", info.native_script)
-        .replace("{{ROMANIZATION}}", info.romanization)
-        .replace("{{LANGUAGE_SPECIFIC_NOTES}}", info.notes);
-    write_language_file(lang_dir, "CLAUDE.md", &claude_md)?;
+/// A word has never been reviewed if it was seen at most once
+/// (missing `repetitions` counts as once).
+fn is_never_reviewed(word: &Value) -> bool {
+    word.get("repetitions")
+        .and_then(|v| v.as_u64())
+        .unwrap_or(1)
+        <= 1
+}
 
-    let vocab = VOCABULARY_TEMPLATE.replace("{{LANGUAGE_NAME}}", language);
-    write_language_file(lang_dir, "vocabulary.json", &vocab)?;
+/// Archive of long-learned words, kept out of vocabulary.json so the tracker
+/// doesn't have to read them on every message.
+const MATURE_VOCABULARY_FILE: &str = "mature-vocabulary.json";
 
-    let grammar = GRAMMAR_TEMPLATE.replace("{{LANGUAGE_NAME}}", language);
-    write_language_file(lang_dir, "grammar.json", &grammar)?;
+/// Interval (in days) from which `archive_mature_words` moves a word to the
+/// archive. Matches the "permanently learned" threshold.
+const ARCHIVE_INTERVAL_DAYS: u64 = 180;
 
-    let overrides = USER_OVERRIDES_TEMPLATE.replace("{{LANGUAGE_NAME}}", language);
-    write_language_file(lang_dir, "user-overrides.json", &overrides)?;
+/// vocabulary.json size above which `check_vocabulary_size` warns.
+const VOCABULARY_WARNING_BYTES: u64 = 256 * 1024;
+const VOCABULARY_WARNING_ENTRIES: usize = 1000;
 
-    let config = LanguageConfig {
-        language: language.to_string(),
-        native_script: info.native_script.to_string(),
-        romanization: info.romanization.to_string(),
-        started: Local::now().format("%Y-%m-%d").to_string(),
+fn is_word_archivable(word: &Value) -> bool {
+    word.get("interval")
+        .and_then(|v| v.as_u64())
+        .is_some_and(|i| i >= ARCHIVE_INTERVAL_DAYS)
+}
+
+/// Reads the mature-word archive, or an empty word list if there isn't one yet.
+fn read_mature_vocabulary(lang_dir: &Path) -> Result<Value, String> {
+    let path = lang_dir.join(MATURE_VOCABULARY_FILE);
+    if !path.exists() {
+        return Ok(json!({ "words": [] }));
+    }
+    let content = fs::read_to_string(&path)
+        .map_err(|e| format!("Failed to read mature vocabulary: {}", e))?;
+    serde_json::from_str(&content).map_err(|e| format!("Failed to parse mature vocabulary: {}", e))
+}
+
+fn find_word_mut<'a>(vocab: &'a mut Value, word: &str) -> Option<&'a mut Value> {
+    vocab
+        .get_mut("words")?
+        .as_array_mut()?
+        .iter_mut()
+        .find(|w| w.get("word").and_then(|v| v.as_str()) == Some(word))
+}
+
+// ============================================================================
+// User-authored fields
+// ============================================================================
+
+/// Vocabulary fields written by the learner rather than the tracker.
+const USER_WORD_FIELDS: &[&str] = &["notes", "tags", "priority", "suspended"];
+
+type UserFieldSnapshot = HashMap<String, serde_json::Map<String, Value>>;
+
+/// Captures the user-authored fields of every word, keyed by word.
+fn snapshot_user_fields(vocab: &Value) -> UserFieldSnapshot {
+    vocabulary_words(vocab)
+        .iter()
+        .filter_map(|entry| {
+            let word = entry.get("word")?.as_str()?;
+            let fields: serde_json::Map<String, Value> = USER_WORD_FIELDS
+                .iter()
+                .filter_map(|&f| Some((f.to_string(), entry.get(f)?.clone())))
+                .collect();
+            (!fields.is_empty()).then(|| (word.to_string(), fields))
+        })
+        .collect()
+}
+
+/// Puts back any snapshotted user field that is missing from its word's entry.
+/// Returns true if anything was restored.
+fn restore_user_fields(vocab: &mut Value, snapshot: &UserFieldSnapshot) -> bool {
+    let Some(words) = vocab.get_mut("words").and_then(|w| w.as_array_mut()) else {
+        return false;
     };
-    let config_json = serde_json::to_string_pretty(&config)
-        .map_err(|e| format!("Failed to serialize config: {}", e))?;
-    write_language_file(lang_dir, "config.json", &config_json)
+
+    let mut restored = false;
+    for entry in words {
+        let Some(fields) = entry
+            .get("word")
+            .and_then(|w| w.as_str())
+            .and_then(|w| snapshot.get(w))
+        else {
+            continue;
+        };
+        let Some(obj) = entry.as_object_mut() else {
+            continue;
+        };
+        for (field, value) in fields {
+            if !obj.contains_key(field) {
+                obj.insert(field.clone(), value.clone());
+                restored = true;
+            }
+        }
+    }
+    restored
+}
+
+/// Re-applies user fields the tracker dropped while rewriting vocabulary.json.
+fn reconcile_user_fields(lang_dir: &Path, snapshot: &UserFieldSnapshot) -> Result<(), String> {
+    let lock = language_lock(lang_dir);
+    let _guard = lock.lock().unwrap_or_else(|e| e.into_inner());
+
+    let mut vocab = read_vocabulary(lang_dir)?;
+    if restore_user_fields(&mut vocab, snapshot) {
+        write_json_atomic(&lang_dir.join("vocabulary.json"), &vocab)?;
+    }
+    Ok(())
 }
 
 // ============================================================================
-// Commands
+// User overrides
 // ============================================================================
 
-#[tauri::command]
-fn bootstrap_language(language: String) -> Result<String, String> {
-    let lang_dir = get_language_dir(&language)?;
+/// Modes accepted in `user-overrides.json`, matching the modes described in the
+/// tutor instructions.
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, Default, Debug)]
+#[serde(rename_all = "lowercase")]
+enum LearningMode {
+    #[default]
+    Learning,
+    Practicing,
+    Fluent,
+    Review,
+}
 
-    if lang_dir.exists() {
-        return Err(format!("Language '{}' already exists", language));
+impl LearningMode {
+    const ALL: [LearningMode; 4] = [
+        LearningMode::Learning,
+        LearningMode::Practicing,
+        LearningMode::Fluent,
+        LearningMode::Review,
+    ];
+
+    fn as_str(self) -> &'static str {
+        match self {
+            LearningMode::Learning => "learning",
+            LearningMode::Practicing => "practicing",
+            LearningMode::Fluent => "fluent",
+            LearningMode::Review => "review",
+        }
     }
 
-    fs::create_dir_all(&lang_dir)
-        .map_err(|e| format!("Failed to create language directory: {}", e))?;
+    /// Extra system prompt handed to the responder so the current mode is
+    /// applied even if the tutor skips re-reading user-overrides.json.
+    fn instructions(self) -> &'static str {
+        match self {
+            LearningMode::Learning => "Current mode: learning. Use mostly known words plus about two new ones, scaffolding from the learner's own sentences. Correct every mistake by modelling the right form.",
+            LearningMode::Practicing => "Current mode: practicing. Converse naturally but stay mindful of the learner's level.",
+            LearningMode::Fluent => "Current mode: fluent. Converse naturally with no vocabulary restrictions and no English.",
+            LearningMode::Review => "Current mode: review. Drill words due for review and weak grammar rules. Introduce no new vocabulary.",
+        }
+    }
+}
 
-    generate_language_files(&lang_dir, &language)?;
+/// Case-insensitive and ignores surrounding whitespace.
+impl std::str::FromStr for LearningMode {
+    type Err = AppError;
 
-    Ok(format!("Successfully bootstrapped {}", language))
+    fn from_str(mode: &str) -> Result<Self, AppError> {
+        let mode = mode.trim().to_lowercase();
+        LearningMode::ALL
+            .into_iter()
+            .find(|m| m.as_str() == mode)
+            .ok_or_else(|| {
+                AppError::InvalidInput(format!(
+                    "Invalid mode '{}'. Must be one of: {}",
+                    mode,
+                    LearningMode::ALL.map(LearningMode::as_str).join(", ")
+                ))
+            })
+    }
 }
 
-const TRACKER_PROMPT: &str = r#"[TRACKER TASK - UPDATE FILES ONLY, NO RESPONSE]
+impl<'de> Deserialize<'de> for LearningMode {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
 
-Process this learner message and update vocabulary.json and grammar.json.
+/// Reads the stored mode, treating a hand-edited unknown value as the default.
+fn lenient_learning_mode<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<LearningMode, D::Error> {
+    let value = Value::deserialize(deserializer)?;
+    Ok(value
+        .as_str()
+        .and_then(|mode| mode.parse().ok())
+        .unwrap_or_default())
+}
 
-Learner said: {{MESSAGE}}
+/// Models accepted for the `model` field, as aliases the Claude CLI resolves.
+const CLAUDE_MODELS: &[&str] = &["sonnet", "opus", "haiku"];
 
-Instructions:
-1. Read vocabulary.json and grammar.json
-2. For each word/particle the learner used:
-   - If NEW: add entry with ease=2.5, interval=1, repetitions=1
-   - If EXISTS: update SM-2 data (see below)
-3. For grammar patterns used:
-   - If NEW: add entry with stars=1, correct_streak=1
-   - If EXISTS: increment correct_streak, upgrade stars if appropriate
-4. Write updated files
-5. Output NOTHING - your only job is updating files
+/// Allowed range for `preferences.new_vocab_per_exchange`.
+const MAX_NEW_VOCAB_PER_EXCHANGE: u32 = 20;
 
-SM-2 Algorithm (when learner uses a word correctly):
-- repetitions += 1
-- if repetitions == 1: interval = 1
-- if repetitions == 2: interval = 6
-- if repetitions >= 3: interval = round(interval × ease)
-- next_review = today + interval days
+/// Contents of `user-overrides.json`. Fields this struct doesn't know about are
+/// kept in `extra` so older or hand-edited files round-trip unchanged.
+#[derive(Serialize, Deserialize, Clone)]
+struct UserOverrides {
+    #[serde(default)]
+    language: String,
+    #[serde(default, deserialize_with = "lenient_learning_mode")]
+    mode: LearningMode,
+    #[serde(default)]
+    preferences: UserPreferences,
+    #[serde(default)]
+    notes: String,
+    /// Passed to the agents as `--model`; the CLI default is used when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    model: Option<String>,
+    /// Extra Claude CLI arguments; replaces the app-wide list when set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    agent_args: Option<Vec<String>>,
+    /// Whether to pass `--dangerously-skip-permissions`; replaces the app-wide
+    /// setting when set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    skip_permissions: Option<bool>,
+    #[serde(flatten)]
+    extra: serde_json::Map<String, Value>,
+}
 
-IMPORTANT: Check for duplicates by word/rule field. Update existing entries, don't create duplicates."#;
+#[derive(Serialize, Deserialize, Clone)]
+struct UserPreferences {
+    #[serde(default = "default_new_vocab_per_exchange")]
+    new_vocab_per_exchange: u32,
+    #[serde(default = "default_show_romanization")]
+    show_romanization: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    session_stale_after_days: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    never_reviewed_after_days: Option<u64>,
+    #[serde(flatten)]
+    extra: serde_json::Map<String, Value>,
+}
 
-fn spawn_tracker_agent(lang_dir: PathBuf, message: String) {
-    tokio::spawn(async move {
-        let tracker_dir = lang_dir.join(".tracker");
-        if let Err(e) = fs::create_dir_all(&tracker_dir) {
-            eprintln!("[Tracker] Failed to create tracker directory: {}", e);
-            return;
+impl Default for UserPreferences {
+    fn default() -> Self {
+        UserPreferences {
+            new_vocab_per_exchange: default_new_vocab_per_exchange(),
+            show_romanization: default_show_romanization(),
+            session_stale_after_days: None,
+            never_reviewed_after_days: None,
+            extra: serde_json::Map::new(),
         }
+    }
+}
+
+fn default_new_vocab_per_exchange() -> u32 {
+    2
+}
 
-        let prompt = TRACKER_PROMPT.replace("{{MESSAGE}}", &message);
-        let task = tokio::task::spawn_blocking(move || {
-            let mut cmd = Command::new("claude");
-            cmd.arg("--dangerously-skip-permissions")
-                .arg("-p")
-                .arg(&prompt)
-                .current_dir(&tracker_dir);
+fn default_show_romanization() -> bool {
+    true
+}
 
-            hide_console_window(&mut cmd);
-            cmd.output()
-        });
+/// Partial update for `set_user_preferences`; only the fields that are set change.
+#[derive(Deserialize)]
+struct UserPreferencesUpdate {
+    new_vocab_per_exchange: Option<u32>,
+    show_romanization: Option<bool>,
+    mode: Option<LearningMode>,
+    notes: Option<String>,
+}
+
+fn read_user_overrides(lang_dir: &Path) -> Result<UserOverrides, String> {
+    let content = fs::read_to_string(lang_dir.join("user-overrides.json"))
+        .map_err(|e| format!("Failed to read user overrides: {}", e))?;
+    serde_json::from_str(&content).map_err(|e| format!("Failed to parse user overrides: {}", e))
+}
+
+fn write_user_overrides(lang_dir: &Path, overrides: &UserOverrides) -> Result<(), String> {
+    write_json_atomic(&lang_dir.join("user-overrides.json"), overrides)
+}
+
+/// Reads the learner's preferences, falling back to defaults if the file is
+/// missing or unreadable. For callers that only need a preference value.
+fn read_user_preferences(lang_dir: &Path) -> UserPreferences {
+    read_user_overrides(lang_dir)
+        .map(|o| o.preferences)
+        .unwrap_or_default()
+}
+
+fn read_learning_mode(lang_dir: &Path) -> Result<LearningMode, String> {
+    Ok(read_user_overrides(lang_dir)?.mode)
+}
 
-        let timeout = Duration::from_secs(TRACKER_TIMEOUT_SECS);
-        match tokio::time::timeout(timeout, task).await {
-            Err(_) => eprintln!("[Tracker] Timed out after {}s", TRACKER_TIMEOUT_SECS),
-            Ok(Err(e)) => eprintln!("[Tracker] Task join error: {}", e),
-            Ok(Ok(Err(e))) => eprintln!("[Tracker] Command error: {}", e),
-            Ok(Ok(Ok(_))) => {}
+/// Normalizes `model` and checks it against `CLAUDE_MODELS`.
+fn validate_model(model: &str) -> Result<String, AppError> {
+    let model = model.trim().to_lowercase();
+    if !CLAUDE_MODELS.contains(&model.as_str()) {
+        return Err(AppError::InvalidInput(format!(
+            "Invalid model '{}'. Must be one of: {}",
+            model,
+            CLAUDE_MODELS.join(", ")
+        )));
+    }
+    Ok(model)
+}
+
+/// Adds `--model` for the language's selected model. Leaves the command alone
+/// when none is set, or when a hand-edited value isn't on the allow-list.
+fn apply_model(cmd: &mut Command, lang_dir: &Path) {
+    let Ok(overrides) = read_user_overrides(lang_dir) else {
+        return;
+    };
+    match overrides.model.as_deref().map(validate_model) {
+        Some(Ok(model)) => {
+            cmd.arg("--model").arg(model);
         }
-    });
+        Some(Err(e)) => warn!("[model] Ignoring model in user overrides: {}", e),
+        None => {}
+    }
 }
 
-async fn run_responder_agent(lang_dir: &Path, message: &str) -> Result<String, String> {
-    let dir = lang_dir.to_path_buf();
-    let msg = message.to_string();
+/// Flags the app sets itself; passing them again would replace the prompt or
+/// the session the agents run with.
+const RESERVED_AGENT_ARGS: &[&str] = &[
+    "-p",
+    "--print",
+    "-c",
+    "--continue",
+    "-r",
+    "--resume",
+    "--system-prompt",
+    "--append-system-prompt",
+    "--output-format",
+    "--input-format",
+    "--model",
+    "--dangerously-skip-permissions",
+];
 
-    let result = tokio::task::spawn_blocking(move || {
-        let mut cmd = Command::new("claude");
-        cmd.arg("--dangerously-skip-permissions")
-            .arg("--continue")
-            .arg("-p")
-            .arg(&msg)
-            .current_dir(&dir);
+/// Rejects empty args, flags in `RESERVED_AGENT_ARGS` (also as `--flag=value`)
+/// and args carrying prompt template markers.
+fn validate_agent_args(args: &[String]) -> Result<(), AppError> {
+    for arg in args {
+        if arg.trim().is_empty() {
+            return Err(AppError::InvalidInput(
+                "Agent arguments can't be empty".to_string(),
+            ));
+        }
+        let flag = arg.split('=').next().unwrap_or(arg);
+        if RESERVED_AGENT_ARGS.contains(&flag) {
+            return Err(AppError::InvalidInput(format!(
+                "'{}' is set by the app and can't be passed as an agent argument",
+                flag
+            )));
+        }
+        if arg.contains("{{") || arg.contains("}}") || arg.contains(USER_NOTES_SENTINEL) {
+            return Err(AppError::InvalidInput(format!(
+                "Agent argument '{}' contains a reserved marker",
+                arg
+            )));
+        }
+    }
+    Ok(())
+}
 
-        hide_console_window(&mut cmd);
-        cmd.output()
-    })
-    .await
-    .map_err(|e| format!("Task join error: {}", e))?
-    .map_err(|e| format!("Failed to run claude: {}", e))?;
+/// Adds `--dangerously-skip-permissions` (unless turned off) and the extra
+/// CLI arguments, preferring the language's overrides over app settings.
+/// Hand-edited args that fail validation are dropped with a warning.
+fn apply_agent_args(cmd: &mut Command, lang_dir: &Path) {
+    let settings = read_settings().unwrap_or_else(|e| {
+        warn!("[agent_args] Using default settings: {}", e);
+        AppSettings::default()
+    });
+    let overrides = read_user_overrides(lang_dir).ok();
+    let skip_permissions = overrides
+        .as_ref()
+        .and_then(|o| o.skip_permissions)
+        .or(settings.skip_permissions)
+        .unwrap_or(true);
+    let args = overrides
+        .and_then(|o| o.agent_args)
+        .unwrap_or(settings.agent_args);
 
-    if result.status.success() {
-        Ok(String::from_utf8_lossy(&result.stdout).trim().to_string())
-    } else {
-        Err(format!(
-            "Claude error: {}",
-            String::from_utf8_lossy(&result.stderr).trim()
-        ))
+    if skip_permissions {
+        cmd.arg("--dangerously-skip-permissions");
+    }
+    match validate_agent_args(&args) {
+        Ok(()) => {
+            cmd.args(&args);
+        }
+        Err(e) => warn!("[agent_args] Ignoring agent args: {}", e),
     }
 }
 
-/// Maximum message length in characters.
-/// Prevents excessively long inputs that could slow down or overwhelm Claude.
-const MAX_MESSAGE_LENGTH: usize = 10000;
+// ============================================================================
+// Session staleness
+// ============================================================================
 
-#[tauri::command]
-async fn send_message(message: String, language: String) -> Result<String, String> {
-    if message.trim().is_empty() {
-        return Err("Message cannot be empty".to_string());
+/// Days of inactivity after which the responder starts a fresh session instead
+/// of resuming the old one. Overridable via `preferences.session_stale_after_days`.
+const DEFAULT_SESSION_STALE_DAYS: u64 = 14;
+
+fn session_stale_after_days(lang_dir: &Path) -> u64 {
+    read_user_preferences(lang_dir)
+        .session_stale_after_days
+        .unwrap_or(DEFAULT_SESSION_STALE_DAYS)
+}
+
+/// True when the Claude project dir for `lang_dir` holds at least one session.
+/// A language whose directory moved has no session under its new path, and
+/// must not `--continue` whatever conversation happens to match.
+fn has_session(lang_dir: &Path) -> bool {
+    get_claude_project_dir(lang_dir)
+        .ok()
+        .and_then(|dir| find_latest_jsonl_file(&dir))
+        .is_some()
+}
+
+/// Records the id of the session the responder last replied in, so the next
+/// message resumes that session rather than whichever file changed last.
+const ACTIVE_SESSION_FILE: &str = ".session-id";
+
+/// Claude session ids are UUIDs; anything else could escape the project dir.
+fn is_valid_session_id(id: &str) -> bool {
+    !id.is_empty() && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+}
+
+/// The recorded session, if there is one and its session file still exists.
+fn active_session(lang_dir: &Path) -> Option<String> {
+    let id = fs::read_to_string(lang_dir.join(ACTIVE_SESSION_FILE)).ok()?;
+    let id = id.trim();
+    if !is_valid_session_id(id) {
+        return None;
     }
-    if message.len() > MAX_MESSAGE_LENGTH {
-        return Err(format!(
-            "Message too long ({} chars). Maximum is {} chars.",
-            message.len(),
-            MAX_MESSAGE_LENGTH
-        ));
+    let project_dir = get_claude_project_dir(lang_dir).ok()?;
+    project_dir
+        .join(format!("{}.jsonl", id))
+        .exists()
+        .then(|| id.to_string())
+}
+
+/// Records the session subsequent messages resume, or forgets it with None.
+fn set_active_session(lang_dir: &Path, id: Option<&str>) -> Result<(), String> {
+    let path = lang_dir.join(ACTIVE_SESSION_FILE);
+    match id {
+        Some(id) => write_language_file(lang_dir, ACTIVE_SESSION_FILE, id),
+        None if path.exists() => fs::remove_file(&path)
+            .map_err(|e| format!("Failed to clear {}: {}", ACTIVE_SESSION_FILE, e)),
+        None => Ok(()),
     }
+}
 
-    let lang_dir = get_language_dir(&language)?;
+/// Session file the next message would resume: the recorded session, or the
+/// most recently modified one.
+fn current_session_file(lang_dir: &Path) -> Option<PathBuf> {
+    let project_dir = get_claude_project_dir(lang_dir).ok()?;
+    match active_session(lang_dir) {
+        Some(id) => Some(project_dir.join(format!("{}.jsonl", id))),
+        None => find_latest_jsonl_file(&project_dir),
+    }
+}
 
-    if !lang_dir.exists() {
-        return Err(format!(
-            "Language '{}' not set up. Please bootstrap it first.",
-            language
-        ));
+/// Flag file set by `start_new_session`: the next message starts a new Claude
+/// session. Removed once that message has been answered.
+const NEW_CONVERSATION_FLAG: &str = ".new-conversation";
+
+/// Clears a pending `start_new_session` request after the fresh session started.
+fn clear_new_conversation_flag(lang_dir: &Path) {
+    let flag = lang_dir.join(NEW_CONVERSATION_FLAG);
+    if flag.exists() {
+        if let Err(e) = fs::remove_file(&flag) {
+            warn!("[send_message] Failed to clear {}: {}", flag.display(), e);
+        }
     }
+}
 
-    spawn_tracker_agent(lang_dir.clone(), message.clone());
-    run_responder_agent(&lang_dir, &message).await
+/// Whether the next message resumes the latest session rather than starting
+/// a new one: a session must exist and must not be stale, and no new
+/// conversation may have been requested.
+fn should_continue_session(lang_dir: &Path) -> bool {
+    if !has_session(lang_dir) || lang_dir.join(NEW_CONVERSATION_FLAG).exists() {
+        return false;
+    }
+    match is_session_stale_at(lang_dir) {
+        Ok(stale) => !stale,
+        Err(e) => {
+            warn!("[send_message] Failed to check session staleness: {}", e);
+            true
+        }
+    }
 }
 
-#[tauri::command]
-fn get_vocabulary(language: String) -> Result<String, String> {
-    let vocab_file = get_language_dir(&language)?.join("vocabulary.json");
-    fs::read_to_string(&vocab_file).map_err(|e| format!("Failed to read vocabulary: {}", e))
+/// True when the current session's last message is older than the staleness
+/// threshold. A language with no session yet is never stale.
+fn is_session_stale_at(lang_dir: &Path) -> Result<bool, String> {
+    let Some(last) = current_session_file(lang_dir).and_then(|p| last_message_timestamp(&p)) else {
+        return Ok(false);
+    };
+
+    let threshold = chrono::Duration::days(session_stale_after_days(lang_dir) as i64);
+    Ok(Local::now() - last > threshold)
 }
 
-#[tauri::command]
-fn get_grammar(language: String) -> Result<String, String> {
-    let grammar_file = get_language_dir(&language)?.join("grammar.json");
-    fs::read_to_string(&grammar_file).map_err(|e| format!("Failed to read grammar: {}", e))
+// ============================================================================
+// Grammar helpers
+// ============================================================================
+
+const UNCATEGORIZED: &str = "uncategorized";
+
+fn read_grammar(lang_dir: &Path) -> Result<Value, String> {
+    let content = fs::read_to_string(lang_dir.join("grammar.json"))
+        .map_err(|e| format!("Failed to read grammar: {}", e))?;
+    serde_json::from_str(&content).map_err(|e| format!("Failed to parse grammar: {}", e))
 }
 
-#[tauri::command]
-fn list_languages() -> Result<Vec<String>, String> {
-    let data_dir = get_data_dir()?;
+fn grammar_rules(grammar: &Value) -> &[Value] {
+    grammar
+        .get("rules")
+        .and_then(|r| r.as_array())
+        .map(|r| r.as_slice())
+        .unwrap_or(&[])
+}
 
-    if !data_dir.exists() {
-        return Ok(vec![]);
-    }
+fn rules_mut(grammar: &mut Value) -> Result<&mut Vec<Value>, String> {
+    grammar
+        .get_mut("rules")
+        .and_then(|r| r.as_array_mut())
+        .ok_or_else(|| "grammar.json has no rules array".to_string())
+}
 
-    let mut languages = Vec::new();
-    let entries =
-        fs::read_dir(&data_dir).map_err(|e| format!("Failed to read data directory: {}", e))?;
+/// Range `upsert_grammar_rule` clamps `stars` to.
+const MIN_GRAMMAR_STARS: u8 = 1;
+const MAX_GRAMMAR_STARS: u8 = 5;
 
-    for entry_result in entries {
-        let entry = match entry_result {
-            Ok(e) => e,
-            Err(e) => {
-                eprintln!("[list_languages] Error reading directory entry: {}", e);
-                continue;
-            }
-        };
-        if entry.path().is_dir() {
-            if let Some(name) = entry.file_name().to_str() {
-                languages.push(capitalize_first(name));
-            }
+fn rule_category(rule: &Value) -> &str {
+    rule.get("category")
+        .and_then(|v| v.as_str())
+        .filter(|c| !c.trim().is_empty())
+        .unwrap_or(UNCATEGORIZED)
+}
+
+// ============================================================================
+// Tracking file validation
+// ============================================================================
+
+/// vocabulary.json as handed to the frontend. Fields beyond the required ones
+/// are passed through untouched.
+#[derive(Serialize, Debug)]
+struct Vocabulary {
+    language: String,
+    words: Vec<VocabularyWord>,
+    #[serde(flatten)]
+    extra: serde_json::Map<String, Value>,
+}
+
+/// Numbers are kept as `Number` so integers aren't rewritten as floats.
+#[derive(Serialize, Deserialize, Debug)]
+struct VocabularyWord {
+    word: String,
+    ease: serde_json::Number,
+    interval: serde_json::Number,
+    repetitions: serde_json::Number,
+    #[serde(flatten)]
+    extra: serde_json::Map<String, Value>,
+}
+
+/// grammar.json as handed to the frontend.
+#[derive(Serialize, Debug)]
+struct Grammar {
+    language: String,
+    rules: Vec<GrammarRule>,
+    #[serde(flatten)]
+    extra: serde_json::Map<String, Value>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct GrammarRule {
+    rule: String,
+    stars: serde_json::Number,
+    correct_streak: serde_json::Number,
+    #[serde(flatten)]
+    extra: serde_json::Map<String, Value>,
+}
+
+/// Reads a tracking file whose entries live in the array under `list_key`,
+/// checking each entry on its own so the error can name the bad one. Returns
+/// the `language` field, the parsed entries and any other top-level fields.
+/// `label_key` is the field used to identify an entry in the message.
+fn parse_tracking_file<E: serde::de::DeserializeOwned>(
+    path: &Path,
+    list_key: &str,
+    label_key: &str,
+) -> Result<(String, Vec<E>, serde_json::Map<String, Value>), AppError> {
+    let file_name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let invalid = |message: String, entry: Option<usize>| AppError::InvalidData { message, entry };
+
+    let content = fs::read_to_string(path)
+        .map_err(|e| AppError::Io(format!("Failed to read {}: {}", file_name, e)))?;
+    let root: Value = serde_json::from_str(&content)
+        .map_err(|e| invalid(format!("{} is not valid JSON: {}", file_name, e), None))?;
+    let Value::Object(mut fields) = root else {
+        return Err(invalid(
+            format!("{} must be a JSON object", file_name),
+            None,
+        ));
+    };
+
+    let language = match fields.remove("language") {
+        None => String::new(),
+        Some(Value::String(language)) => language,
+        Some(_) => {
+            return Err(invalid(
+                format!("{}: \"language\" must be a string", file_name),
+                None,
+            ))
+        }
+    };
+    let entries = match fields.remove(list_key) {
+        Some(Value::Array(entries)) => entries,
+        _ => {
+            return Err(invalid(
+                format!("{}: \"{}\" must be an array", file_name, list_key),
+                None,
+            ))
         }
+    };
+
+    let mut parsed = Vec::with_capacity(entries.len());
+    for (index, entry) in entries.into_iter().enumerate() {
+        let label = entry
+            .get(label_key)
+            .and_then(|l| l.as_str())
+            .map(|l| format!(" (\"{}\")", l))
+            .unwrap_or_default();
+        let item = serde_json::from_value(entry).map_err(|e| {
+            invalid(
+                format!("{}: entry {}{} is invalid: {}", file_name, index, label, e),
+                Some(index),
+            )
+        })?;
+        parsed.push(item);
     }
 
-    Ok(languages)
+    Ok((language, parsed, fields))
 }
 
-#[tauri::command]
-fn delete_language(language: String) -> Result<String, String> {
-    let lang_dir = get_language_dir(&language)?;
+fn read_validated_vocabulary(lang_dir: &Path) -> Result<Vocabulary, AppError> {
+    let (language, words, extra) =
+        parse_tracking_file(&lang_dir.join("vocabulary.json"), "words", "word")?;
+    Ok(Vocabulary {
+        language,
+        words,
+        extra,
+    })
+}
 
-    if !lang_dir.exists() {
-        return Err(format!("Language '{}' does not exist", language));
+fn read_validated_grammar(lang_dir: &Path) -> Result<Grammar, AppError> {
+    let (language, rules, extra) =
+        parse_tracking_file(&lang_dir.join("grammar.json"), "rules", "rule")?;
+    Ok(Grammar {
+        language,
+        rules,
+        extra,
+    })
+}
+
+// ============================================================================
+// Statistics
+// ============================================================================
+
+/// Interval (in days) from which a word counts as mature, as in Anki.
+const MATURE_INTERVAL_DAYS: u64 = 21;
+
+#[derive(Serialize)]
+struct LanguageStats {
+    vocabulary: usize,
+    mature: usize,
+    grammar_rules: usize,
+    study_days: usize,
+    last_active: Option<String>,
+}
+
+impl LanguageStats {
+    /// Weighted progress score used to rank languages against each other.
+    fn score(&self) -> usize {
+        self.vocabulary + 2 * self.mature + 3 * self.grammar_rules + self.study_days
     }
+}
+
+fn is_word_mature(word: &Value) -> bool {
+    word.get("interval")
+        .and_then(|v| v.as_u64())
+        .is_some_and(|i| i >= MATURE_INTERVAL_DAYS)
+}
 
-    fs::remove_dir_all(&lang_dir).map_err(|e| format!("Failed to delete language: {}", e))?;
+fn compute_language_stats(lang_dir: &Path) -> Result<LanguageStats, String> {
+    let vocab = read_merged_vocabulary(lang_dir)?;
+    let grammar = read_grammar(lang_dir)?;
+    let words = vocabulary_words(&vocab);
+    let archived = read_mature_vocabulary(lang_dir)?;
+    let archived = vocabulary_words(&archived);
+    let activity = collect_activity_dates(&get_claude_project_dir(lang_dir)?);
 
-    Ok(format!("Deleted {}", language))
+    Ok(LanguageStats {
+        vocabulary: words.len() + archived.len(),
+        mature: words.iter().filter(|w| is_word_mature(w)).count() + archived.len(),
+        grammar_rules: grammar_rules(&grammar).len(),
+        study_days: activity.len(),
+        last_active: activity.last().map(|d| d.format(DATE_FORMAT).to_string()),
+    })
 }
 
-#[tauri::command]
-fn get_chat_history(language: String) -> Result<Vec<ChatMessage>, String> {
-    let lang_dir = get_language_dir(&language)?;
+/// Progress summary for a single language's dashboard. Files that can't be
+/// read contribute zeros and a message in `warnings` instead of failing.
+#[derive(Serialize, Default)]
+struct LanguageDashboard {
+    total_words: usize,
+    due_today: usize,
+    grammar_rules: usize,
+    /// Rule counts keyed by star level; rules without stars count as 0.
+    grammar_by_stars: BTreeMap<u64, usize>,
+    days_since_started: Option<i64>,
+    warnings: Vec<String>,
+}
 
-    if !lang_dir.exists() {
-        return Err(format!("Language '{}' not set up", language));
+fn compute_language_dashboard(lang_dir: &Path) -> LanguageDashboard {
+    let today = Local::now().date_naive();
+    let mut dashboard = LanguageDashboard::default();
+
+    match read_merged_vocabulary(lang_dir) {
+        Ok(vocab) => {
+            dashboard.total_words = vocabulary_words(&vocab).len();
+            dashboard.due_today = count_due_words(&vocab, today);
+        }
+        Err(e) => dashboard.warnings.push(e),
     }
 
-    let claude_project_dir = get_claude_project_dir(&lang_dir)?;
+    match read_grammar(lang_dir) {
+        Ok(grammar) => {
+            let rules = grammar_rules(&grammar);
+            dashboard.grammar_rules = rules.len();
+            for rule in rules {
+                let stars = rule.get("stars").and_then(|v| v.as_u64()).unwrap_or(0);
+                *dashboard.grammar_by_stars.entry(stars).or_default() += 1;
+            }
+        }
+        Err(e) => dashboard.warnings.push(e),
+    }
 
-    if !claude_project_dir.exists() {
-        return Ok(vec![]);
+    match read_config(lang_dir).and_then(|config| parse_date(&config.started)) {
+        Ok(started) => dashboard.days_since_started = Some((today - started).num_days()),
+        Err(e) => dashboard.warnings.push(e),
     }
 
-    match find_latest_jsonl_file(&claude_project_dir) {
-        Some(path) => parse_chat_messages_from_jsonl(&path),
-        None => Ok(vec![]),
+    for warning in &dashboard.warnings {
+        warn!("[Stats] {}: {}", lang_dir.display(), warning);
     }
+    dashboard
 }
 
-#[cfg_attr(mobile, tauri::mobile_entry_point)]
-pub fn run() {
-    tauri::Builder::default()
-        .plugin(tauri_plugin_shell::init())
-        .invoke_handler(tauri::generate_handler![
+// ============================================================================
+// Tracker change reports
+// ============================================================================
+
+/// Vocabulary and grammar entries keyed by `word` / `rule`, used to compare the
+/// files before and after a tracker run.
+struct TrackedEntries {
+    words: HashMap<String, Value>,
+    rules: HashMap<String, Value>,
+}
+
+#[derive(Serialize, Default)]
+struct TrackingChanges {
+    words_added: Vec<String>,
+    words_updated: Vec<String>,
+    rules_added: Vec<String>,
+    rules_updated: Vec<String>,
+}
+
+/// What `send_message` returns: the tutor's reply and, with `sync_tracking`,
+/// what the tracker made of the message.
+#[derive(Serialize)]
+struct SyncedReply {
+    reply: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tracking: Option<TrackingChanges>,
+    /// Set instead of `tracking` when the tracker failed; the reply stands.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tracking_error: Option<AppError>,
+}
+
+fn index_entries(entries: &[Value], key: &str) -> HashMap<String, Value> {
+    entries
+        .iter()
+        .filter_map(|e| Some((e.get(key)?.as_str()?.to_string(), e.clone())))
+        .collect()
+}
+
+fn read_tracked_entries(lang_dir: &Path) -> Result<TrackedEntries, String> {
+    Ok(TrackedEntries {
+        words: index_entries(vocabulary_words(&read_vocabulary(lang_dir)?), "word"),
+        rules: index_entries(grammar_rules(&read_grammar(lang_dir)?), "rule"),
+    })
+}
+
+/// Returns the keys that are new in `after`, and those whose entry changed.
+fn diff_entries(
+    before: &HashMap<String, Value>,
+    after: &HashMap<String, Value>,
+) -> (Vec<String>, Vec<String>) {
+    let mut added = Vec::new();
+    let mut updated = Vec::new();
+    for (key, entry) in after {
+        match before.get(key) {
+            None => added.push(key.clone()),
+            Some(old) if old != entry => updated.push(key.clone()),
+            Some(_) => {}
+        }
+    }
+    added.sort();
+    updated.sort();
+    (added, updated)
+}
+
+fn diff_tracked_entries(before: &TrackedEntries, after: &TrackedEntries) -> TrackingChanges {
+    let (words_added, words_updated) = diff_entries(&before.words, &after.words);
+    let (rules_added, rules_updated) = diff_entries(&before.rules, &after.rules);
+    TrackingChanges {
+        words_added,
+        words_updated,
+        rules_added,
+        rules_updated,
+    }
+}
+
+// ============================================================================
+// Per-language locking
+// ============================================================================
+
+static LANGUAGE_LOCKS: OnceLock<Mutex<HashMap<PathBuf, Arc<Mutex<()>>>>> = OnceLock::new();
+
+/// Returns the lock guarding read-modify-write cycles on a language's files.
+/// Callers hold the returned mutex for the whole cycle.
+fn language_lock(lang_dir: &Path) -> Arc<Mutex<()>> {
+    let locks = LANGUAGE_LOCKS.get_or_init(|| Mutex::new(HashMap::new()));
+    let mut locks = locks.lock().unwrap_or_else(|e| e.into_inner());
+    locks.entry(lang_dir.to_path_buf()).or_default().clone()
+}
+
+static TRACKER_LOCKS: OnceLock<Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>> =
+    OnceLock::new();
+
+/// Returns the async lock serializing tracker runs for a language, keyed by
+/// lowercase language name.
+///
+/// Locking contract:
+/// - The tracker holds this for its whole run (bounded by `TRACKER_TIMEOUT_SECS`),
+///   so at most one tracker writes a language's files at a time.
+/// - Commands that hand raw vocabulary.json / grammar.json to the frontend take
+///   it before reading, so they wait out an in-flight tracker instead of
+///   returning a half-written file.
+/// - Commands that edit vocabulary, grammar or CLAUDE.md in Rust, or move the
+///   language's folder, take this first and then `language_lock` for the
+///   read-modify-write itself. Otherwise a tracker that read the files before
+///   the edit would write its copy back over it, as would `TrackedFilesBackup`
+///   restoring a cancelled run.
+/// - Both locks are always taken in that order, so the two can't deadlock.
+fn tracker_lock(language: &str) -> Arc<tokio::sync::Mutex<()>> {
+    let locks = TRACKER_LOCKS.get_or_init(|| Mutex::new(HashMap::new()));
+    let mut locks = locks.lock().unwrap_or_else(|e| e.into_inner());
+    locks.entry(language.to_lowercase()).or_default().clone()
+}
+
+static SEND_LOCKS: OnceLock<Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>> = OnceLock::new();
+
+/// Claims the right to message a language. Held by `send_message` until its
+/// reply is in, so a second message waits rather than racing the first for
+/// the `--continue` session. With `wait` false, fails with `Busy` instead.
+/// Trackers are already serialized by `tracker_lock`.
+async fn acquire_send_slot(
+    language: &str,
+    wait: bool,
+) -> Result<tokio::sync::OwnedMutexGuard<()>, AppError> {
+    let lock = {
+        let locks = SEND_LOCKS.get_or_init(|| Mutex::new(HashMap::new()));
+        let mut locks = locks.lock().unwrap_or_else(|e| e.into_inner());
+        locks
+            .entry(language_dir_name(language))
+            .or_default()
+            .clone()
+    };
+    if wait {
+        Ok(lock.lock_owned().await)
+    } else {
+        lock.try_lock_owned().map_err(|_| {
+            AppError::Busy(format!(
+                "Still answering the previous message for {}",
+                language
+            ))
+        })
+    }
+}
+
+/// Lock key for a language directory: its folder name, which is the
+/// lowercased language name.
+fn language_key(lang_dir: &Path) -> String {
+    lang_dir
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+// ============================================================================
+// Cancellation
+// ============================================================================
+
+/// Cancel signals for in-flight `send_message` calls, keyed by lowercase
+/// language name. `cancel_message` flips the value to `true`.
+static IN_FLIGHT: OnceLock<Mutex<HashMap<String, tokio::sync::watch::Sender<bool>>>> =
+    OnceLock::new();
+
+fn in_flight() -> std::sync::MutexGuard<'static, HashMap<String, tokio::sync::watch::Sender<bool>>>
+{
+    IN_FLIGHT
+        .get_or_init(|| Mutex::new(HashMap::new()))
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+}
+
+/// Registration of one in-flight request; unregisters itself when dropped.
+struct InFlightRequest {
+    key: String,
+    sender: tokio::sync::watch::Sender<bool>,
+    cancelled: tokio::sync::watch::Receiver<bool>,
+}
+
+impl InFlightRequest {
+    /// Registers a request for `language`. A newer request replaces an older
+    /// one's entry, so `cancel_message` always targets the latest.
+    fn register(language: &str) -> Self {
+        let (sender, cancelled) = tokio::sync::watch::channel(false);
+        let key = language_dir_name(language);
+        in_flight().insert(key.clone(), sender.clone());
+        InFlightRequest {
+            key,
+            sender,
+            cancelled,
+        }
+    }
+
+    /// Resolves once `cancel_message` is called for this request.
+    async fn cancelled(&self) {
+        wait_for_cancel(self.cancelled.clone()).await
+    }
+
+    /// Like `cancelled`, for work that may outlive the request, such as the
+    /// tracker. Never resolves if the request finishes without a cancel.
+    fn cancel_signal(&self) -> impl Future<Output = ()> + Send + 'static {
+        wait_for_cancel(self.cancelled.clone())
+    }
+
+    fn error(&self) -> AppError {
+        AppError::Cancelled("Request cancelled".to_string())
+    }
+}
+
+/// Resolves once `cancelled` turns true. `wait_for` only fails once every
+/// sender is gone, and then no cancel can come.
+async fn wait_for_cancel(mut cancelled: tokio::sync::watch::Receiver<bool>) {
+    if cancelled.wait_for(|c| *c).await.is_err() {
+        std::future::pending::<()>().await;
+    }
+}
+
+impl Drop for InFlightRequest {
+    fn drop(&mut self) {
+        let mut requests = in_flight();
+        if requests
+            .get(&self.key)
+            .is_some_and(|s| s.same_channel(&self.sender))
+        {
+            requests.remove(&self.key);
+        }
+    }
+}
+
+/// Kills the child if it's still running when dropped, so abandoning an agent
+/// future (on timeout or cancellation) never leaves claude running.
+struct KillOnDrop(Arc<Mutex<Child>>);
+
+impl Drop for KillOnDrop {
+    fn drop(&mut self) {
+        let mut child = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        if let Ok(None) = child.try_wait() {
+            if let Err(e) = child.kill() {
+                error!("[Agent] Failed to kill claude: {}", e);
+            }
+            let _ = child.wait();
+        }
+    }
+}
+
+/// Files the tracker rewrites, restored by `TrackedFilesBackup` if it's stopped.
+const TRACKER_WRITTEN_FILES: &[&str] = &["vocabulary.json", "grammar.json"];
+
+/// Contents of the tracking files from before a tracker run. Unless `disarm`ed,
+/// writes them back when dropped, so a tracker killed mid-write can't leave a
+/// truncated file behind.
+struct TrackedFilesBackup {
+    files: Vec<(PathBuf, Vec<u8>)>,
+    armed: bool,
+}
+
+impl TrackedFilesBackup {
+    fn capture(lang_dir: &Path) -> Self {
+        let files = TRACKER_WRITTEN_FILES
+            .iter()
+            .map(|name| lang_dir.join(name))
+            .filter_map(|path| fs::read(&path).ok().map(|bytes| (path, bytes)))
+            .collect();
+        TrackedFilesBackup { files, armed: true }
+    }
+
+    fn disarm(&mut self) {
+        self.armed = false;
+    }
+}
+
+impl Drop for TrackedFilesBackup {
+    fn drop(&mut self) {
+        if !self.armed {
+            return;
+        }
+        for (path, bytes) in &self.files {
+            match write_atomic(path, bytes) {
+                Ok(()) => info!("[Tracker] Restored {}", path.display()),
+                Err(e) => error!("[Tracker] Failed to restore {}: {}", path.display(), e),
+            }
+        }
+    }
+}
+
+/// Reads a child's stdout and stderr to the end and waits for it to exit,
+/// polling so a `KillOnDrop` on another thread can still take the lock.
+fn collect_child_output(
+    stdout: ChildStdout,
+    stderr: ChildStderr,
+    child: &Mutex<Child>,
+) -> Result<(std::process::ExitStatus, String, String), String> {
+    let stderr_reader = std::thread::spawn(move || {
+        let mut buf = String::new();
+        let _ = BufReader::new(stderr).read_to_string(&mut buf);
+        buf
+    });
+
+    let mut out = String::new();
+    BufReader::new(stdout)
+        .read_to_string(&mut out)
+        .map_err(|e| format!("Failed to read claude output: {}", e))?;
+
+    let status = loop {
+        let exited = child
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .try_wait()
+            .map_err(|e| format!("Failed to wait for claude: {}", e))?;
+        match exited {
+            Some(status) => break status,
+            None => std::thread::sleep(Duration::from_millis(50)),
+        }
+    };
+    Ok((status, out, stderr_reader.join().unwrap_or_default()))
+}
+
+// ============================================================================
+// Reviews
+// ============================================================================
+
+#[derive(Serialize)]
+struct ReviewResult {
+    interval: u32,
+    next_review: String,
+}
+
+// ============================================================================
+// Platform helpers
+// ============================================================================
+
+/// Configures Command to hide the console window on Windows
+#[cfg(windows)]
+fn hide_console_window(cmd: &mut Command) {
+    use std::os::windows::process::CommandExt;
+    // Windows API flag to prevent spawning a visible console window
+    const CREATE_NO_WINDOW: u32 = 0x08000000;
+    cmd.creation_flags(CREATE_NO_WINDOW);
+}
+
+#[cfg(not(windows))]
+fn hide_console_window(_cmd: &mut Command) {
+    // No-op on non-Windows platforms
+}
+
+// ============================================================================
+// Claude CLI resolution
+// ============================================================================
+
+/// Environment variable overriding the Claude CLI binary (a path or a name on PATH).
+const CLAUDE_BIN_ENV: &str = "YSFL_CLAUDE_BIN";
+const DEFAULT_CLAUDE_BIN: &str = "claude";
+
+/// The configured binary, read once from the environment at first use.
+fn configured_claude_bin() -> &'static str {
+    static CLAUDE_BIN: OnceLock<String> = OnceLock::new();
+    CLAUDE_BIN.get_or_init(|| {
+        env::var(CLAUDE_BIN_ENV)
+            .ok()
+            .map(|v| v.trim().to_string())
+            .filter(|v| !v.is_empty())
+            .unwrap_or_else(|| DEFAULT_CLAUDE_BIN.to_string())
+    })
+}
+
+fn expand_tilde(path: &str) -> PathBuf {
+    let rest = path.strip_prefix("~/").or_else(|| path.strip_prefix("~\\"));
+    match (rest, dirs::home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ if path == "~" => dirs::home_dir().unwrap_or_else(|| PathBuf::from(path)),
+        _ => PathBuf::from(path),
+    }
+}
+
+/// Looks `name` up on PATH, trying the usual executable extensions on Windows.
+fn find_in_path(name: &str) -> Option<PathBuf> {
+    let extensions: &[&str] = if cfg!(windows) {
+        &["", ".exe", ".cmd", ".bat"]
+    } else {
+        &[""]
+    };
+    env::split_paths(&env::var_os("PATH")?)
+        .flat_map(|dir| {
+            extensions
+                .iter()
+                .map(move |ext| dir.join(format!("{}{}", name, ext)))
+        })
+        .find(|candidate| candidate.is_file())
+}
+
+/// Resolves the Claude CLI to an existing file. Bare names are searched on
+/// PATH; anything containing a path separator is treated as a path.
+fn resolve_claude_bin() -> Result<PathBuf, AppError> {
+    let configured = configured_claude_bin();
+    let expanded = expand_tilde(configured);
+
+    let resolved = if expanded.components().count() > 1 {
+        Some(expanded.clone()).filter(|p| p.is_file())
+    } else {
+        find_in_path(configured)
+    };
+
+    resolved.ok_or_else(|| {
+        AppError::ClaudeUnavailable(format!("Claude CLI not found at {}", expanded.display()))
+    })
+}
+
+#[derive(Serialize)]
+struct ClaudeStatus {
+    /// The CLI was found and answered `--version` in time.
+    installed: bool,
+    version: Option<String>,
+    /// The resolved binary, or the configured location when it wasn't found.
+    path: String,
+}
+
+/// Pulls the version number out of `claude --version` output such as
+/// "1.0.33 (Claude Code)".
+fn parse_claude_version(output: &str) -> Option<String> {
+    output
+        .split_whitespace()
+        .find(|token| token.starts_with(|c: char| c.is_ascii_digit()))
+        .map(|token| token.trim_start_matches('v').to_string())
+}
+
+/// Builds a `claude` invocation using the active profile (if any), with the
+/// console window hidden.
+fn claude_command() -> Result<Command, AppError> {
+    let mut cmd = Command::new(resolve_claude_bin()?);
+    match active_profile_config_dir() {
+        Ok(Some(config_dir)) => {
+            cmd.env(CLAUDE_CONFIG_DIR_ENV, config_dir);
+        }
+        Ok(None) => {}
+        Err(e) => warn!("[claude] Falling back to the default profile: {}", e),
+    }
+    hide_console_window(&mut cmd);
+    Ok(cmd)
+}
+
+// ============================================================================
+// Path helpers
+// ============================================================================
+
+fn get_exe_dir() -> Result<PathBuf, String> {
+    env::current_exe()
+        .map_err(|e| format!("Failed to get exe path: {}", e))?
+        .parent()
+        .map(|p| p.to_path_buf())
+        .ok_or_else(|| "Failed to get exe directory".to_string())
+}
+
+/// Set to `1` to keep data next to the executable (portable builds).
+const PORTABLE_ENV: &str = "YSFL_PORTABLE";
+
+/// Folder created under the platform's application data directory.
+const APP_DATA_DIR_NAME: &str = "your-second-first-language";
+
+/// Points the data directory somewhere else, e.g. a separate learning profile.
+const DATA_DIR_ENV: &str = "YSFL_DATA_DIR";
+
+/// Data root set with `set_data_root`, taking precedence over the default.
+static DATA_ROOT: RwLock<Option<PathBuf>> = RwLock::new(None);
+
+/// Makes every language path resolve against `root` (or the default location
+/// for `None`). Set once at startup; tests can point it at a temp dir.
+fn set_data_root(root: Option<PathBuf>) {
+    *DATA_ROOT.write().unwrap_or_else(|e| e.into_inner()) = root;
+}
+
+fn data_root_override() -> Option<PathBuf> {
+    DATA_ROOT.read().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+fn is_portable() -> bool {
+    env::var(PORTABLE_ENV).is_ok_and(|v| v.trim() == "1")
+}
+
+/// Where data lived before it moved to the application data directory, and
+/// still lives for portable builds.
+fn get_exe_data_dir() -> Result<PathBuf, String> {
+    Ok(get_exe_dir()?.join("data"))
+}
+
+/// The root set with `set_data_root` if any, else
+/// `<platform data dir>/your-second-first-language`, or `exe_dir/data` when
+/// `YSFL_PORTABLE=1` or the platform has no data directory.
+fn get_data_dir() -> Result<PathBuf, String> {
+    if let Some(root) = data_root_override() {
+        return Ok(root);
+    }
+    static DATA_DIR: OnceLock<Result<PathBuf, String>> = OnceLock::new();
+    DATA_DIR
+        .get_or_init(|| {
+            if is_portable() {
+                return get_exe_data_dir();
+            }
+            match dirs::data_dir() {
+                Some(dir) => Ok(dir.join(APP_DATA_DIR_NAME)),
+                None => {
+                    warn!("[Data dir] No platform data directory, using the exe directory");
+                    get_exe_data_dir()
+                }
+            }
+        })
+        .clone()
+}
+
+/// Creates the data directory, first moving an existing `exe_dir/data` into it
+/// (along with the Claude sessions recorded for its language folders) if the
+/// new location hasn't been used yet. Runs once at startup.
+fn prepare_data_dir() -> Result<(), String> {
+    let data_dir = get_data_dir()?;
+    let legacy_dir = get_exe_data_dir()?;
+
+    // An explicitly chosen root is its own profile; don't pull old data into it.
+    if data_root_override().is_none()
+        && data_dir != legacy_dir
+        && legacy_dir.is_dir()
+        && !data_dir.exists()
+    {
+        migrate_data_dir(&legacy_dir, &data_dir)?;
+    }
+
+    fs::create_dir_all(&data_dir).map_err(|e| format!("Failed to create data directory: {}", e))
+}
+
+/// File briefly created to prove the data directory accepts writes.
+const WRITE_PROBE_FILE: &str = ".write-test";
+
+/// Creates the data directory if needed and checks a file can be written in
+/// it, so commands fail early with a clear error instead of partway through.
+fn ensure_data_dir_writable() -> Result<PathBuf, AppError> {
+    let data_dir = get_data_dir()?;
+    let not_writable = |e: std::io::Error| {
+        AppError::DataDirNotWritable(format!(
+            "Can't write to the data directory {}: {}",
+            data_dir.display(),
+            e
+        ))
+    };
+    fs::create_dir_all(&data_dir).map_err(not_writable)?;
+    let probe = data_dir.join(WRITE_PROBE_FILE);
+    fs::write(&probe, b"").map_err(not_writable)?;
+    if let Err(e) = fs::remove_file(&probe) {
+        warn!("[Data dir] Failed to remove {}: {}", probe.display(), e);
+    }
+    Ok(data_dir)
+}
+
+fn migrate_data_dir(from: &Path, to: &Path) -> Result<(), String> {
+    info!("[Data dir] Moving {} to {}", from.display(), to.display());
+
+    // Claude keys sessions by working directory, so note where each language's
+    // sessions live before the folders move.
+    let mut sessions = Vec::new();
+    let entries =
+        fs::read_dir(from).map_err(|e| format!("Failed to read data directory: {}", e))?;
+    for entry in entries.filter_map(|e| e.ok()) {
+        if !entry.path().is_dir() {
+            continue;
+        }
+        for sub in ["", TRACKER_DIR, ONESHOT_DIR] {
+            let old_dir = entry.path().join(sub);
+            let new_dir = to.join(entry.file_name()).join(sub);
+            sessions.push((
+                encode_project_dir_name(&resolve_absolute_path(&old_dir)?),
+                new_dir,
+            ));
+        }
+    }
+
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create data directory: {}", e))?;
+    }
+    if fs::rename(from, to).is_err() {
+        // Renaming fails across drives; fall back to copying
+        copy_dir_recursive(from, to)?;
+        fs::remove_dir_all(from)
+            .map_err(|e| format!("Copied data but failed to remove the old copy: {}", e))?;
+    }
+
+    let projects_dir = claude_config_dir()?.join("projects");
+    for (old_name, new_dir) in sessions {
+        if let Err(e) = move_claude_project_dir(&projects_dir.join(old_name), &new_dir) {
+            warn!("[Data dir] {}", e);
+        }
+    }
+
+    Ok(())
+}
+
+fn copy_dir_recursive(from: &Path, to: &Path) -> Result<(), String> {
+    fs::create_dir_all(to).map_err(|e| format!("Failed to create {}: {}", to.display(), e))?;
+    let entries =
+        fs::read_dir(from).map_err(|e| format!("Failed to read {}: {}", from.display(), e))?;
+    for entry in entries {
+        let entry = entry.map_err(|e| format!("Failed to read directory entry: {}", e))?;
+        let target = to.join(entry.file_name());
+        if entry.path().is_dir() {
+            copy_dir_recursive(&entry.path(), &target)?;
+        } else {
+            fs::copy(entry.path(), &target)
+                .map_err(|e| format!("Failed to copy {}: {}", entry.path().display(), e))?;
+        }
+    }
+    Ok(())
+}
+
+// ============================================================================
+// Logging
+// ============================================================================
+
+/// Log folder inside the data dir. Hidden so it isn't listed as a language.
+const LOG_DIR_NAME: &str = ".logs";
+const LOG_FILE_PREFIX: &str = "ysfl";
+/// Daily log files kept before the oldest is deleted.
+const MAX_LOG_FILES: usize = 7;
+/// Filter used when `RUST_LOG` isn't set.
+const DEFAULT_LOG_FILTER: &str = "info";
+
+fn get_log_dir() -> Result<PathBuf, String> {
+    Ok(get_data_dir()?.join(LOG_DIR_NAME))
+}
+
+/// Installs the global subscriber: stderr always, plus a daily-rotated file in
+/// the data dir so release builds (which have no console) still leave a trail.
+/// Span close events carry timings, which is what timeouts get diagnosed from.
+/// Returns an error, after setting up stderr-only logging, if the file can't be opened.
+fn init_logging() -> Result<(), String> {
+    use tracing_subscriber::fmt::format::FmtSpan;
+    use tracing_subscriber::prelude::*;
+    use tracing_subscriber::EnvFilter;
+
+    let filter =
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(DEFAULT_LOG_FILTER));
+    let stderr_layer = tracing_subscriber::fmt::layer()
+        .with_writer(std::io::stderr)
+        .with_span_events(FmtSpan::CLOSE);
+
+    let file_appender = get_log_dir().and_then(|log_dir| {
+        fs::create_dir_all(&log_dir)
+            .map_err(|e| format!("Failed to create log directory: {}", e))?;
+        tracing_appender::rolling::RollingFileAppender::builder()
+            .rotation(tracing_appender::rolling::Rotation::DAILY)
+            .filename_prefix(LOG_FILE_PREFIX)
+            .filename_suffix("log")
+            .max_log_files(MAX_LOG_FILES)
+            .build(&log_dir)
+            .map_err(|e| format!("Failed to open log file in {}: {}", log_dir.display(), e))
+    });
+    let (file_layer, result) = match file_appender {
+        Ok(appender) => (
+            Some(
+                tracing_subscriber::fmt::layer()
+                    .with_writer(appender)
+                    .with_ansi(false)
+                    .with_span_events(FmtSpan::CLOSE),
+            ),
+            Ok(()),
+        ),
+        Err(e) => (None, Err(e)),
+    };
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(stderr_layer)
+        .with(file_layer)
+        .try_init()
+        .map_err(|e| format!("Failed to install logger: {}", e))?;
+    result
+}
+
+// ============================================================================
+// App settings
+// ============================================================================
+
+/// App-wide settings, stored in `settings.json` in the data directory.
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+struct AppSettings {
+    /// Claude profile used by all agents; None uses the CLI's own default.
+    claude_profile: Option<String>,
+    /// Extra Claude CLI arguments for languages that don't set their own.
+    agent_args: Vec<String>,
+    /// Whether to pass `--dangerously-skip-permissions`; None means yes.
+    skip_permissions: Option<bool>,
+}
+
+fn get_settings_path() -> Result<PathBuf, String> {
+    Ok(get_data_dir()?.join("settings.json"))
+}
+
+fn read_settings() -> Result<AppSettings, String> {
+    let path = get_settings_path()?;
+    if !path.exists() {
+        return Ok(AppSettings::default());
+    }
+    let content =
+        fs::read_to_string(&path).map_err(|e| format!("Failed to read settings: {}", e))?;
+    serde_json::from_str(&content).map_err(|e| format!("Failed to parse settings: {}", e))
+}
+
+fn write_settings(settings: &AppSettings) -> Result<(), String> {
+    let path = get_settings_path()?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create data directory: {}", e))?;
+    }
+    write_json_atomic(&path, settings)
+}
+
+// ============================================================================
+// Claude profiles
+// ============================================================================
+
+/// Environment variable the Claude CLI reads its config directory from.
+const CLAUDE_CONFIG_DIR_ENV: &str = "CLAUDE_CONFIG_DIR";
+
+/// Name reported for the CLI's own config directory.
+const DEFAULT_CLAUDE_PROFILE: &str = "default";
+
+/// Profiles are sibling config directories named `~/.claude-<name>`.
+const CLAUDE_PROFILE_PREFIX: &str = ".claude-";
+
+fn claude_profile_dir(name: &str) -> Result<PathBuf, String> {
+    let home = dirs::home_dir().ok_or("Failed to get home directory")?;
+    Ok(home.join(format!("{}{}", CLAUDE_PROFILE_PREFIX, name)))
+}
+
+/// Config directory of the selected profile, or None when using the default.
+fn active_profile_config_dir() -> Result<Option<PathBuf>, String> {
+    match read_settings()?.claude_profile {
+        Some(name) => claude_profile_dir(&name).map(Some),
+        None => Ok(None),
+    }
+}
+
+/// Config directory the CLI will use: the selected profile, else
+/// `$CLAUDE_CONFIG_DIR`, else `~/.claude`.
+fn claude_config_dir() -> Result<PathBuf, String> {
+    if let Some(dir) = active_profile_config_dir()? {
+        return Ok(dir);
+    }
+    if let Some(dir) = env::var_os(CLAUDE_CONFIG_DIR_ENV) {
+        return Ok(PathBuf::from(dir));
+    }
+    let home = dirs::home_dir().ok_or("Failed to get home directory")?;
+    Ok(home.join(".claude"))
+}
+
+/// Trims a language name and collapses internal whitespace to single spaces,
+/// giving the display name stored in config.json.
+fn normalize_language_name(language: &str) -> String {
+    language.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Folder name for a language: its normalized name, lowercased, so names
+/// differing only in case or whitespace share a directory.
+fn language_dir_name(language: &str) -> String {
+    normalize_language_name(language).to_lowercase()
+}
+
+/// Longest language name accepted, in characters.
+const MAX_LANGUAGE_NAME_CHARS: usize = 64;
+
+/// Characters Windows forbids in file names, besides control characters.
+const FORBIDDEN_NAME_CHARS: &[char] = &['/', '\\', '<', '>', ':', '"', '|', '?', '*'];
+
+/// Device names Windows reserves, with or without an extension.
+const RESERVED_DEVICE_NAMES: &[&str] = &[
+    "con", "prn", "aux", "nul", "com1", "com2", "com3", "com4", "com5", "com6", "com7", "com8",
+    "com9", "lpt1", "lpt2", "lpt3", "lpt4", "lpt5", "lpt6", "lpt7", "lpt8", "lpt9",
+];
+
+/// Checks that a language name is usable as a folder name on every platform.
+/// Any letters, marks, digits and punctuation are allowed apart from path
+/// separators and the characters Windows forbids.
+fn validate_language_name(language: &str) -> Result<(), AppError> {
+    if language.is_empty() {
+        return Err(AppError::InvalidName(
+            "Language name cannot be empty".to_string(),
+        ));
+    }
+    if language.chars().count() > MAX_LANGUAGE_NAME_CHARS {
+        return Err(AppError::InvalidName(format!(
+            "Language name can be at most {} characters",
+            MAX_LANGUAGE_NAME_CHARS
+        )));
+    }
+    if let Some(c) = language
+        .chars()
+        .find(|c| c.is_control() || FORBIDDEN_NAME_CHARS.contains(c))
+    {
+        return Err(AppError::InvalidNameChars(format!(
+            "Language name cannot contain '{}'",
+            c.escape_default()
+        )));
+    }
+    if language.contains("..") {
+        return Err(AppError::InvalidNameChars(
+            "Language name cannot contain '..'".to_string(),
+        ));
+    }
+    if language.starts_with('.') || language.ends_with('.') || language.ends_with(' ') {
+        return Err(AppError::InvalidNameEdges(
+            "Language name cannot start with a dot or end with a dot or space".to_string(),
+        ));
+    }
+    let stem = language.split('.').next().unwrap_or_default().trim_end();
+    if RESERVED_DEVICE_NAMES.contains(&stem.to_lowercase().as_str()) {
+        return Err(AppError::ReservedName(format!(
+            "'{}' is a reserved name on Windows",
+            language
+        )));
+    }
+    Ok(())
+}
+
+fn get_language_dir(language: &str) -> Result<PathBuf, AppError> {
+    validate_language_name(&normalize_language_name(language))?;
+    Ok(get_data_dir()?.join(language_dir_name(language)))
+}
+
+/// Uppercases the first letter of every whitespace-separated word, leaving the
+/// rest of each word (and the whitespace) untouched.
+fn title_case(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    let mut at_word_start = true;
+    for c in s.chars() {
+        if at_word_start {
+            result.extend(c.to_uppercase());
+        } else {
+            result.push(c);
+        }
+        at_word_start = c.is_whitespace();
+    }
+    result
+}
+
+/// Derives the Claude CLI project path from a directory.
+/// E.g., C:\Users\wongp\Desktop\lang\data\korean -> ~/.claude/projects/C--Users-wongp-Desktop-lang-data-korean
+/// and /home/wongp/lang/data/korean -> ~/.claude/projects/-home-wongp-lang-data-korean
+fn get_claude_project_dir(dir: &Path) -> Result<PathBuf, String> {
+    Ok(claude_config_dir()?
+        .join("projects")
+        .join(encode_project_dir_name(&resolve_absolute_path(dir)?)))
+}
+
+/// Canonicalizes `dir`. When it can't be resolved (e.g. the directory hasn't
+/// been created yet), the path is made absolute against the working directory,
+/// cleaned of `.`/`..`, and its deepest existing ancestor is canonicalized, so
+/// the result matches what Claude will see once the directory exists.
+fn resolve_absolute_path(dir: &Path) -> Result<PathBuf, String> {
+    if let Ok(canonical) = dir.canonicalize() {
+        return Ok(canonical);
+    }
+
+    let absolute = if dir.is_absolute() {
+        dir.to_path_buf()
+    } else {
+        env::current_dir()
+            .map_err(|e| format!("Failed to resolve path: {}", e))?
+            .join(dir)
+    };
+    let cleaned = normalize_path(&absolute);
+
+    let mut missing = Vec::new();
+    let mut existing = cleaned.as_path();
+    while let Some(parent) = existing.parent() {
+        missing.push(existing.file_name().unwrap_or_default().to_owned());
+        existing = parent;
+        if let Ok(canonical) = existing.canonicalize() {
+            return Ok(missing
+                .iter()
+                .rev()
+                .fold(canonical, |path, name| path.join(name)));
+        }
+    }
+    Ok(cleaned)
+}
+
+/// Removes `.` components and resolves `..` against the preceding component,
+/// without touching the file system. Meant for absolute paths.
+fn normalize_path(path: &Path) -> PathBuf {
+    use std::path::Component;
+
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            // `..` at the root stays at the root
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            other => normalized.push(other),
+        }
+    }
+    normalized
+}
+
+/// Removes the Windows extended-length prefix BEFORE any replacements.
+/// `\\?\C:\foo` becomes `C:\foo`; `\\?\UNC\server\share` becomes the
+/// regular UNC form `\\server\share`.
+fn strip_extended_path_prefix(path_str: &str) -> String {
+    if let Some(unc) = path_str.strip_prefix(r"\\?\UNC\") {
+        format!(r"\\{}", unc)
+    } else if let Some(local) = path_str.strip_prefix(r"\\?\") {
+        local.to_string()
+    } else {
+        path_str.to_string()
+    }
+}
+
+fn encode_project_dir_name(path: &Path) -> String {
+    encode_project_path(&path.to_string_lossy(), cfg!(windows))
+}
+
+/// `encode_project_dir_name` on a path string, with the platform passed in so
+/// every platform's paths can be checked on any host.
+fn encode_project_path(path_str: &str, windows: bool) -> String {
+    let path_str = if windows {
+        strip_extended_path_prefix(path_str)
+    } else {
+        path_str.to_string()
+    };
+
+    // Convert path to Claude's project folder format, which replaces every
+    // character other than ASCII letters and digits with a dash:
+    // C:\Users\foo\bar -> C--Users-foo-bar
+    // \\server\share\foo -> --server-share-foo
+    // /Users/foo/my.lang -> -Users-foo-my-lang
+    path_str
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect()
+}
+
+// ============================================================================
+// Bootstrap helpers
+// ============================================================================
+
+fn write_language_file(dir: &Path, filename: &str, content: &str) -> Result<(), String> {
+    write_atomic(&dir.join(filename), content.as_bytes())
+        .map_err(|e| format!("Failed to write {}: {}", filename, e))
+}
+
+/// Files rendered from templates during bootstrap.
+const RENDERED_FILES: &[&str] = &[
+    "CLAUDE.md",
+    "vocabulary.json",
+    "grammar.json",
+    "user-overrides.json",
+];
+
+/// Returns every `{{...}}` placeholder left in rendered content.
+fn find_unresolved_placeholders(content: &str) -> Vec<String> {
+    let mut placeholders = Vec::new();
+    let mut rest = content;
+    while let Some(start) = rest.find("{{") {
+        let after = &rest[start..];
+        let end = after.find("}}").map_or(after.len(), |i| i + 2);
+        placeholders.push(after[..end].chars().take(64).collect());
+        rest = &after[end..];
+    }
+    placeholders
+}
+
+fn render_tutor_instructions(language: &str, info: &LanguageInfo) -> String {
+    TUTOR_TEMPLATE
+        .replace("{{LANGUAGE_NAME}}", language)
+        .replace("{{LANGUAGE_NATIVE}}", &info.native_script)
+        .replace("{{ROMANIZATION}}", &info.romanization)
+        .replace("{{LANGUAGE_SPECIFIC_NOTES}}", &info.notes)
+}
+
+/// Marks where the learner's own additions to CLAUDE.md start. Everything after
+/// it survives `regenerate_tutor_instructions` and renames.
+const USER_NOTES_SENTINEL: &str = "<!-- USER NOTES -->";
+
+/// Replaces the part of `rendered` after the sentinel with the learner's notes
+/// from `previous`, if it has any.
+fn keep_user_notes(rendered: String, previous: Option<&str>) -> String {
+    let Some((_, notes)) = previous.and_then(|p| p.split_once(USER_NOTES_SENTINEL)) else {
+        return rendered;
+    };
+    match rendered.split_once(USER_NOTES_SENTINEL) {
+        Some((base, _)) => format!("{}{}{}", base, USER_NOTES_SENTINEL, notes),
+        None => format!(
+            "{}\n\n{}{}",
+            rendered.trim_end(),
+            USER_NOTES_SENTINEL,
+            notes
+        ),
+    }
+}
+
+/// Writes a freshly rendered CLAUDE.md, keeping the learner's notes from the
+/// current one.
+fn write_tutor_instructions(
+    lang_dir: &Path,
+    language: &str,
+    info: &LanguageInfo,
+) -> Result<(), String> {
+    let rendered = render_tutor_instructions(language, info);
+    let unresolved = find_unresolved_placeholders(&rendered);
+    if !unresolved.is_empty() {
+        return Err(format!(
+            "Unresolved template placeholders in CLAUDE.md: {}",
+            unresolved.join(", ")
+        ));
+    }
+
+    let previous = fs::read_to_string(lang_dir.join("CLAUDE.md")).ok();
+    write_language_file(
+        lang_dir,
+        "CLAUDE.md",
+        &keep_user_notes(rendered, previous.as_deref()),
+    )
+}
+
+fn generate_language_files(lang_dir: &Path, language: &str) -> Result<(), String> {
+    for (filename, content) in render_language_files(language)? {
+        write_language_file(lang_dir, filename, &content)?;
+    }
+    Ok(())
+}
+
+/// Renders every file a new language starts with, without touching the disk.
+/// Fails if any template placeholder is left unresolved.
+fn render_language_files(language: &str) -> Result<Vec<(&'static str, String)>, String> {
+    let info = get_language_info(language);
+
+    let claude_md = render_tutor_instructions(language, &info);
+    let vocab = VOCABULARY_TEMPLATE.replace("{{LANGUAGE_NAME}}", language);
+    let grammar = GRAMMAR_TEMPLATE.replace("{{LANGUAGE_NAME}}", language);
+    let overrides = USER_OVERRIDES_TEMPLATE.replace("{{LANGUAGE_NAME}}", language);
+
+    let mut rendered = vec![
+        ("CLAUDE.md", claude_md),
+        ("vocabulary.json", vocab),
+        ("grammar.json", grammar),
+        ("user-overrides.json", overrides),
+    ];
+
+    // Check everything up front so a bad template leaves no files behind
+    for (filename, content) in &rendered {
+        let unresolved = find_unresolved_placeholders(content);
+        if !unresolved.is_empty() {
+            return Err(format!(
+                "Unresolved template placeholders in {}: {}",
+                filename,
+                unresolved.join(", ")
+            ));
+        }
+    }
+
+    let config = LanguageConfig {
+        language: language.to_string(),
+        native_script: info.native_script,
+        romanization: info.romanization,
+        started: Local::now().format("%Y-%m-%d").to_string(),
+        greeting: None,
+        archived: false,
+    };
+    let config_json = serde_json::to_string_pretty(&config)
+        .map_err(|e| format!("Failed to serialize config: {}", e))?;
+    rendered.push(("config.json", config_json));
+    Ok(rendered)
+}
+
+fn read_config(lang_dir: &Path) -> Result<LanguageConfig, String> {
+    let content = fs::read_to_string(lang_dir.join("config.json"))
+        .map_err(|e| format!("Failed to read config: {}", e))?;
+    serde_json::from_str(&content).map_err(|e| format!("Failed to parse config: {}", e))
+}
+
+// ============================================================================
+// Backups
+// ============================================================================
+
+/// Folder inside each language dir holding copies of files taken before
+/// destructive edits, one timestamped subfolder per backup.
+const BACKUPS_DIR: &str = ".backups";
+
+/// Name format of the timestamped backup folders.
+const BACKUP_STAMP_FORMAT: &str = "%Y%m%d-%H%M%S%.3f";
+
+/// Copies those of `files` that exist into a new `.backups/<timestamp>` folder
+/// and returns its path.
+fn backup_language_files(lang_dir: &Path, files: &[&str]) -> Result<PathBuf, String> {
+    let stamp = Local::now().format(BACKUP_STAMP_FORMAT).to_string();
+    let backup_dir = lang_dir.join(BACKUPS_DIR).join(stamp);
+    fs::create_dir_all(&backup_dir)
+        .map_err(|e| format!("Failed to create backup directory: {}", e))?;
+
+    for name in files {
+        let source = lang_dir.join(name);
+        if source.exists() {
+            fs::copy(&source, backup_dir.join(name))
+                .map_err(|e| format!("Failed to back up {}: {}", name, e))?;
+        }
+    }
+    Ok(backup_dir)
+}
+
+/// Files `restore_backup` may put back.
+const RESTORABLE_FILES: &[&str] = &[
+    "CLAUDE.md",
+    "vocabulary.json",
+    "grammar.json",
+    MATURE_VOCABULARY_FILE,
+];
+
+/// One file inside a backup folder.
+#[derive(Serialize)]
+struct BackupEntry {
+    /// `<backup folder>/<file>`, as passed to `restore_backup`.
+    path: String,
+    file: String,
+    /// When the backup was taken, from the folder name.
+    created: Option<String>,
+    size: u64,
+}
+
+/// Every restorable file in a language's backups, newest backup first.
+fn list_backup_entries(lang_dir: &Path) -> Result<Vec<BackupEntry>, String> {
+    let Ok(folders) = fs::read_dir(lang_dir.join(BACKUPS_DIR)) else {
+        return Ok(Vec::new());
+    };
+    let mut folders: Vec<_> = folders
+        .filter_map(|e| e.ok())
+        .filter(|e| e.path().is_dir())
+        .filter_map(|e| Some((e.path(), e.file_name().to_str()?.to_string())))
+        .collect();
+    // Timestamps sort lexically
+    folders.sort_by(|a, b| b.1.cmp(&a.1));
+
+    let mut entries = Vec::new();
+    for (folder, stamp) in folders {
+        let created = NaiveDateTime::parse_from_str(&stamp, BACKUP_STAMP_FORMAT)
+            .ok()
+            .map(|t| t.format("%Y-%m-%dT%H:%M:%S").to_string());
+        for &file in RESTORABLE_FILES {
+            let Ok(metadata) = fs::metadata(folder.join(file)) else {
+                continue;
+            };
+            entries.push(BackupEntry {
+                path: format!("{}/{}", stamp, file),
+                file: file.to_string(),
+                created: created.clone(),
+                size: metadata.len(),
+            });
+        }
+    }
+    Ok(entries)
+}
+
+/// Resolves a `BackupEntry::path` to the backed-up file and the live file name
+/// it restores, refusing anything outside the language's `.backups` folder.
+fn resolve_backup_path(lang_dir: &Path, path: &str) -> Result<(PathBuf, &'static str), AppError> {
+    let invalid = || AppError::InvalidInput(format!("Invalid backup path '{}'", path));
+    let (folder, file) = path.split_once('/').ok_or_else(invalid)?;
+    if folder.is_empty() || folder.starts_with('.') || folder.contains('\\') {
+        return Err(invalid());
+    }
+    let file = RESTORABLE_FILES
+        .iter()
+        .copied()
+        .find(|&f| f == file)
+        .ok_or_else(invalid)?;
+
+    let backups_dir = lang_dir.join(BACKUPS_DIR);
+    let backup = backups_dir.join(folder).join(file);
+    if !backup.is_file() {
+        return Err(AppError::NotFound(format!(
+            "Backup '{}' does not exist",
+            path
+        )));
+    }
+    // Guards against links pointing out of the backups folder
+    let resolved = backup.canonicalize().map_err(|e| e.to_string())?;
+    let root = backups_dir.canonicalize().map_err(|e| e.to_string())?;
+    if !resolved.starts_with(&root) {
+        return Err(invalid());
+    }
+    Ok((resolved, file))
+}
+
+/// Checks a backed-up copy of `file` reads as a valid version of it.
+fn validate_backup(backup: &Path, file: &str) -> Result<(), AppError> {
+    match file {
+        "vocabulary.json" => {
+            parse_tracking_file::<VocabularyWord>(backup, "words", "word")?;
+        }
+        "grammar.json" => {
+            parse_tracking_file::<GrammarRule>(backup, "rules", "rule")?;
+        }
+        _ => {
+            let content = fs::read_to_string(backup)
+                .map_err(|e| AppError::Io(format!("Failed to read backup: {}", e)))?;
+            if content.trim().is_empty() {
+                return Err(AppError::InvalidData {
+                    message: format!("Backup of {} is empty", file),
+                    entry: None,
+                });
+            }
+            if file.ends_with(".json") {
+                serde_json::from_str::<Value>(&content).map_err(|e| AppError::InvalidData {
+                    message: format!("Backup of {} is not valid JSON: {}", file, e),
+                    entry: None,
+                })?;
+            }
+        }
+    }
+    Ok(())
+}
+
+// ============================================================================
+// Trash
+// ============================================================================
+
+/// Folder in the data dir that deleted languages are moved to.
+const TRASH_DIR: &str = ".trash";
+/// Deleted languages kept in the trash; older ones are purged.
+const MAX_TRASHED_LANGUAGES: usize = 10;
+/// Prefix of a trash entry's name. Contains no `_`.
+const TRASH_STAMP_FORMAT: &str = "%Y%m%d-%H%M%S%.3f";
+
+fn get_trash_dir() -> Result<PathBuf, String> {
+    Ok(get_data_dir()?.join(TRASH_DIR))
+}
+
+/// Trashed languages as `(entry path, language dir name)`, oldest first.
+/// Entries are named `<timestamp>_<language dir>`. The timestamp has no `_`,
+/// so splitting at the first one keeps any in the language name intact.
+fn list_trash() -> Result<Vec<(PathBuf, String)>, String> {
+    let trash_dir = get_trash_dir()?;
+    let Ok(entries) = fs::read_dir(&trash_dir) else {
+        return Ok(Vec::new());
+    };
+
+    let mut trashed: Vec<(PathBuf, String)> = entries
+        .filter_map(|e| e.ok())
+        .filter(|e| e.path().is_dir())
+        .filter_map(|e| {
+            let name = e.file_name().to_str()?.to_string();
+            let (stamp, language) = name.split_once('_')?;
+            NaiveDateTime::parse_from_str(stamp, TRASH_STAMP_FORMAT).ok()?;
+            Some((e.path(), language.to_string()))
+        })
+        .collect();
+    // Timestamps sort lexically
+    trashed.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(trashed)
+}
+
+/// Deletes the oldest trashed languages beyond `MAX_TRASHED_LANGUAGES`.
+fn purge_trash() -> Result<(), String> {
+    let trashed = list_trash()?;
+    let excess = trashed.len().saturating_sub(MAX_TRASHED_LANGUAGES);
+    for (path, _) in &trashed[..excess] {
+        fs::remove_dir_all(path)
+            .map_err(|e| format!("Failed to purge {}: {}", path.display(), e))?;
+    }
+    Ok(())
+}
+
+/// Moves a language folder into the trash and purges old entries.
+fn move_to_trash(lang_dir: &Path) -> Result<(), String> {
+    let trash_dir = get_trash_dir()?;
+    fs::create_dir_all(&trash_dir)
+        .map_err(|e| format!("Failed to create trash directory: {}", e))?;
+    let stamp = Local::now().format(TRASH_STAMP_FORMAT);
+    let entry = trash_dir.join(format!("{}_{}", stamp, language_key(lang_dir)));
+    fs::rename(lang_dir, &entry).map_err(|e| format!("Failed to delete language: {}", e))?;
+
+    if let Err(e) = purge_trash() {
+        warn!("[Trash] {}", e);
+    }
+    Ok(())
+}
+
+// ============================================================================
+// Language merge
+// ============================================================================
+
+/// Scheduling fields compared, in order, to decide which copy of a word is
+/// further along.
+const VOCAB_PROGRESS_FIELDS: &[&str] = &["repetitions", "interval", "ease"];
+const GRAMMAR_PROGRESS_FIELDS: &[&str] = &["stars", "correct_streak"];
+
+fn progress_key(entry: &Value, fields: &[&str]) -> Vec<f64> {
+    fields
+        .iter()
+        .map(|f| entry.get(f).and_then(|v| v.as_f64()).unwrap_or(0.0))
+        .collect()
+}
+
+/// Adds `source` entries to `target`, matching on `key_field`. When both have
+/// an entry, the one with the higher `progress_fields` (compared in order)
+/// wins; ties keep the target's. Entries whose key is in `skip` are dropped.
+/// Returns how many entries were added or replaced.
+fn merge_entries(
+    target: &mut Vec<Value>,
+    source: Vec<Value>,
+    key_field: &str,
+    progress_fields: &[&str],
+    skip: &HashSet<String>,
+) -> usize {
+    let mut index: HashMap<String, usize> = target
+        .iter()
+        .enumerate()
+        .filter_map(|(i, e)| Some((e.get(key_field)?.as_str()?.to_string(), i)))
+        .collect();
+
+    let mut changed = 0;
+    for entry in source {
+        let Some(key) = entry.get(key_field).and_then(|v| v.as_str()) else {
+            continue;
+        };
+        if skip.contains(key) {
+            continue;
+        }
+        match index.get(key) {
+            Some(&i) => {
+                let ours = progress_key(&target[i], progress_fields);
+                let theirs = progress_key(&entry, progress_fields);
+                if theirs.partial_cmp(&ours) == Some(std::cmp::Ordering::Greater) {
+                    target[i] = entry;
+                    changed += 1;
+                }
+            }
+            None => {
+                index.insert(key.to_string(), target.len());
+                target.push(entry);
+                changed += 1;
+            }
+        }
+    }
+    changed
+}
+
+fn entries_mut<'a>(data: &'a mut Value, list_key: &str) -> Result<&'a mut Vec<Value>, String> {
+    if !data.get(list_key).is_some_and(Value::is_array) {
+        data[list_key] = json!([]);
+    }
+    data.get_mut(list_key)
+        .and_then(|v| v.as_array_mut())
+        .ok_or_else(|| format!("Expected a '{}' array", list_key))
+}
+
+fn take_entries(mut data: Value, list_key: &str) -> Vec<Value> {
+    match data.get_mut(list_key).map(Value::take) {
+        Some(Value::Array(entries)) => entries,
+        _ => Vec::new(),
+    }
+}
+
+/// How many entries `merge_languages` brought over.
+#[derive(Serialize)]
+struct MergeSummary {
+    words: usize,
+    rules: usize,
+    /// Backup of the target's files from before the merge.
+    backup_dir: String,
+}
+
+// ============================================================================
+// Flashcard export
+// ============================================================================
+
+const FLASHCARDS_TEMPLATE: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>{{LANGUAGE_NAME}} flashcards</title>
+<style>
+  body { font-family: sans-serif; margin: 0; padding: 1cm; }
+  h1 { font-size: 14pt; margin: 0 0 0.5cm; }
+  .grid { display: grid; grid-template-columns: repeat(3, 1fr); gap: 0; }
+  .card { border: 1px dashed #999; height: 6cm; display: flex; flex-direction: column; page-break-inside: avoid; break-inside: avoid; }
+  .front, .back { flex: 1; display: flex; flex-direction: column; align-items: center; justify-content: center; text-align: center; padding: 0.3cm; }
+  .front { border-bottom: 1px dotted #ccc; }
+  .word { font-size: 24pt; }
+  .romanization { font-size: 11pt; color: #555; margin-top: 0.2cm; }
+  .back { font-size: 13pt; transform: rotate(180deg); }
+  @media print { body { padding: 0; } h1 { display: none; } }
+</style>
+</head>
+<body>
+<h1>{{LANGUAGE_NAME}} flashcards: cut along the dashed lines, fold along the dotted line</h1>
+<div class="grid">
+{{CARDS}}
+</div>
+</body>
+</html>
+"#;
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn render_flashcard(word: &Value, show_romanization: bool) -> Option<String> {
+    let text = word.get("word")?.as_str()?;
+    let meaning = word.get("meaning").and_then(|v| v.as_str()).unwrap_or("");
+    let romanization = word
+        .get("romanization")
+        .and_then(|v| v.as_str())
+        .filter(|r| show_romanization && !r.is_empty())
+        .map(|r| format!("<div class=\"romanization\">{}</div>", escape_html(r)))
+        .unwrap_or_default();
+
+    Some(format!(
+        "<div class=\"card\"><div class=\"front\"><div class=\"word\">{}</div>{}</div><div class=\"back\">{}</div></div>",
+        escape_html(text),
+        romanization,
+        escape_html(meaning)
+    ))
+}
+
+// ============================================================================
+// Transcript export
+// ============================================================================
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+enum TranscriptFormat {
+    Markdown,
+    Html,
+}
+
+impl TranscriptFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            TranscriptFormat::Markdown => "md",
+            TranscriptFormat::Html => "html",
+        }
+    }
+}
+
+const TRANSCRIPT_HTML_TEMPLATE: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>{{TITLE}}</title>
+<style>
+  body { font-family: sans-serif; max-width: 40em; margin: 2em auto; padding: 0 1em; }
+  .message { margin: 1em 0; padding: 0.6em 0.9em; border-radius: 8px; }
+  .user { background: #e8f0fe; }
+  .assistant { background: #f1f3f4; }
+  .meta { font-size: 9pt; color: #666; margin-bottom: 0.3em; }
+  .content { white-space: pre-wrap; }
+</style>
+</head>
+<body>
+<h1>{{TITLE}}</h1>
+{{MESSAGES}}
+</body>
+</html>
+"#;
+
+fn speaker_label(role: &str) -> &'static str {
+    if role == "user" {
+        "Learner"
+    } else {
+        "Tutor"
+    }
+}
+
+/// Message time as local `YYYY-MM-DD HH:MM`, or the raw value if it doesn't parse.
+fn transcript_time(message: &ChatMessage) -> Option<String> {
+    let raw = message.timestamp.as_deref()?;
+    Some(match DateTime::parse_from_rfc3339(raw) {
+        Ok(time) => time
+            .with_timezone(&Local)
+            .format("%Y-%m-%d %H:%M")
+            .to_string(),
+        Err(_) => raw.to_string(),
+    })
+}
+
+/// Renders messages as a transcript. Message text is kept verbatim, so
+/// romanization and the tutor's own markdown come through unchanged.
+fn render_transcript(title: &str, messages: &[ChatMessage], format: TranscriptFormat) -> String {
+    match format {
+        TranscriptFormat::Markdown => {
+            let mut out = format!("# {}\n", title);
+            for msg in messages {
+                let label = speaker_label(&msg.role);
+                match transcript_time(msg) {
+                    Some(time) => out.push_str(&format!("\n**{}** · {}\n\n", label, time)),
+                    None => out.push_str(&format!("\n**{}**\n\n", label)),
+                }
+                out.push_str(msg.content.trim());
+                out.push_str("\n\n---\n");
+            }
+            out
+        }
+        TranscriptFormat::Html => {
+            let rendered: Vec<String> = messages
+                .iter()
+                .map(|msg| {
+                    let meta = match transcript_time(msg) {
+                        Some(time) => format!("{} · {}", speaker_label(&msg.role), time),
+                        None => speaker_label(&msg.role).to_string(),
+                    };
+                    format!(
+                        "<div class=\"message {}\"><div class=\"meta\">{}</div><div class=\"content\">{}</div></div>",
+                        if msg.role == "user" { "user" } else { "assistant" },
+                        escape_html(&meta),
+                        escape_html(msg.content.trim())
+                    )
+                })
+                .collect();
+            TRANSCRIPT_HTML_TEMPLATE
+                .replace("{{TITLE}}", &escape_html(title))
+                .replace("{{MESSAGES}}", &rendered.join("\n"))
+        }
+    }
+}
+
+// ============================================================================
+// Language archives
+// ============================================================================
+
+/// Working directories that are regenerated on demand and never archived.
+const ARCHIVE_SKIPPED_DIRS: &[&str] = &[TRACKER_DIR, ONESHOT_DIR, BACKUPS_DIR];
+
+/// Adds every file under `dir` to the archive, named relative to `root` with
+/// forward slashes. Files are streamed in rather than read into memory.
+fn add_dir_to_zip(
+    zip: &mut zip::ZipWriter<File>,
+    root: &Path,
+    dir: &Path,
+    options: zip::write::SimpleFileOptions,
+) -> Result<(), String> {
+    let entries =
+        fs::read_dir(dir).map_err(|e| format!("Failed to read {}: {}", dir.display(), e))?;
+
+    for entry in entries {
+        let entry = entry.map_err(|e| format!("Failed to read directory entry: {}", e))?;
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let file_type = entry
+            .file_type()
+            .map_err(|e| format!("Failed to inspect {}: {}", path.display(), e))?;
+
+        if file_type.is_dir() {
+            if dir == root && ARCHIVE_SKIPPED_DIRS.contains(&name.as_str()) {
+                continue;
+            }
+            add_dir_to_zip(zip, root, &path, options)?;
+        } else if file_type.is_file() && !name.ends_with(".tmp") {
+            let relative = path
+                .strip_prefix(root)
+                .map_err(|e| format!("Failed to resolve archive path: {}", e))?;
+            let entry_name = relative
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+
+            zip.start_file(entry_name, options)
+                .map_err(|e| format!("Failed to add {} to archive: {}", name, e))?;
+            let mut file =
+                File::open(&path).map_err(|e| format!("Failed to open {}: {}", name, e))?;
+            std::io::copy(&mut file, zip)
+                .map_err(|e| format!("Failed to write {} to archive: {}", name, e))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// True for archive entry names that are absolute or step outside the archive root.
+fn is_unsafe_archive_path(name: &str) -> bool {
+    name.starts_with('/')
+        || name.starts_with('\\')
+        || name.split(['/', '\\']).any(|part| part == "..")
+        || name.contains(':')
+}
+
+/// Extracts every entry of `archive` under `dest`. Entries must already have
+/// been vetted with `is_unsafe_archive_path`.
+fn extract_zip(archive: &mut zip::ZipArchive<File>, dest: &Path) -> Result<(), String> {
+    for i in 0..archive.len() {
+        let mut entry = archive
+            .by_index(i)
+            .map_err(|e| format!("Failed to read archive entry: {}", e))?;
+        let relative = entry
+            .enclosed_name()
+            .ok_or_else(|| format!("Archive entry '{}' has an unsafe path", entry.name()))?;
+        let out_path = dest.join(relative);
+
+        if entry.is_dir() {
+            fs::create_dir_all(&out_path)
+                .map_err(|e| format!("Failed to create {}: {}", out_path.display(), e))?;
+            continue;
+        }
+        if let Some(parent) = out_path.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+        }
+        let mut file = File::create(&out_path)
+            .map_err(|e| format!("Failed to create {}: {}", out_path.display(), e))?;
+        std::io::copy(&mut entry, &mut file)
+            .map_err(|e| format!("Failed to extract {}: {}", out_path.display(), e))?;
+    }
+
+    Ok(())
+}
+
+// ============================================================================
+// Chat search
+// ============================================================================
+
+/// Characters of context kept on each side of the first match in a snippet.
+const SEARCH_SNIPPET_CONTEXT_CHARS: usize = 40;
+
+/// Characters kept of the messages either side of a hit.
+const SEARCH_NEIGHBOUR_PREVIEW_CHARS: usize = 200;
+
+#[derive(Serialize)]
+struct ChatSearchHit {
+    /// Position of the message in the history searched.
+    index: usize,
+    message: ChatMessage,
+    snippet: String,
+    /// `[start, end)` ranges of the matches within `snippet`, in UTF-16 code
+    /// units so they can be passed straight to `String.prototype.slice`.
+    highlights: Vec<[usize; 2]>,
+    /// The messages just before and after the hit, shortened, for context.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    previous: Option<ChatMessage>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    next: Option<ChatMessage>,
+    /// Session the message is from, when searching the session files.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    session_id: Option<String>,
+}
+
+/// Byte ranges of the non-overlapping, case-insensitive matches of `query` in
+/// `text`. Case folding is done per character with Unicode lowercasing, so
+/// ranges always fall on character boundaries of the original text.
+fn find_case_insensitive(text: &str, query: &str) -> Vec<(usize, usize)> {
+    let needle: Vec<char> = query.to_lowercase().chars().collect();
+    if needle.is_empty() {
+        return Vec::new();
+    }
+    // Each lowercased char with the byte span of the original char it came from.
+    let folded: Vec<(char, usize, usize)> = text
+        .char_indices()
+        .flat_map(|(i, c)| c.to_lowercase().map(move |lc| (lc, i, i + c.len_utf8())))
+        .collect();
+
+    let mut matches = Vec::new();
+    let mut k = 0;
+    while k + needle.len() <= folded.len() {
+        let window = &folded[k..k + needle.len()];
+        if window.iter().map(|(c, _, _)| c).eq(needle.iter()) {
+            matches.push((window[0].1, window[needle.len() - 1].2));
+            k += needle.len();
+        } else {
+            k += 1;
+        }
+    }
+    matches
+}
+
+fn utf16_len(s: &str) -> usize {
+    s.encode_utf16().count()
+}
+
+/// Cuts a window around the first match and maps the matches inside it to
+/// UTF-16 offsets within the snippet.
+fn build_snippet(text: &str, matches: &[(usize, usize)]) -> (String, Vec<[usize; 2]>) {
+    let Some(&(first_start, first_end)) = matches.first() else {
+        return (String::new(), Vec::new());
+    };
+    let start = text[..first_start]
+        .char_indices()
+        .rev()
+        .nth(SEARCH_SNIPPET_CONTEXT_CHARS - 1)
+        .map_or(0, |(i, _)| i);
+    let end = text[first_end..]
+        .char_indices()
+        .nth(SEARCH_SNIPPET_CONTEXT_CHARS)
+        .map_or(text.len(), |(i, _)| first_end + i);
+
+    let prefix = if start > 0 { "…" } else { "" };
+    let suffix = if end < text.len() { "…" } else { "" };
+    let snippet = format!("{}{}{}", prefix, &text[start..end], suffix);
+
+    let base = utf16_len(prefix);
+    let highlights = matches
+        .iter()
+        .filter(|(s, e)| *s >= start && *e <= end)
+        .map(|&(s, e)| {
+            let from = base + utf16_len(&text[start..s]);
+            [from, from + utf16_len(&text[s..e])]
+        })
+        .collect();
+    (snippet, highlights)
+}
+
+// ============================================================================
+// Diagnostics
+// ============================================================================
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum Severity {
+    Error,
+    Warning,
+}
+
+/// One problem found by `diagnose`.
+#[derive(Serialize)]
+struct Finding {
+    language: String,
+    severity: Severity,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    file: Option<String>,
+    /// Index of the offending word or rule, if the problem is confined to one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    entry: Option<usize>,
+    message: String,
+}
+
+/// Files every language folder is expected to have.
+const EXPECTED_FILES: &[&str] = &[
+    "CLAUDE.md",
+    "vocabulary.json",
+    "grammar.json",
+    "user-overrides.json",
+    "config.json",
+];
+
+/// Reports entries whose `field` value appears earlier in the list too.
+fn find_duplicate_entries(entries: &[Value], field: &str) -> Vec<(usize, String)> {
+    let mut seen = HashSet::new();
+    entries
+        .iter()
+        .enumerate()
+        .filter_map(|(i, e)| {
+            let key = e.get(field)?.as_str()?;
+            (!seen.insert(key)).then(|| (i, key.to_string()))
+        })
+        .collect()
+}
+
+/// Checks one language's files and session mapping.
+fn diagnose_language(language: &str) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    let mut report = |severity, file: Option<&str>, entry, message: String| {
+        findings.push(Finding {
+            language: language.to_string(),
+            severity,
+            file: file.map(str::to_string),
+            entry,
+            message,
+        });
+    };
+
+    let lang_dir = match get_language_dir(language) {
+        Ok(dir) => dir,
+        Err(e) => {
+            report(Severity::Error, None, None, e.to_string());
+            return findings;
+        }
+    };
+
+    for &file in EXPECTED_FILES {
+        if !lang_dir.join(file).exists() {
+            report(
+                Severity::Error,
+                Some(file),
+                None,
+                format!("{} is missing", file),
+            );
+        }
+    }
+
+    for &file in RENDERED_FILES {
+        if let Ok(content) = fs::read_to_string(lang_dir.join(file)) {
+            for placeholder in find_unresolved_placeholders(&content) {
+                report(
+                    Severity::Warning,
+                    Some(file),
+                    None,
+                    format!("Unresolved template placeholder {}", placeholder),
+                );
+            }
+        }
+    }
+
+    let tracking = [
+        ("vocabulary.json", "words", "word"),
+        ("grammar.json", "rules", "rule"),
+    ];
+    for (file, list_key, label_key) in tracking {
+        let path = lang_dir.join(file);
+        if !path.exists() {
+            continue;
+        }
+        let valid = if list_key == "words" {
+            read_validated_vocabulary(&lang_dir).map(|_| ())
+        } else {
+            read_validated_grammar(&lang_dir).map(|_| ())
+        };
+        if let Err(e) = valid {
+            let entry = match &e {
+                AppError::InvalidData { entry, .. } => *entry,
+                _ => None,
+            };
+            report(Severity::Error, Some(file), entry, e.to_string());
+            continue;
+        }
+        let Ok(data) = fs::read_to_string(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|c| serde_json::from_str::<Value>(&c).map_err(|e| e.to_string()))
+        else {
+            continue;
+        };
+        let entries = data
+            .get(list_key)
+            .and_then(|v| v.as_array())
+            .map_or(&[][..], Vec::as_slice);
+        for (index, key) in find_duplicate_entries(entries, label_key) {
+            report(
+                Severity::Warning,
+                Some(file),
+                Some(index),
+                format!("Duplicate {} '{}'", label_key, key),
+            );
+        }
+    }
+
+    if lang_dir.join("config.json").exists() {
+        if let Err(e) = read_config(&lang_dir) {
+            report(Severity::Error, Some("config.json"), None, e);
+        }
+    }
+
+    if lang_dir.join("user-overrides.json").exists() {
+        match read_user_overrides(&lang_dir) {
+            Ok(overrides) => {
+                if let Some(Err(e)) = overrides.model.as_deref().map(validate_model) {
+                    report(
+                        Severity::Warning,
+                        Some("user-overrides.json"),
+                        None,
+                        e.to_string(),
+                    );
+                }
+            }
+            Err(e) => report(Severity::Error, Some("user-overrides.json"), None, e),
+        }
+    }
+
+    match get_claude_project_dir(&lang_dir) {
+        Ok(project_dir) => {
+            if !project_dir.exists() && lang_dir.join(chat_store::CHAT_DB_FILE).exists() {
+                report(
+                    Severity::Warning,
+                    None,
+                    None,
+                    format!(
+                        "No Claude sessions at {}; the tutor will start a new conversation",
+                        project_dir.display()
+                    ),
+                );
+            }
+        }
+        Err(e) => report(
+            Severity::Error,
+            None,
+            None,
+            format!("Can't resolve the Claude project folder: {}", e),
+        ),
+    }
+
+    findings
+}
+
+// ============================================================================
+// Agent backends
+// ============================================================================
+
+/// Boxed so `Agent` stays object-safe and can live in managed state.
+type AgentFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// A CLI (or API) that plays the tutor and the tracker. `send_message` uses
+/// whichever one is in managed state, so tests can swap in a mock that
+/// records its calls.
+trait Agent: Send + Sync {
+    /// Produces the tutor's reply, passing `RESPONDER_CHUNK_EVENT`s to
+    /// `options.events` as it streams.
+    fn respond<'a>(
+        &'a self,
+        dir: &'a Path,
+        message: &'a str,
+        options: ResponderOptions,
+    ) -> AgentFuture<'a, Result<String, AppError>>;
+
+    /// Updates the tracking files for one learner message.
+    fn track<'a>(
+        &'a self,
+        dir: &'a Path,
+        message: &'a str,
+    ) -> AgentFuture<'a, Result<(), AppError>>;
+}
+
+type SharedAgent = Arc<dyn Agent>;
+
+/// Takes an event name and payload. Commands send them to the frontend with
+/// `app_events`; tests record them.
+type EventSink = Arc<dyn Fn(&str, Value) + Send + Sync>;
+
+fn app_events(app: &AppHandle) -> EventSink {
+    let app = app.clone();
+    Arc::new(move |event: &str, payload: Value| {
+        if let Err(e) = app.emit(event, payload) {
+            warn!("Failed to emit {}: {}", event, e);
+        }
+    })
+}
+
+/// Lowercase stderr fragments that mark a claude failure as a passing network
+/// or rate-limit blip worth retrying.
+const TRANSIENT_ERROR_MARKERS: &[&str] = &[
+    "rate limit",
+    "rate_limit_error",
+    "overloaded",
+    "network error",
+    "socket hang up",
+    "econnreset",
+    "econnrefused",
+    "etimedout",
+    "enotfound",
+    "eai_again",
+];
+
+/// HTTP statuses worth retrying: rate limited, bad gateway, unavailable and
+/// overloaded.
+const TRANSIENT_HTTP_STATUSES: &[&str] = &["429", "502", "503", "529"];
+
+/// Words a status code follows in claude's errors, as in "API Error: 529" or
+/// "status code 503". A number anywhere else may be a token count or a date.
+const HTTP_STATUS_PREFIXES: &[&str] = &["error", "status", "code", "http"];
+
+/// Whether `message` (lowercased) reports one of `TRANSIENT_HTTP_STATUSES`.
+fn has_transient_status(message: &str) -> bool {
+    let words: Vec<&str> = message
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .collect();
+    words.windows(2).any(|pair| {
+        HTTP_STATUS_PREFIXES.contains(&pair[0]) && TRANSIENT_HTTP_STATUSES.contains(&pair[1])
+    })
+}
+
+/// Lowercase stderr fragments that mean a retry would fail the same way.
+const FATAL_ERROR_MARKERS: &[&str] = &["command not found", "no such file or directory"];
+
+/// Whether a failed claude run is worth retrying. Only runs that exited
+/// non-zero qualify; spawn failures, timeouts and cancellations never do.
+fn is_transient_claude_error(error: &AppError) -> bool {
+    let AppError::Claude(message) = error else {
+        return false;
+    };
+    let message = message.to_lowercase();
+    !FATAL_ERROR_MARKERS.iter().any(|m| message.contains(m))
+        && (TRANSIENT_ERROR_MARKERS.iter().any(|m| message.contains(m))
+            || has_transient_status(&message))
+}
+
+/// Runs `attempt` until it succeeds, fails with an error `retryable` rejects,
+/// or `claude_retries()` retries are used up, backing off exponentially
+/// between tries.
+async fn with_claude_retries<T, F, Fut>(
+    label: &str,
+    mut attempt: F,
+    retryable: impl Fn(&AppError) -> bool,
+) -> Result<T, AppError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, AppError>>,
+{
+    let retries = claude_retries();
+    let mut delay = Duration::from_millis(CLAUDE_RETRY_BASE_DELAY_MS);
+    for retry in 1..=retries {
+        match attempt().await {
+            Err(e) if retryable(&e) => {
+                warn!(
+                    "[{}] Attempt {}/{} failed, retrying in {}ms: {}",
+                    label,
+                    retry,
+                    retries + 1,
+                    delay.as_millis(),
+                    e
+                );
+                tokio::time::sleep(delay).await;
+                delay *= 2;
+            }
+            result => return result,
+        }
+    }
+    attempt().await
+}
+
+/// Drives the `claude` CLI.
+struct ClaudeAgent;
+
+impl Agent for ClaudeAgent {
+    fn respond<'a>(
+        &'a self,
+        dir: &'a Path,
+        message: &'a str,
+        options: ResponderOptions,
+    ) -> AgentFuture<'a, Result<String, AppError>> {
+        Box::pin(run_responder_agent(dir, message, options))
+    }
+
+    fn track<'a>(
+        &'a self,
+        dir: &'a Path,
+        message: &'a str,
+    ) -> AgentFuture<'a, Result<(), AppError>> {
+        Box::pin(run_tracker_agent(dir, message))
+    }
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+/// Returns the files `bootstrap_language` would create (filename → content)
+/// without writing anything.
+#[tauri::command]
+fn preview_bootstrap(language: String) -> Result<HashMap<String, String>, AppError> {
+    get_language_dir(&language)?;
+    Ok(render_language_files(&language)?
+        .into_iter()
+        .map(|(filename, content)| (filename.to_string(), content))
+        .collect())
+}
+
+/// What `bootstrap_language` created, so the UI can render the new language
+/// without reading its config back.
+#[derive(Serialize)]
+struct BootstrapResult {
+    language: String,
+    native_script: String,
+    romanization: String,
+    started: String,
+}
+
+#[tauri::command]
+#[tracing::instrument(err)]
+fn bootstrap_language(language: String) -> Result<BootstrapResult, AppError> {
+    let language = normalize_language_name(&language);
+    let lang_dir = get_language_dir(&language)?;
+
+    if lang_dir.exists() {
+        return Err(AppError::AlreadyExists(format!(
+            "Language '{}' already exists",
+            language
+        )));
+    }
+
+    ensure_data_dir_writable()?;
+    fs::create_dir_all(&lang_dir)
+        .map_err(|e| format!("Failed to create language directory: {}", e))?;
+
+    if let Err(e) = generate_language_files(&lang_dir, &language) {
+        if let Err(cleanup_err) = fs::remove_dir_all(&lang_dir) {
+            error!(
+                "[bootstrap] Failed to roll back {}: {}",
+                lang_dir.display(),
+                cleanup_err
+            );
+        }
+        return Err(e.into());
+    }
+
+    let config = read_config(&lang_dir)?;
+    Ok(BootstrapResult {
+        language: config.language,
+        native_script: config.native_script,
+        romanization: config.romanization,
+        started: config.started,
+    })
+}
+
+/// Data files carrying a top-level `language` field.
+const LANGUAGE_TAGGED_FILES: &[&str] = &[
+    "vocabulary.json",
+    "grammar.json",
+    "user-overrides.json",
+    MATURE_VOCABULARY_FILE,
+];
+
+/// Renames a language, moving its directory and Claude session history and
+/// rewriting the name stored in its files.
+#[tauri::command]
+async fn rename_language(old: String, new: String) -> Result<String, AppError> {
+    let new = normalize_language_name(&new);
+    let old_dir = get_language_dir(&old)?;
+    let new_dir = get_language_dir(&new)?;
+
+    if !old_dir.exists() {
+        return Err(AppError::NotFound(format!(
+            "Language '{}' does not exist",
+            old
+        )));
+    }
+    // Names differing only in case share a directory; just rewrite the files.
+    let moving = old_dir != new_dir;
+    if moving && new_dir.exists() {
+        return Err(AppError::AlreadyExists(format!(
+            "Language '{}' already exists",
+            new
+        )));
+    }
+
+    let tracker = tracker_lock(&language_key(&old_dir));
+    let _tracker_guard = tracker.lock().await;
+    let lock = language_lock(&old_dir);
+    let _guard = lock.lock().unwrap_or_else(|e| e.into_inner());
+
+    if moving {
+        // Resolve project dirs before the move; canonicalizing needs the path to exist.
+        let project_dirs = [
+            old_dir.clone(),
+            old_dir.join(TRACKER_DIR),
+            old_dir.join(ONESHOT_DIR),
+        ]
+        .iter()
+        .map(|dir| get_claude_project_dir(dir))
+        .collect::<Result<Vec<_>, _>>()?;
+
+        fs::rename(&old_dir, &new_dir)
+            .map_err(|e| format!("Failed to rename language directory: {}", e))?;
+
+        let targets = [
+            new_dir.clone(),
+            new_dir.join(TRACKER_DIR),
+            new_dir.join(ONESHOT_DIR),
+        ];
+        for (i, (from, dir)) in project_dirs.iter().zip(&targets).enumerate() {
+            if let Err(e) = move_claude_project_dir(from, dir) {
+                if i > 0 {
+                    // Only the tutor's own sessions matter for history.
+                    warn!("[rename_language] {}", e);
+                    continue;
+                }
+                if let Err(rollback_err) = fs::rename(&new_dir, &old_dir) {
+                    error!(
+                        "[rename_language] Failed to roll back {}: {}",
+                        new_dir.display(),
+                        rollback_err
+                    );
+                }
+                return Err(e.into());
+            }
+        }
+    }
+
+    let info = get_language_info(&new);
+    write_tutor_instructions(&new_dir, &new, &info)?;
+
+    let mut config = read_config(&new_dir)?;
+    config.language = new.clone();
+    config.native_script = info.native_script;
+    config.romanization = info.romanization;
+    write_json_atomic(&new_dir.join("config.json"), &config)?;
+
+    for filename in LANGUAGE_TAGGED_FILES {
+        let path = new_dir.join(filename);
+        if !path.exists() {
+            continue;
+        }
+        let content =
+            fs::read_to_string(&path).map_err(|e| format!("Failed to read {}: {}", filename, e))?;
+        let mut data: Value = serde_json::from_str(&content)
+            .map_err(|e| format!("Failed to parse {}: {}", filename, e))?;
+        if let Some(obj) = data.as_object_mut() {
+            obj.insert("language".to_string(), json!(new));
+        }
+        write_json_atomic(&path, &data)?;
+    }
+
+    Ok(format!("Renamed {} to {}", old, new))
+}
+
+/// Moves a Claude projects folder so sessions follow a renamed directory.
+fn move_claude_project_dir(from: &Path, to_dir: &Path) -> Result<(), String> {
+    if !from.exists() {
+        return Ok(());
+    }
+    let to = get_claude_project_dir(to_dir)?;
+    if to.exists() {
+        return Err(format!(
+            "Claude project folder {} already exists",
+            to.display()
+        ));
+    }
+    fs::rename(from, &to).map_err(|e| format!("Failed to move chat history: {}", e))
+}
+
+/// Health check for hand-edited data: checks one language, or every language
+/// (archived ones included) when `language` is None. An empty list means no
+/// problems were found.
+#[tauri::command]
+fn diagnose(language: Option<String>) -> Result<Vec<Finding>, AppError> {
+    let languages = match language {
+        Some(language) => {
+            if !get_language_dir(&language)?.exists() {
+                return Err(AppError::NotFound(format!(
+                    "Language '{}' does not exist",
+                    language
+                )));
+            }
+            vec![language]
+        }
+        None => collect_languages(true)?,
+    };
+    Ok(languages
+        .iter()
+        .flat_map(|language| diagnose_language(language))
+        .collect())
+}
+
+/// Scans a language's template-rendered files for leftover `{{...}}`
+/// placeholders. Returns one entry per problem; empty means all good.
+#[tauri::command]
+fn check_rendered_files(language: String) -> Result<Vec<String>, AppError> {
+    let lang_dir = get_language_dir(&language)?;
+
+    let mut problems = Vec::new();
+    for filename in RENDERED_FILES {
+        let content = fs::read_to_string(lang_dir.join(filename))
+            .map_err(|e| format!("Failed to read {}: {}", filename, e))?;
+        for placeholder in find_unresolved_placeholders(&content) {
+            problems.push(format!("{}: {}", filename, placeholder));
+        }
+    }
+
+    Ok(problems)
+}
+
+const TRACKER_PROMPT: &str = r#"[TRACKER TASK - UPDATE FILES ONLY, NO RESPONSE]
+
+Process this learner message and update vocabulary.json and grammar.json.
+
+Learner said: {{MESSAGE}}
+
+Known words (may be partial; vocabulary.json is authoritative): {{KNOWN_WORDS}}
+Known grammar rules (may be partial; grammar.json is authoritative): {{KNOWN_RULES}}
+
+Instructions:
+1. Use the known lists above to tell new entries from existing ones; open
+   vocabulary.json and grammar.json to make your edits
+2. For each word/particle the learner used:
+   - If NEW: add entry with ease=2.5, interval=1, repetitions=1,
+     next_review=tomorrow and added=today (both YYYY-MM-DD)
+   - If EXISTS: leave it alone - do NOT change ease, interval, repetitions or next_review
+3. For grammar patterns used:
+   - If NEW: add entry with stars=1, correct_streak=1, and a short lowercase category
+     (e.g. "particles", "tenses", "cases", "conjugation", "word order")
+   - If EXISTS: increment correct_streak, upgrade stars if appropriate
+4. Write updated files
+5. Output ONLY a JSON array of the words that already existed in vocabulary.json
+   and were used correctly, e.g. ["word1", "word2"]. Output [] if there are none.
+
+IMPORTANT: Check for duplicates by word/rule field. Update existing entries, don't create duplicates.
+Preserve every existing field you don't update, including fields you don't recognize.
+Never change or remove "notes", "tags", "priority" or "suspended" - they are written by the learner."#;
+
+/// Most known words listed in the tracker prompt.
+const MAX_TRACKER_KNOWN_WORDS: usize = 300;
+/// Most known grammar rules listed in the tracker prompt.
+const MAX_TRACKER_KNOWN_RULES: usize = 100;
+
+/// Comma-separated `field` values of `entries` for the tracker prompt, at most
+/// `max` of them. Entries mentioned in the message come first, then those
+/// with the earliest `next_review`, so the likeliest matches survive the cap.
+fn known_entries_list(entries: &[Value], field: &str, message: &str, max: usize) -> String {
+    let mut known: Vec<(bool, &str, &str)> = entries
+        .iter()
+        .filter_map(|e| {
+            let key = e.get(field)?.as_str()?;
+            let next_review = e.get("next_review").and_then(|v| v.as_str()).unwrap_or("");
+            Some((!message.contains(key), next_review, key))
+        })
+        .collect();
+    if known.is_empty() {
+        return "(none)".to_string();
+    }
+    known.sort();
+    let total = known.len();
+    let mut list = known
+        .iter()
+        .take(max)
+        .map(|(_, _, key)| *key)
+        .collect::<Vec<_>>()
+        .join(", ");
+    if total > max {
+        list.push_str(&format!(" ... and {} more", total - max));
+    }
+    list
+}
+
+/// Working directory of the background tracker, inside the language dir.
+const TRACKER_DIR: &str = ".tracker";
+
+/// Tracker high-water mark, kept across `clean_tracker` unless `full` is set.
+/// Holds `last_success`, the RFC 3339 time the last tracker run completed.
+const TRACKER_STATE_FILE: &str = "state.json";
+
+fn read_last_tracker_success(lang_dir: &Path) -> Option<DateTime<Local>> {
+    let content = fs::read_to_string(lang_dir.join(TRACKER_DIR).join(TRACKER_STATE_FILE)).ok()?;
+    let state: Value = serde_json::from_str(&content).ok()?;
+    parse_local_timestamp(state.get("last_success")?.as_str()?)
+}
+
+/// Emitted once when a tracker run finishes and the tracking files are up to date.
+const TRACKER_DONE_EVENT: &str = "tracker://done";
+/// Emitted once instead of `TRACKER_DONE_EVENT` when a run fails, times out or
+/// is cancelled.
+const TRACKER_ERROR_EVENT: &str = "tracker://error";
+
+#[derive(Serialize, Clone)]
+struct TrackerEvent {
+    language: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<AppError>,
+}
+
+fn emit_tracker_event(events: &EventSink, lang_dir: &Path, error: Option<AppError>) {
+    let event = if error.is_some() {
+        TRACKER_ERROR_EVENT
+    } else {
+        TRACKER_DONE_EVENT
+    };
+    let payload = TrackerEvent {
+        language: language_key(lang_dir),
+        error,
+    };
+    events(event, json!(payload));
+}
+
+/// A background tracker run; resolves to what it changed.
+type TrackerTask = tokio::task::JoinHandle<Result<TrackingChanges, AppError>>;
+
+/// Runs the tracker in the background and reports the outcome with
+/// `TRACKER_DONE_EVENT` or `TRACKER_ERROR_EVENT`. If `request` is cancelled
+/// first, the run is stopped and its file edits rolled back.
+fn spawn_tracker_agent(
+    events: EventSink,
+    agent: SharedAgent,
+    lang_dir: PathBuf,
+    message: String,
+    request: &InFlightRequest,
+) -> TrackerTask {
+    let cancelled = request.cancel_signal();
+    tokio::spawn(async move {
+        let result = tokio::select! {
+            result = track_changes(agent.as_ref(), &lang_dir, &message) => result,
+            _ = cancelled => Err(AppError::Cancelled("Tracker cancelled".to_string())),
+        };
+        if let Err(e) = &result {
+            error!("[Tracker] {}", e);
+        }
+        emit_tracker_event(&events, &lang_dir, result.as_ref().err().cloned());
+        result
+    })
+}
+
+/// Runs the tracker under `tracker_lock` and diffs the vocabulary and grammar
+/// across the run. Both reads happen under the lock, so edits from an earlier
+/// tracker that is still finishing don't show up as this run's changes.
+async fn track_changes(
+    agent: &dyn Agent,
+    lang_dir: &Path,
+    message: &str,
+) -> Result<TrackingChanges, AppError> {
+    let lock = tracker_lock(&language_key(lang_dir));
+    let _tracker_guard = lock.lock().await;
+
+    let before = read_tracked_entries(lang_dir)?;
+    agent.track(lang_dir, message).await?;
+    let after = read_tracked_entries(lang_dir)?;
+    Ok(diff_tracked_entries(&before, &after))
+}
+
+/// Runs the tracker on one learner message and waits for it to finish.
+/// Callers hold `tracker_lock`.
+///
+/// If the returned future is dropped early (timeout or `cancel_message`), the
+/// claude process is killed and the tracking files are put back as they were.
+#[tracing::instrument(skip_all, fields(lang_dir = %lang_dir.display()))]
+async fn run_tracker_agent(lang_dir: &Path, message: &str) -> Result<(), AppError> {
+    let tracker_dir = lang_dir.join(TRACKER_DIR);
+    fs::create_dir_all(&tracker_dir)
+        .map_err(|e| format!("Failed to create tracker directory: {}", e))?;
+
+    let snapshot = match read_vocabulary(lang_dir) {
+        Ok(vocab) => Some(snapshot_user_fields(&vocab)),
+        Err(e) => {
+            warn!("[Tracker] Failed to snapshot user fields: {}", e);
+            None
+        }
+    };
+
+    let result = with_claude_retries(
+        "Tracker",
+        || run_tracker_process(lang_dir, &tracker_dir, message),
+        is_transient_claude_error,
+    )
+    .await;
+
+    if let Some(snapshot) = snapshot.filter(|s| !s.is_empty()) {
+        if let Err(e) = reconcile_user_fields(lang_dir, &snapshot) {
+            error!("[Tracker] Failed to restore user fields: {}", e);
+        }
+    }
+    result
+}
+
+/// Runs the tracker's claude process and applies the reviews it reports.
+async fn run_tracker_process(
+    lang_dir: &Path,
+    tracker_dir: &Path,
+    message: &str,
+) -> Result<(), AppError> {
+    let known_words = match read_vocabulary(lang_dir) {
+        Ok(vocab) => known_entries_list(
+            vocabulary_words(&vocab),
+            "word",
+            message,
+            MAX_TRACKER_KNOWN_WORDS,
+        ),
+        Err(e) => {
+            warn!("[Tracker] Failed to list known words: {}", e);
+            "(unavailable)".to_string()
+        }
+    };
+    let known_rules = match read_grammar(lang_dir) {
+        Ok(grammar) => known_entries_list(
+            grammar_rules(&grammar),
+            "rule",
+            message,
+            MAX_TRACKER_KNOWN_RULES,
+        ),
+        Err(e) => {
+            warn!("[Tracker] Failed to list known rules: {}", e);
+            "(unavailable)".to_string()
+        }
+    };
+    // The message goes in last so text in it can't be taken for a placeholder.
+    let prompt = TRACKER_PROMPT
+        .replace("{{KNOWN_WORDS}}", &known_words)
+        .replace("{{KNOWN_RULES}}", &known_rules)
+        .replace("{{MESSAGE}}", message);
+    let mut cmd = claude_command()?;
+    apply_model(&mut cmd, lang_dir);
+    apply_agent_args(&mut cmd, lang_dir);
+    cmd.arg("-p")
+        .arg(&prompt)
+        .current_dir(tracker_dir)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+
+    let mut backup = TrackedFilesBackup::capture(lang_dir);
+    let mut child = match cmd.spawn() {
+        Ok(child) => child,
+        Err(e) => {
+            backup.disarm();
+            return Err(AppError::ClaudeUnavailable(format!(
+                "Failed to run claude: {}",
+                e
+            )));
+        }
+    };
+    let stdout = child.stdout.take();
+    let stderr = child.stderr.take();
+    let child = Arc::new(Mutex::new(child));
+    let _kill_guard = KillOnDrop(Arc::clone(&child));
+    let task = {
+        let child = Arc::clone(&child);
+        tokio::task::spawn_blocking(move || {
+            let (Some(stdout), Some(stderr)) = (stdout, stderr) else {
+                return Err("Output was not captured".to_string());
+            };
+            collect_child_output(stdout, stderr, &child)
+        })
+    };
+
+    let timeout = Duration::from_secs(TRACKER_TIMEOUT_SECS);
+    let (status, stdout, stderr) = tokio::time::timeout(timeout, task)
+        .await
+        .map_err(|_| {
+            AppError::Timeout(format!("Tracker timed out after {}s", TRACKER_TIMEOUT_SECS))
+        })?
+        .map_err(|e| format!("Task join error: {}", e))??;
+    // The process exited on its own, so whatever it wrote is complete.
+    backup.disarm();
+    if !status.success() {
+        return Err(AppError::Claude(format!("Claude error: {}", stderr.trim())));
+    }
+
+    match parse_json_array_from_output::<Vec<String>>(&stdout) {
+        Some(words) => review_used_words(lang_dir, &words)
+            .map_err(|e| format!("Failed to schedule reviews: {}", e))?,
+        None => warn!("[Tracker] No word list in tracker output"),
+    }
+
+    let state = json!({ "last_success": Local::now().to_rfc3339() });
+    if let Err(e) = write_json_atomic(&tracker_dir.join(TRACKER_STATE_FILE), &state) {
+        error!("[Tracker] Failed to record tracker state: {}", e);
+    }
+    Ok(())
+}
+
+/// Applies an SM-2 review to each pre-existing word the tracker saw the
+/// learner use correctly.
+fn review_used_words(lang_dir: &Path, words: &[String]) -> Result<(), String> {
+    if words.is_empty() {
+        return Ok(());
+    }
+
+    let lock = language_lock(lang_dir);
+    let _guard = lock.lock().unwrap_or_else(|e| e.into_inner());
+
+    let mut vocab = read_vocabulary(lang_dir)?;
+    let mut reviewed = BTreeSet::new();
+    for word in words {
+        if !reviewed.insert(word.as_str()) {
+            continue;
+        }
+        match find_word_mut(&mut vocab, word) {
+            Some(entry) => {
+                sm2::review_entry(entry, sm2::QUALITY_USED_CORRECTLY)?;
+            }
+            None => warn!("[Tracker] Reported word '{}' not in vocabulary", word),
+        }
+    }
+
+    write_json_atomic(&lang_dir.join("vocabulary.json"), &vocab)
+}
+
+/// Working directory for one-off agent prompts. Keeping them out of the language
+/// dir stops them from becoming the session that `--continue` resumes.
+const ONESHOT_DIR: &str = ".oneshot";
+
+/// Timeout for one-off agent prompts such as word suggestions.
+const ONESHOT_TIMEOUT_SECS: u64 = 60;
+
+/// Runs a single stateless prompt and returns the agent's stdout.
+async fn run_oneshot_agent(lang_dir: &Path, prompt: String) -> Result<String, AppError> {
+    run_oneshot_agent_with_timeout(lang_dir, prompt, ONESHOT_TIMEOUT_SECS).await
+}
+
+async fn run_oneshot_agent_with_timeout(
+    lang_dir: &Path,
+    prompt: String,
+    timeout_secs: u64,
+) -> Result<String, AppError> {
+    let oneshot_dir = lang_dir.join(ONESHOT_DIR);
+    fs::create_dir_all(&oneshot_dir)
+        .map_err(|e| format!("Failed to create oneshot directory: {}", e))?;
+
+    let mut cmd = claude_command()?;
+    apply_model(&mut cmd, lang_dir);
+    apply_agent_args(&mut cmd, lang_dir);
+    cmd.arg("-p")
+        .arg(&prompt)
+        .current_dir(&oneshot_dir)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+
+    let mut child = cmd
+        .spawn()
+        .map_err(|e| AppError::ClaudeUnavailable(format!("Failed to run claude: {}", e)))?;
+    let stdout = child.stdout.take();
+    let stderr = child.stderr.take();
+    let child = Arc::new(Mutex::new(child));
+    // Kills claude on timeout, or if the caller drops this future.
+    let _kill_guard = KillOnDrop(Arc::clone(&child));
+    let task = {
+        let child = Arc::clone(&child);
+        tokio::task::spawn_blocking(move || {
+            let (Some(stdout), Some(stderr)) = (stdout, stderr) else {
+                return Err("Output was not captured".to_string());
+            };
+            collect_child_output(stdout, stderr, &child)
+        })
+    };
+
+    let (status, stdout, stderr) = tokio::time::timeout(Duration::from_secs(timeout_secs), task)
+        .await
+        .map_err(|_| AppError::Timeout(format!("Timed out after {}s", timeout_secs)))?
+        .map_err(|e| format!("Task join error: {}", e))??;
+
+    if status.success() {
+        Ok(stdout.trim().to_string())
+    } else {
+        Err(AppError::Claude(format!("Claude error: {}", stderr.trim())))
+    }
+}
+
+/// Extracts the outermost JSON value delimited by `open`/`close` from
+/// free-form model output.
+fn parse_json_from_output<T: serde::de::DeserializeOwned>(
+    output: &str,
+    open: char,
+    close: char,
+) -> Option<T> {
+    let start = output.find(open)?;
+    let end = output.rfind(close)?;
+    if end < start {
+        return None;
+    }
+    serde_json::from_str(&output[start..=end]).ok()
+}
+
+/// Extracts the first JSON array from free-form model output.
+fn parse_json_array_from_output<T: serde::de::DeserializeOwned>(output: &str) -> Option<T> {
+    parse_json_from_output(output, '[', ']')
+}
+
+/// Extracts the first JSON object from free-form model output.
+fn parse_json_object_from_output<T: serde::de::DeserializeOwned>(output: &str) -> Option<T> {
+    parse_json_from_output(output, '{', '}')
+}
+
+struct ResponderOptions {
+    /// Appended to the tutor's system prompt for this turn only.
+    system_prompt: String,
+    /// Resume the latest session; when false the responder starts a new one.
+    continue_session: bool,
+    /// Receives the reply's `RESPONDER_CHUNK_EVENT`s and `RESPONDER_DONE_EVENT`.
+    events: EventSink,
+}
+
+/// Emitted for each piece of reply text as the responder produces it.
+const RESPONDER_CHUNK_EVENT: &str = "chat://chunk";
+/// Emitted once with the full reply after the responder exits successfully.
+const RESPONDER_DONE_EVENT: &str = "chat://done";
+/// Emitted instead of `RESPONDER_DONE_EVENT` when `cancel_message` stops a
+/// request, with empty text.
+const RESPONDER_CANCELLED_EVENT: &str = "chat://cancelled";
+
+#[derive(Serialize, Clone)]
+struct ResponderEvent {
+    language: String,
+    text: String,
+}
+
+/// Runs the responder, retrying transient claude failures. A run that already
+/// streamed part of its reply isn't retried: the message is in the session by
+/// then, and resuming it again would send it twice.
+#[tracing::instrument(skip_all, err, fields(lang_dir = %lang_dir.display()))]
+async fn run_responder_agent(
+    lang_dir: &Path,
+    message: &str,
+    options: ResponderOptions,
+) -> Result<String, AppError> {
+    let streamed = Arc::new(AtomicBool::new(false));
+    with_claude_retries(
+        "Responder",
+        || run_responder_process(lang_dir, message, &options, &streamed),
+        |e| is_transient_claude_error(e) && !streamed.load(Ordering::Relaxed),
+    )
+    .await
+}
+
+/// One responder run: spawns claude and streams its reply. Sets `streamed`
+/// once the first chunk has been emitted.
+async fn run_responder_process(
+    lang_dir: &Path,
+    message: &str,
+    options: &ResponderOptions,
+    streamed: &Arc<AtomicBool>,
+) -> Result<String, AppError> {
+    let continue_session = options.continue_session && has_session(lang_dir);
+
+    let mut cmd = claude_command()?;
+    apply_model(&mut cmd, lang_dir);
+    apply_agent_args(&mut cmd, lang_dir);
+    if continue_session {
+        match active_session(lang_dir) {
+            Some(id) => {
+                cmd.arg("--resume").arg(id);
+            }
+            None => {
+                cmd.arg("--continue");
+            }
+        }
+    }
+    cmd.arg("--append-system-prompt")
+        .arg(&options.system_prompt)
+        .arg("--output-format")
+        .arg("stream-json")
+        .arg("--verbose")
+        .arg("--include-partial-messages")
+        .arg("-p")
+        .arg(message)
+        .current_dir(lang_dir)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+
+    let language = lang_dir
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let mut child = cmd
+        .spawn()
+        .map_err(|e| AppError::ClaudeUnavailable(format!("Failed to run claude: {}", e)))?;
+    let stdout = child
+        .stdout
+        .take()
+        .ok_or("Responder stdout was not captured")?;
+    let stderr = child.stderr.take();
+    let child = Arc::new(Mutex::new(child));
+    // Kills claude if this future is dropped by `cancel_message`.
+    let _kill_guard = KillOnDrop(Arc::clone(&child));
+
+    let events = Arc::clone(&options.events);
+    let task = {
+        let child = Arc::clone(&child);
+        let streamed = Arc::clone(streamed);
+        tokio::task::spawn_blocking(move || {
+            let (reply, session_id) =
+                stream_responder_output(stdout, stderr, &child, &events, &language, &streamed)?;
+            emit_responder_event(&events, RESPONDER_DONE_EVENT, &language, &reply);
+            Ok::<_, AppError>((reply, session_id))
+        })
+    };
+
+    let timeout_secs = responder_timeout_secs();
+    match tokio::time::timeout(Duration::from_secs(timeout_secs), task).await {
+        Ok(joined) => {
+            let (reply, session_id) =
+                joined.map_err(|e| AppError::Io(format!("Task join error: {}", e)))??;
+            match session_id.filter(|id| is_valid_session_id(id)) {
+                Some(id) => {
+                    if let Err(e) = set_active_session(lang_dir, Some(&id)) {
+                        warn!("[Responder] Failed to record session: {}", e);
+                    }
+                }
+                None => warn!("[Responder] Reply carried no session id"),
+            }
+            Ok(reply)
+        }
+        Err(_) => {
+            // Killing the child closes stdout, which lets the reader task finish.
+            let mut child = child.lock().unwrap_or_else(|e| e.into_inner());
+            if let Err(e) = child.kill() {
+                error!("[Responder] Failed to kill timed out claude: {}", e);
+            }
+            Err(AppError::Timeout(format!(
+                "Responder timed out after {}s",
+                timeout_secs
+            )))
+        }
+    }
+}
+
+/// Reads the responder's `stream-json` output line by line, emitting each text
+/// delta as it arrives (and setting `emitted`), and returns the final reply and
+/// the session it was given in once the process exits.
+fn stream_responder_output(
+    stdout: ChildStdout,
+    stderr: Option<ChildStderr>,
+    child: &Mutex<Child>,
+    events: &EventSink,
+    language: &str,
+    emitted: &AtomicBool,
+) -> Result<(String, Option<String>), AppError> {
+    // Drain stderr on its own thread so a chatty CLI can't block on a full pipe.
+    let stderr_reader = std::thread::spawn(move || {
+        let mut buf = String::new();
+        if let Some(mut stderr) = stderr {
+            let _ = stderr.read_to_string(&mut buf);
+        }
+        buf
+    });
+
+    let mut streamed = String::new();
+    let mut result = None;
+    let mut session_id = None;
+    for line in BufReader::new(stdout).lines() {
+        let line = line.map_err(|e| format!("Failed to read responder output: {}", e))?;
+        let Ok(event) = serde_json::from_str::<Value>(&line) else {
+            continue;
+        };
+        if session_id.is_none() {
+            session_id = event
+                .get("session_id")
+                .and_then(|id| id.as_str())
+                .map(str::to_string);
+        }
+        match event.get("type").and_then(|t| t.as_str()) {
+            Some("stream_event") => {
+                if let Some(text) = stream_text_delta(&event) {
+                    streamed.push_str(text);
+                    emitted.store(true, Ordering::Relaxed);
+                    emit_responder_event(events, RESPONDER_CHUNK_EVENT, language, text);
+                }
+            }
+            Some("result") => {
+                result = event
+                    .get("result")
+                    .and_then(|r| r.as_str())
+                    .map(str::to_string);
+            }
+            _ => {}
+        }
+    }
+
+    // Poll rather than block in `wait` so the timeout path can take the lock to kill.
+    let status = loop {
+        let exited = child
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .try_wait()
+            .map_err(|e| format!("Failed to wait for claude: {}", e))?;
+        match exited {
+            Some(status) => break status,
+            None => std::thread::sleep(Duration::from_millis(50)),
+        }
+    };
+    let stderr = stderr_reader.join().unwrap_or_default();
+    if !status.success() {
+        return Err(AppError::Claude(format!("Claude error: {}", stderr.trim())));
+    }
+
+    Ok((result.unwrap_or(streamed).trim().to_string(), session_id))
+}
+
+/// Text of a `content_block_delta` stream event, if it carries any.
+fn stream_text_delta(event: &Value) -> Option<&str> {
+    let inner = event.get("event")?;
+    if inner.get("type")?.as_str()? != "content_block_delta" {
+        return None;
+    }
+    let delta = inner.get("delta")?;
+    if delta.get("type")?.as_str()? != "text_delta" {
+        return None;
+    }
+    delta.get("text")?.as_str()
+}
+
+fn emit_responder_event(events: &EventSink, event: &str, language: &str, text: &str) {
+    let payload = ResponderEvent {
+        language: language.to_string(),
+        text: text.to_string(),
+    };
+    events(event, json!(payload));
+}
+
+/// Upper bound on how many words `suggest_next_words` may ask for at once.
+const MAX_WORD_SUGGESTIONS: usize = 20;
+
+const SUGGEST_WORDS_PROMPT: &str = r#"You are planning a {{LANGUAGE_NAME}} curriculum.
+
+The learner already knows these words:
+{{KNOWN_WORDS}}
+
+Suggest the {{COUNT}} most useful {{LANGUAGE_NAME}} words for them to learn next, building on what they know.
+Do not repeat known words. Reply with ONLY a JSON array of strings in {{LANGUAGE_NAME}}, e.g. ["word1", "word2"]."#;
+
+/// Asks the agent for the next most useful words to introduce, filtered
+/// against the existing vocabulary.
+#[tauri::command]
+async fn suggest_next_words(language: String, count: usize) -> Result<Vec<String>, AppError> {
+    if count == 0 || count > MAX_WORD_SUGGESTIONS {
+        return Err(AppError::InvalidInput(format!(
+            "Count must be between 1 and {}",
+            MAX_WORD_SUGGESTIONS
+        )));
+    }
+
+    let lang_dir = get_language_dir(&language)?;
+    let vocab = read_merged_vocabulary(&lang_dir)?;
+    let known: Vec<&str> = vocabulary_words(&vocab)
+        .iter()
+        .filter_map(|w| w.get("word")?.as_str())
+        .collect();
+
+    let known_list = if known.is_empty() {
+        "(none yet)".to_string()
+    } else {
+        known.join(", ")
+    };
+    let prompt = SUGGEST_WORDS_PROMPT
+        .replace("{{LANGUAGE_NAME}}", &language)
+        .replace("{{KNOWN_WORDS}}", &known_list)
+        .replace("{{COUNT}}", &count.to_string());
+
+    let output = run_oneshot_agent(&lang_dir, prompt).await?;
+    let suggestions: Vec<String> = parse_json_array_from_output(&output).ok_or_else(|| {
+        AppError::Claude("Could not parse word suggestions from the agent's reply".to_string())
+    })?;
+
+    let mut seen: BTreeSet<String> = known.iter().map(|w| w.to_string()).collect();
+    Ok(suggestions
+        .into_iter()
+        .map(|w| w.trim().to_string())
+        .filter(|w| !w.is_empty() && seen.insert(w.clone()))
+        .take(count)
+        .collect())
+}
+
+/// Default number of recent chat messages `summarize_session` recaps.
+const SUMMARY_MESSAGES: usize = 40;
+
+const SESSION_SUMMARY_PROMPT: &str = r#"Here is the end of a {{LANGUAGE_NAME}} study session between a learner and their tutor:
+
+{{TRANSCRIPT}}
+
+Write a short recap for the learner in markdown with two sections:
+- "New words": the {{LANGUAGE_NAME}} words introduced or practiced, each with a brief meaning.
+- "Grammar": the grammar points practiced, one line each.
+
+Keep it under 150 words. Reply with ONLY the markdown."#;
+
+/// Recaps the latest turns of a language's chat as markdown. Runs as a
+/// one-off prompt so the tutor's `--continue` session is untouched, and
+/// leaves the tracking files alone.
+#[tauri::command]
+async fn summarize_session(
+    cache: State<'_, ChatHistoryCache>,
+    language: String,
+    max_messages: Option<usize>,
+) -> Result<String, AppError> {
+    let limit = max_messages.unwrap_or(SUMMARY_MESSAGES).max(1);
+    let messages = read_chat_history_page(&cache, &language, 0, limit)?.messages;
+    if messages.is_empty() {
+        return Err(AppError::NotFound(format!(
+            "No chat history to summarize for '{}'",
+            language
+        )));
+    }
+
+    let transcript = messages
+        .iter()
+        .map(|m| {
+            let speaker = if m.role == "user" { "Learner" } else { "Tutor" };
+            format!("{}: {}", speaker, m.content)
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    let prompt = SESSION_SUMMARY_PROMPT
+        .replace("{{LANGUAGE_NAME}}", &language)
+        .replace("{{TRANSCRIPT}}", &transcript);
+
+    let lang_dir = get_language_dir(&language)?;
+    run_oneshot_agent_with_timeout(&lang_dir, prompt, responder_timeout_secs()).await
+}
+
+const ROMANIZATION_PROMPT: &str = r#"Give the {{ROMANIZATION}} romanization of each of these {{LANGUAGE_NAME}} words:
+{{WORDS}}
+
+Reply with ONLY a JSON object mapping each word exactly as given to its romanization, e.g. {"word1": "romanization1"}."#;
+
+fn has_romanization(word: &Value) -> bool {
+    word.get("romanization")
+        .and_then(|v| v.as_str())
+        .is_some_and(|r| !r.trim().is_empty())
+}
+
+/// Fills in `romanization` for words that lack it, using a one-off agent
+/// prompt. Words that already have one are left alone. Returns how many
+/// entries were updated.
+#[tauri::command]
+async fn regenerate_romanization(language: String) -> Result<usize, AppError> {
+    let lang_dir = get_language_dir(&language)?;
+    let config = read_config(&lang_dir)?;
+    let scheme = match config.romanization.as_str() {
+        "none" => "standard",
+        other => other,
+    };
+
+    let vocab = read_vocabulary(&lang_dir)?;
+    let missing: Vec<String> = vocabulary_words(&vocab)
+        .iter()
+        .filter(|w| !has_romanization(w))
+        .filter_map(|w| Some(w.get("word")?.as_str()?.to_string()))
+        .collect();
+    if missing.is_empty() {
+        return Ok(0);
+    }
+
+    let prompt = ROMANIZATION_PROMPT
+        .replace("{{ROMANIZATION}}", scheme)
+        .replace("{{LANGUAGE_NAME}}", &config.language)
+        .replace("{{WORDS}}", &missing.join("\n"));
+    let output = run_oneshot_agent(&lang_dir, prompt).await?;
+    let mapping: HashMap<String, String> =
+        parse_json_object_from_output(&output).ok_or_else(|| {
+            AppError::Claude("Could not parse romanizations from the agent's reply".to_string())
+        })?;
+
+    let tracker = tracker_lock(&language_key(&lang_dir));
+    let _tracker_guard = tracker.lock().await;
+    let lock = language_lock(&lang_dir);
+    let _guard = lock.lock().unwrap_or_else(|e| e.into_inner());
+
+    // Re-read: the file may have changed while the agent was running
+    let mut vocab = read_vocabulary(&lang_dir)?;
+    let mut updated = 0;
+    for word in &missing {
+        let Some(romanization) = mapping
+            .get(word)
+            .map(|r| r.trim())
+            .filter(|r| !r.is_empty())
+        else {
+            continue;
+        };
+        if let Some(entry) = find_word_mut(&mut vocab, word).filter(|e| !has_romanization(e)) {
+            entry["romanization"] = json!(romanization);
+            updated += 1;
+        }
+    }
+
+    if updated > 0 {
+        write_json_atomic(&lang_dir.join("vocabulary.json"), &vocab)?;
+    }
+    Ok(updated)
+}
+
+/// Maximum custom greeting length in characters.
+const MAX_GREETING_LENGTH: usize = 100;
+
+/// Returns the first-message greeting: the learner's custom greeting if set,
+/// otherwise the built-in one for the language.
+#[tauri::command]
+fn get_cold_start_greeting(language: String) -> Result<String, AppError> {
+    let config = read_config(&get_language_dir(&language)?)?;
+    Ok(config
+        .greeting
+        .unwrap_or_else(|| get_language_info(&config.language).greeting))
+}
+
+#[tauri::command]
+fn set_greeting(language: String, greeting: String) -> Result<(), AppError> {
+    let greeting = greeting.trim();
+    if greeting.is_empty() {
+        return Err(AppError::InvalidInput(
+            "Greeting cannot be empty".to_string(),
+        ));
+    }
+    let length = greeting.chars().count();
+    if length > MAX_GREETING_LENGTH {
+        return Err(AppError::InvalidInput(format!(
+            "Greeting too long ({} chars). Maximum is {} chars.",
+            length, MAX_GREETING_LENGTH
+        )));
+    }
+
+    let lang_dir = get_language_dir(&language)?;
+    let lock = language_lock(&lang_dir);
+    let _guard = lock.lock().unwrap_or_else(|e| e.into_inner());
+
+    let mut config = read_config(&lang_dir)?;
+    config.greeting = Some(greeting.to_string());
+    let config_json = serde_json::to_string_pretty(&config)
+        .map_err(|e| format!("Failed to serialize config: {}", e))?;
+    Ok(write_language_file(&lang_dir, "config.json", &config_json)?)
+}
+
+/// Maximum message length in characters.
+/// Prevents excessively long inputs that could slow down or overwhelm Claude.
+const MAX_MESSAGE_LENGTH: usize = 10000;
+
+/// Sends a learner message to the tutor and returns the reply. The tracker
+/// runs alongside; with `sync_tracking` the call also waits for it and reports
+/// what it changed, so the vocabulary is up to date on return.
+#[tauri::command]
+#[tracing::instrument(skip(app, agent, cache, message), err)]
+async fn send_message(
+    app: AppHandle,
+    agent: State<'_, SharedAgent>,
+    cache: State<'_, ChatHistoryCache>,
+    message: String,
+    language: String,
+    wait: Option<bool>,
+    sync_tracking: Option<bool>,
+) -> Result<SyncedReply, AppError> {
+    send_message_with(
+        app_events(&app),
+        agent.inner().clone(),
+        cache.inner(),
+        message,
+        language,
+        wait.unwrap_or(true),
+        sync_tracking.unwrap_or(false),
+    )
+    .await
+}
+
+async fn send_message_with(
+    events: EventSink,
+    agent: SharedAgent,
+    cache: &ChatHistoryCache,
+    message: String,
+    language: String,
+    wait: bool,
+    sync_tracking: bool,
+) -> Result<SyncedReply, AppError> {
+    let _slot = acquire_send_slot(&language, wait).await?;
+    let (lang_dir, options) = prepare_message(&message, &language, cache, &events)?;
+    let sent_at = Local::now().to_rfc3339();
+    let request = InFlightRequest::register(&language);
+
+    let mut tracker = spawn_tracker_agent(
+        events,
+        agent.clone(),
+        lang_dir.clone(),
+        message.clone(),
+        &request,
+    );
+    let reply = respond_unless_cancelled(
+        agent.as_ref(),
+        &request,
+        &lang_dir,
+        &message,
+        options,
+        Some(&mut tracker),
+    )
+    .await?;
+    record_exchange(&lang_dir, &message, sent_at, &reply);
+    clear_new_conversation_flag(&lang_dir);
+
+    let mut synced = SyncedReply {
+        reply,
+        tracking: None,
+        tracking_error: None,
+    };
+    if sync_tracking {
+        match tracker.await {
+            Ok(Ok(changes)) => synced.tracking = Some(changes),
+            Ok(Err(e)) => synced.tracking_error = Some(e),
+            Err(e) => {
+                synced.tracking_error =
+                    Some(AppError::Io(format!("Tracker task join error: {}", e)))
+            }
+        }
+    }
+    Ok(synced)
+}
+
+/// Gets the tutor's reply unless `cancel_message` stops the request first. On
+/// cancel, waits for `tracker` (which stops on the same signal) to roll back
+/// its edits, emits `RESPONDER_CANCELLED_EVENT` and returns a `Cancelled` error.
+async fn respond_unless_cancelled(
+    agent: &dyn Agent,
+    request: &InFlightRequest,
+    lang_dir: &Path,
+    message: &str,
+    options: ResponderOptions,
+    tracker: Option<&mut TrackerTask>,
+) -> Result<String, AppError> {
+    let events = Arc::clone(&options.events);
+    tokio::select! {
+        reply = agent.respond(lang_dir, message, options) => reply,
+        _ = request.cancelled() => {
+            if let Some(tracker) = tracker {
+                let _ = tracker.await;
+            }
+            emit_responder_event(&events, RESPONDER_CANCELLED_EVENT, &language_key(lang_dir), "");
+            Err(request.error())
+        }
+    }
+}
+
+/// Stops the in-flight `send_message` for a language: its claude process is
+/// killed, a still-running tracker is stopped and its file edits rolled back,
+/// and the call returns a `cancelled` error after emitting
+/// `RESPONDER_CANCELLED_EVENT`. A call that already has its reply and is only
+/// waiting for the tracker returns the reply with a `cancelled`
+/// `tracking_error`. Returns false if nothing was running.
+#[tauri::command]
+fn cancel_message(language: String) -> Result<bool, AppError> {
+    let cancelled = match in_flight().get(&language_dir_name(&language)) {
+        Some(sender) => sender.send(true).is_ok(),
+        None => false,
+    };
+    if cancelled {
+        info!("[Responder] Cancelled request for {}", language);
+    }
+    Ok(cancelled)
+}
+
+/// Session messages searched, from the end, for the learner's last message.
+const REGENERATE_LOOKBACK_MESSAGES: usize = 20;
+
+/// Appended to the system prompt when a reply is regenerated.
+const REGENERATE_NOTE: &str =
+    "\nThe learner asked for a new reply to their last message. Answer it again differently.";
+
+/// The learner's most recent message in the current session.
+fn last_user_message(lang_dir: &Path) -> Option<String> {
+    let path = current_session_file(lang_dir)?;
+    tail_chat_messages(&path, REGENERATE_LOOKBACK_MESSAGES)
+        .ok()?
+        .into_iter()
+        .rev()
+        .find(|m| m.message.role == "user")
+        .map(|m| m.message.content)
+}
+
+/// Asks the tutor again for a reply to the learner's last message, after an
+/// error or an unhelpful answer. The tracker isn't run again, since the
+/// message was already tracked, and the new reply replaces the old one in the
+/// chat log. Cancellable with `cancel_message` like `send_message`.
+#[tauri::command]
+#[tracing::instrument(skip(app, agent, cache), err)]
+async fn regenerate_response(
+    app: AppHandle,
+    agent: State<'_, SharedAgent>,
+    cache: State<'_, ChatHistoryCache>,
+    language: String,
+    wait: Option<bool>,
+) -> Result<String, AppError> {
+    let _slot = acquire_send_slot(&language, wait.unwrap_or(true)).await?;
+    let message = last_user_message(&get_language_dir(&language)?).ok_or_else(|| {
+        AppError::NotFound(format!(
+            "No message to regenerate a reply to for {}",
+            language
+        ))
+    })?;
+    let (lang_dir, mut options) = prepare_message(&message, &language, &cache, &app_events(&app))?;
+    options.system_prompt.push_str(REGENERATE_NOTE);
+
+    let request = InFlightRequest::register(&language);
+    let reply = respond_unless_cancelled(
+        agent.inner().as_ref(),
+        &request,
+        &lang_dir,
+        &message,
+        options,
+        None,
+    )
+    .await?;
+    record_regenerated_reply(&lang_dir, &message, &reply);
+    clear_new_conversation_flag(&lang_dir);
+    Ok(reply)
+}
+
+/// Earlier messages replayed to the tutor when a conversation is branched.
+const EDIT_CONTEXT_MESSAGES: usize = 20;
+
+/// Introduces the replayed conversation in the system prompt of a branch.
+const EDIT_CONTEXT_NOTE: &str = "\nThis conversation continues from an earlier one. \
+The learner corrected their last message; the conversation so far was:\n";
+
+/// Starts a new branch of the conversation from the learner message at
+/// `message_index` (an index into the chat history), sending `new_text` in
+/// its place. The tutor gets a fresh session primed with the messages before
+/// it; that message and everything after it are dropped from the chat log and
+/// replaced by the new exchange. The tracker runs on `new_text` as it does
+/// for `send_message`.
+#[tauri::command]
+#[tracing::instrument(skip(app, agent, cache, new_text), err)]
+async fn edit_and_resend(
+    app: AppHandle,
+    agent: State<'_, SharedAgent>,
+    cache: State<'_, ChatHistoryCache>,
+    language: String,
+    message_index: usize,
+    new_text: String,
+    wait: Option<bool>,
+) -> Result<String, AppError> {
+    let _slot = acquire_send_slot(&language, wait.unwrap_or(true)).await?;
+    let events = app_events(&app);
+    let (lang_dir, mut options) = prepare_message(&new_text, &language, &cache, &events)?;
+    let sent_at = Local::now().to_rfc3339();
+
+    let history = load_chat_messages(&lang_dir, &cache)?.messages;
+    if history.get(message_index).is_none_or(|m| m.role != "user") {
+        return Err(AppError::InvalidInput(format!(
+            "Message {} is not a learner message",
+            message_index
+        )));
+    }
+    let context_start = message_index.saturating_sub(EDIT_CONTEXT_MESSAGES);
+    if message_index > 0 {
+        options.system_prompt.push_str(EDIT_CONTEXT_NOTE);
+        for msg in &history[context_start..message_index] {
+            let speaker = if msg.role == "user" {
+                "Learner"
+            } else {
+                "Tutor"
+            };
+            options
+                .system_prompt
+                .push_str(&format!("{}: {}\n", speaker, msg.content));
+        }
+    }
+    options.continue_session = false;
+
+    let request = InFlightRequest::register(&language);
+    let mut tracker = spawn_tracker_agent(
+        events,
+        agent.inner().clone(),
+        lang_dir.clone(),
+        new_text.clone(),
+        &request,
+    );
+    let reply = respond_unless_cancelled(
+        agent.inner().as_ref(),
+        &request,
+        &lang_dir,
+        &new_text,
+        options,
+        Some(&mut tracker),
+    )
+    .await?;
+    if let Err(e) = ChatStore::open(&lang_dir).and_then(|mut store| store.truncate(message_index)) {
+        error!("[Chat store] Failed to drop replaced messages: {}", e);
+    }
+    record_exchange(&lang_dir, &new_text, sent_at, &reply);
+    clear_new_conversation_flag(&lang_dir);
+    Ok(reply)
+}
+
+/// Tells the responder whether to write romanization, per the learner's preference.
+fn romanization_note(show_romanization: bool) -> &'static str {
+    if show_romanization {
+        "\nRomanization: on. Give romanization (in parentheses) after target-language words."
+    } else {
+        "\nRomanization: off. Do not include any romanization; write only the native script."
+    }
+}
+
+/// Appended to the system prompt for the learner's very first message.
+const FIRST_MESSAGE_NOTE: &str =
+    "\nThis is the learner's very first message. Open with the cold start greeting.";
+
+/// Validates a learner message and builds the responder options for it.
+fn prepare_message(
+    message: &str,
+    language: &str,
+    cache: &ChatHistoryCache,
+    events: &EventSink,
+) -> Result<(PathBuf, ResponderOptions), AppError> {
+    if message.trim().is_empty() {
+        return Err(AppError::InvalidInput(
+            "Message cannot be empty".to_string(),
+        ));
+    }
+    if message.len() > MAX_MESSAGE_LENGTH {
+        return Err(AppError::InvalidInput(format!(
+            "Message too long ({} chars). Maximum is {} chars.",
+            message.len(),
+            MAX_MESSAGE_LENGTH
+        )));
+    }
+
+    let lang_dir = get_language_dir(language)?;
+
+    if !lang_dir.exists() {
+        return Err(AppError::NotFound(format!(
+            "Language '{}' not set up. Please bootstrap it first.",
+            language
+        )));
+    }
+
+    // Checking the log also imports existing history before this exchange
+    // lands in the session files, so the import can't log it twice.
+    let first_session = is_first_session_at(&lang_dir, cache).unwrap_or_else(|e| {
+        warn!("[send_message] Failed to check for a first session: {}", e);
+        false
+    });
+
+    let mode = read_learning_mode(&lang_dir)?;
+    let mut system_prompt = mode.instructions().to_string();
+    if first_session {
+        system_prompt.push_str(FIRST_MESSAGE_NOTE);
+    }
+    // Missing or malformed overrides fall back to showing romanization.
+    system_prompt.push_str(romanization_note(
+        read_user_preferences(&lang_dir).show_romanization,
+    ));
+    if let Some(greeting) = read_config(&lang_dir).ok().and_then(|c| c.greeting) {
+        system_prompt.push_str(&format!(
+            "\nFor the cold start greeting, use the learner's chosen greeting: {}",
+            greeting
+        ));
+    }
+
+    let continue_session = !first_session && should_continue_session(&lang_dir);
+
+    Ok((
+        lang_dir,
+        ResponderOptions {
+            system_prompt,
+            continue_session,
+            events: Arc::clone(events),
+        },
+    ))
+}
+
+/// Makes the next message start a new Claude session instead of resuming the
+/// current one; later messages then continue that new session. Vocabulary,
+/// grammar and chat history are kept.
+#[tauri::command]
+fn start_new_session(language: String) -> Result<(), AppError> {
+    let lang_dir = get_language_dir(&language)?;
+    if !lang_dir.exists() {
+        return Err(AppError::NotFound(format!(
+            "Language '{}' does not exist",
+            language
+        )));
+    }
+    let lock = language_lock(&lang_dir);
+    let _guard = lock.lock().unwrap_or_else(|e| e.into_inner());
+
+    set_active_session(&lang_dir, None)?;
+    Ok(write_language_file(&lang_dir, NEW_CONVERSATION_FLAG, "")?)
+}
+
+/// Whether the next message will resume the existing conversation (true) or
+/// start a new one (false).
+#[tauri::command]
+fn will_continue_session(language: String) -> Result<bool, AppError> {
+    Ok(should_continue_session(&get_language_dir(&language)?))
+}
+
+/// Whether the learner has yet to send a first message for this language.
+#[tauri::command]
+fn is_first_session(
+    cache: State<'_, ChatHistoryCache>,
+    language: String,
+) -> Result<bool, AppError> {
+    let lang_dir = get_language_dir(&language)?;
+    if !lang_dir.exists() {
+        return Err(AppError::NotFound(format!(
+            "Language '{}' does not exist",
+            language
+        )));
+    }
+    Ok(is_first_session_at(&lang_dir, &cache)?)
+}
+
+#[tauri::command]
+fn is_session_stale(language: String) -> Result<bool, AppError> {
+    Ok(is_session_stale_at(&get_language_dir(&language)?)?)
+}
+
+#[tauri::command]
+fn get_mode(language: String) -> Result<LearningMode, AppError> {
+    Ok(read_learning_mode(&get_language_dir(&language)?)?)
+}
+
+#[tauri::command]
+fn set_mode(language: String, mode: LearningMode) -> Result<(), AppError> {
+    let lang_dir = get_language_dir(&language)?;
+    let lock = language_lock(&lang_dir);
+    let _guard = lock.lock().unwrap_or_else(|e| e.into_inner());
+
+    let mut overrides = read_user_overrides(&lang_dir)?;
+    overrides.mode = mode;
+    Ok(write_user_overrides(&lang_dir, &overrides)?)
+}
+
+#[tauri::command]
+fn set_show_romanization(language: String, show: bool) -> Result<(), AppError> {
+    let lang_dir = get_language_dir(&language)?;
+    let lock = language_lock(&lang_dir);
+    let _guard = lock.lock().unwrap_or_else(|e| e.into_inner());
+
+    let mut overrides = read_user_overrides(&lang_dir)?;
+    overrides.preferences.show_romanization = show;
+    Ok(write_user_overrides(&lang_dir, &overrides)?)
+}
+
+/// Selects the model the responder and tracker use for this language.
+/// Pass `None` or an empty string to go back to the CLI default.
+#[tauri::command]
+fn set_model(language: String, model: Option<String>) -> Result<(), AppError> {
+    let model = match model.as_deref().map(str::trim) {
+        None | Some("") => None,
+        Some(model) => Some(validate_model(model)?),
+    };
+
+    let lang_dir = get_language_dir(&language)?;
+    let lock = language_lock(&lang_dir);
+    let _guard = lock.lock().unwrap_or_else(|e| e.into_inner());
+
+    let mut overrides = read_user_overrides(&lang_dir)?;
+    overrides.model = model;
+    Ok(write_user_overrides(&lang_dir, &overrides)?)
+}
+
+/// Sets the extra Claude CLI arguments and whether permissions are skipped,
+/// for one language or, when `language` is None, for every language that
+/// doesn't set its own. Passing `None` for `args` or `skip_permissions` on a
+/// language clears it back to the app-wide setting.
+#[tauri::command]
+fn set_agent_args(
+    language: Option<String>,
+    args: Option<Vec<String>>,
+    skip_permissions: Option<bool>,
+) -> Result<(), AppError> {
+    if let Some(args) = &args {
+        validate_agent_args(args)?;
+    }
+
+    let Some(language) = language else {
+        let mut settings = read_settings()?;
+        settings.agent_args = args.unwrap_or_default();
+        settings.skip_permissions = skip_permissions;
+        return Ok(write_settings(&settings)?);
+    };
+
+    let lang_dir = get_language_dir(&language)?;
+    let lock = language_lock(&lang_dir);
+    let _guard = lock.lock().unwrap_or_else(|e| e.into_inner());
+
+    let mut overrides = read_user_overrides(&lang_dir)?;
+    overrides.agent_args = args;
+    overrides.skip_permissions = skip_permissions;
+    Ok(write_user_overrides(&lang_dir, &overrides)?)
+}
+
+#[tauri::command]
+fn get_user_preferences(language: String) -> Result<UserOverrides, AppError> {
+    Ok(read_user_overrides(&get_language_dir(&language)?)?)
+}
+
+/// Returns a language's config.json.
+#[tauri::command]
+fn get_config(language: String) -> Result<LanguageConfig, AppError> {
+    let lang_dir = get_language_dir(&language)?;
+    if !lang_dir.exists() {
+        return Err(AppError::NotFound(format!(
+            "Language '{}' does not exist",
+            language
+        )));
+    }
+    if !lang_dir.join("config.json").exists() {
+        return Err(AppError::NotFound(format!(
+            "config.json is missing for '{}'",
+            language
+        )));
+    }
+    Ok(read_config(&lang_dir)?)
+}
+
+/// Everything the chat header needs: config.json plus the learner's mode and
+/// romanization setting.
+#[derive(Serialize)]
+struct LanguageSettings {
+    #[serde(flatten)]
+    config: LanguageConfig,
+    mode: LearningMode,
+    show_romanization: bool,
+}
+
+#[tauri::command]
+fn get_language_settings(language: String) -> Result<LanguageSettings, AppError> {
+    let config = get_config(language.clone())?;
+    let lang_dir = get_language_dir(&language)?;
+    Ok(LanguageSettings {
+        config,
+        mode: read_learning_mode(&lang_dir)?,
+        show_romanization: read_user_preferences(&lang_dir).show_romanization,
+    })
+}
+
+/// Merges the given fields into user-overrides.json and returns the result.
+#[tauri::command]
+fn set_user_preferences(
+    language: String,
+    preferences: UserPreferencesUpdate,
+) -> Result<UserOverrides, AppError> {
+    if let Some(n) = preferences.new_vocab_per_exchange {
+        if n > MAX_NEW_VOCAB_PER_EXCHANGE {
+            return Err(AppError::InvalidInput(format!(
+                "new_vocab_per_exchange must be between 0 and {}",
+                MAX_NEW_VOCAB_PER_EXCHANGE
+            )));
+        }
+    }
+    let lang_dir = get_language_dir(&language)?;
+    let lock = language_lock(&lang_dir);
+    let _guard = lock.lock().unwrap_or_else(|e| e.into_inner());
+
+    let mut overrides = read_user_overrides(&lang_dir)?;
+    if let Some(n) = preferences.new_vocab_per_exchange {
+        overrides.preferences.new_vocab_per_exchange = n;
+    }
+    if let Some(show) = preferences.show_romanization {
+        overrides.preferences.show_romanization = show;
+    }
+    if let Some(mode) = preferences.mode {
+        overrides.mode = mode;
+    }
+    if let Some(notes) = preferences.notes {
+        overrides.notes = notes;
+    }
+
+    write_user_overrides(&lang_dir, &overrides)?;
+    Ok(overrides)
+}
+
+/// Returns vocabulary.json after checking it against the schema, waiting for
+/// any running tracker first.
+#[tauri::command]
+async fn get_vocabulary(language: String) -> Result<Vocabulary, AppError> {
+    let lang_dir = get_language_dir(&language)?;
+    let lock = tracker_lock(&language_key(&lang_dir));
+    let _guard = lock.lock().await;
+    read_validated_vocabulary(&lang_dir)
+}
+
+/// Finds the entry whose `field` equals `key`, ignoring case, and parses it.
+/// Returns its index too, for error reporting.
+fn find_entry_case_insensitive<'a>(
+    entries: &'a [Value],
+    field: &str,
+    key: &str,
+) -> Option<(usize, &'a Value)> {
+    let key = key.trim().to_lowercase();
+    entries.iter().enumerate().find(|(_, entry)| {
+        entry
+            .get(field)
+            .and_then(|v| v.as_str())
+            .is_some_and(|v| v.to_lowercase() == key)
+    })
+}
+
+/// Returns one vocabulary entry with its scheduling fields, matching `word`
+/// case-insensitively, or None if it isn't in the vocabulary.
+#[tauri::command]
+async fn get_vocab_word(
+    language: String,
+    word: String,
+) -> Result<Option<sm2::VocabItem>, AppError> {
+    let lang_dir = get_language_dir(&language)?;
+    let lock = tracker_lock(&language_key(&lang_dir));
+    let _guard = lock.lock().await;
+
+    let vocab = read_vocabulary(&lang_dir)?;
+    find_entry_case_insensitive(vocabulary_words(&vocab), "word", &word)
+        .map(|(index, entry)| {
+            serde_json::from_value(entry.clone()).map_err(|e| AppError::InvalidData {
+                message: format!("Invalid vocabulary entry for '{}': {}", word, e),
+                entry: Some(index),
+            })
+        })
+        .transpose()
+}
+
+/// Returns the learner's vocabulary merged with the shared deck, in the same
+/// shape as vocabulary.json.
+#[tauri::command]
+fn get_merged_vocabulary(language: String) -> Result<Value, AppError> {
+    Ok(read_merged_vocabulary(&get_language_dir(&language)?)?)
+}
+
+/// Reports the size of vocabulary.json and whether it's large enough to slow
+/// the tracker down.
+#[tauri::command]
+fn check_vocabulary_size(language: String) -> Result<Value, AppError> {
+    let lang_dir = get_language_dir(&language)?;
+    let bytes = fs::metadata(lang_dir.join("vocabulary.json"))
+        .map_err(|e| format!("Failed to read vocabulary: {}", e))?
+        .len();
+    let vocab = read_vocabulary(&lang_dir)?;
+    let words = vocabulary_words(&vocab);
+    let archivable = words.iter().filter(|w| is_word_archivable(w)).count();
+
+    let warning = bytes > VOCABULARY_WARNING_BYTES || words.len() > VOCABULARY_WARNING_ENTRIES;
+    let suggestion = (warning && archivable > 0).then(|| {
+        format!(
+            "Archive {} mature words to keep the tracker fast",
+            archivable
+        )
+    });
+
+    Ok(json!({
+        "entries": words.len(),
+        "bytes": bytes,
+        "warning": warning,
+        "archivable": archivable,
+        "suggestion": suggestion,
+    }))
+}
+
+/// Moves words with an interval of at least `ARCHIVE_INTERVAL_DAYS` from
+/// vocabulary.json into mature-vocabulary.json. Returns how many were moved.
+#[tauri::command]
+async fn archive_mature_words(language: String) -> Result<usize, AppError> {
+    let lang_dir = get_language_dir(&language)?;
+    let tracker = tracker_lock(&language_key(&lang_dir));
+    let _tracker_guard = tracker.lock().await;
+    let lock = language_lock(&lang_dir);
+    let _guard = lock.lock().unwrap_or_else(|e| e.into_inner());
+
+    let mut vocab = read_vocabulary(&lang_dir)?;
+    let Some(words) = vocab.get_mut("words").and_then(|w| w.as_array_mut()) else {
+        return Ok(0);
+    };
+    let (mature, active): (Vec<Value>, Vec<Value>) = words.drain(..).partition(is_word_archivable);
+    *words = active;
+
+    let moved = mature.len();
+    if moved == 0 {
+        return Ok(0);
+    }
+
+    let mut archive = read_mature_vocabulary(&lang_dir)?;
+    archive["language"] = vocab.get("language").cloned().unwrap_or(Value::Null);
+    match archive.get_mut("words").and_then(|w| w.as_array_mut()) {
+        Some(archived) => archived.extend(mature),
+        None => archive["words"] = Value::Array(mature),
+    }
+
+    // Write the archive first so a failure can't lose words
+    write_json_atomic(&lang_dir.join(MATURE_VOCABULARY_FILE), &archive)?;
+    write_json_atomic(&lang_dir.join("vocabulary.json"), &vocab)?;
+
+    Ok(moved)
+}
+
+/// Renders the vocabulary as a self-contained, printable HTML page of
+/// fold-over flashcards (word on the front, meaning on the back).
+#[tauri::command]
+fn export_flashcards_html(language: String) -> Result<String, AppError> {
+    let lang_dir = get_language_dir(&language)?;
+    let vocab = read_merged_vocabulary(&lang_dir)?;
+    let show_romanization = read_user_preferences(&lang_dir).show_romanization;
+
+    let cards: Vec<String> = vocabulary_words(&vocab)
+        .iter()
+        .filter_map(|w| render_flashcard(w, show_romanization))
+        .collect();
+
+    Ok(FLASHCARDS_TEMPLATE
+        .replace("{{LANGUAGE_NAME}}", &escape_html(&language))
+        .replace("{{CARDS}}", &cards.join("\n")))
+}
+
+/// Writes a conversation transcript to `dest_path`, which the frontend gets
+/// from a save dialog: one session's messages when `session_id` is set,
+/// otherwise the whole chat history. A directory destination gets
+/// `<language>-transcript.<ext>` inside it, and a missing extension is added.
+/// Returns the file's path.
+#[tauri::command]
+fn export_transcript(
+    cache: State<'_, ChatHistoryCache>,
+    language: String,
+    session_id: Option<String>,
+    format: TranscriptFormat,
+    dest_path: String,
+) -> Result<String, AppError> {
+    let lang_dir = get_language_dir(&language)?;
+    if !lang_dir.exists() {
+        return Err(AppError::NotFound(format!(
+            "Language '{}' does not exist",
+            language
+        )));
+    }
+    let display_name = read_config(&lang_dir)
+        .map(|c| c.language)
+        .unwrap_or_else(|_| title_case(&language));
+
+    let (title, messages) = match &session_id {
+        Some(id) => {
+            let path = session_file_path(&lang_dir, id)?;
+            let messages = cache
+                .messages(&path)?
+                .messages
+                .iter()
+                .map(|m| m.message.clone())
+                .collect();
+            (format!("{} conversation ({})", display_name, id), messages)
+        }
+        None => (
+            format!("{} conversation", display_name),
+            read_chat_history_page(&cache, &language, 0, usize::MAX)?.messages,
+        ),
+    };
+
+    let mut dest = expand_tilde(dest_path.trim());
+    if dest.is_dir() {
+        dest = dest.join(format!(
+            "{}-transcript.{}",
+            language_key(&lang_dir),
+            format.extension()
+        ));
+    } else if dest.extension().is_none() {
+        dest.set_extension(format.extension());
+    }
+
+    let transcript = render_transcript(&title, &messages, format);
+    write_atomic(&dest, transcript.as_bytes())
+        .map_err(|e| format!("Failed to write transcript: {}", e))?;
+    Ok(dest.to_string_lossy().into_owned())
+}
+
+/// Bundles a language directory into a zip archive at `dest_path`. A directory
+/// destination gets `<language>.zip` inside it. Returns the archive's path.
+#[tauri::command]
+fn export_language(language: String, dest_path: String) -> Result<String, AppError> {
+    let lang_dir = get_language_dir(&language)?;
+    if !lang_dir.exists() {
+        return Err(AppError::NotFound(format!(
+            "Language '{}' does not exist",
+            language
+        )));
+    }
+
+    let mut dest = expand_tilde(dest_path.trim());
+    if dest.is_dir() {
+        dest = dest.join(format!("{}.zip", language_key(&lang_dir)));
+    } else if dest.extension().is_none() {
+        dest.set_extension("zip");
+    }
+
+    let lock = language_lock(&lang_dir);
+    let _guard = lock.lock().unwrap_or_else(|e| e.into_inner());
+
+    // Build beside the destination and rename, so a failed export leaves no partial zip
+    let tmp_path = dest.with_extension("zip.tmp");
+    let file = File::create(&tmp_path).map_err(|e| format!("Failed to create archive: {}", e))?;
+    let mut zip = zip::ZipWriter::new(file);
+    let options = zip::write::SimpleFileOptions::default()
+        .compression_method(zip::CompressionMethod::Deflated);
+
+    let result = add_dir_to_zip(&mut zip, &lang_dir, &lang_dir, options).and_then(|()| {
+        zip.finish()
+            .map(drop)
+            .map_err(|e| format!("Failed to finish archive: {}", e))
+    });
+    if let Err(e) = result {
+        let _ = fs::remove_file(&tmp_path);
+        return Err(e.into());
+    }
+
+    fs::rename(&tmp_path, &dest).map_err(|e| format!("Failed to write archive: {}", e))?;
+    Ok(dest.to_string_lossy().into_owned())
+}
+
+/// Restores a language from an `export_language` archive. The language name is
+/// taken from the archive's config.json. Returns the imported language name.
+#[tauri::command]
+async fn import_language(zip_path: String, overwrite: bool) -> Result<String, AppError> {
+    let file = File::open(expand_tilde(zip_path.trim()))
+        .map_err(|e| format!("Failed to open archive: {}", e))?;
+    let mut archive =
+        zip::ZipArchive::new(file).map_err(|e| format!("Failed to read archive: {}", e))?;
+
+    // Vet every entry before anything is written
+    for i in 0..archive.len() {
+        let entry = archive
+            .by_index_raw(i)
+            .map_err(|e| format!("Failed to read archive entry: {}", e))?;
+        if is_unsafe_archive_path(entry.name()) || entry.enclosed_name().is_none() {
+            return Err(AppError::InvalidInput(format!(
+                "Archive entry '{}' has an unsafe path",
+                entry.name()
+            )));
+        }
+    }
+
+    let config: LanguageConfig = {
+        let entry = archive
+            .by_name("config.json")
+            .map_err(|_| AppError::InvalidInput("Archive has no config.json".to_string()))?;
+        serde_json::from_reader(entry).map_err(|e| format!("Failed to parse config: {}", e))?
+    };
+    let language = config.language;
+    let lang_dir = get_language_dir(&language)?;
+
+    let tracker = tracker_lock(&language_key(&lang_dir));
+    let _tracker_guard = tracker.lock().await;
+    let lock = language_lock(&lang_dir);
+    let _guard = lock.lock().unwrap_or_else(|e| e.into_inner());
+
+    if lang_dir.exists() && !overwrite {
+        return Err(AppError::AlreadyExists(format!(
+            "Language '{}' already exists",
+            language
+        )));
+    }
+
+    // Extract into a hidden staging dir so a bad archive never touches the live language
+    let data_dir = ensure_data_dir_writable()?;
+    let dir_name = language_dir_name(&language);
+    let staging_dir = data_dir.join(format!(".import-{}", dir_name));
+    if staging_dir.exists() {
+        fs::remove_dir_all(&staging_dir)
+            .map_err(|e| format!("Failed to clear staging directory: {}", e))?;
+    }
+    fs::create_dir_all(&staging_dir)
+        .map_err(|e| format!("Failed to create staging directory: {}", e))?;
+
+    if let Err(e) = extract_zip(&mut archive, &staging_dir) {
+        let _ = fs::remove_dir_all(&staging_dir);
+        return Err(e.into());
+    }
+
+    // Set the old copy aside instead of deleting it, so a failed install can
+    // put it back
+    let replaced_dir = data_dir.join(format!(".replaced-{}", dir_name));
+    let replacing = lang_dir.exists();
+    if replacing {
+        let set_aside = match fs::remove_dir_all(&replaced_dir) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => fs::rename(&lang_dir, &replaced_dir),
+        };
+        if let Err(e) = set_aside {
+            let _ = fs::remove_dir_all(&staging_dir);
+            return Err(format!("Failed to replace existing language: {}", e).into());
+        }
+    }
+
+    if let Err(e) = fs::rename(&staging_dir, &lang_dir) {
+        if replacing {
+            if let Err(e) = fs::rename(&replaced_dir, &lang_dir) {
+                error!("[Import] Failed to put {} back: {}", lang_dir.display(), e);
+            }
+        }
+        let _ = fs::remove_dir_all(&staging_dir);
+        return Err(format!("Failed to install imported language: {}", e).into());
+    }
+    if replacing {
+        if let Err(e) = fs::remove_dir_all(&replaced_dir) {
+            warn!("[Import] Failed to remove the replaced copy: {}", e);
+        }
+    }
+
+    Ok(language)
+}
+
+/// Appends `note` to the word's `notes` field, starting a new line if it
+/// already has notes.
+#[tauri::command]
+async fn add_word_note(language: String, word: String, note: String) -> Result<(), AppError> {
+    let note = note.trim();
+    if note.is_empty() {
+        return Err(AppError::InvalidInput("Note cannot be empty".to_string()));
+    }
+
+    let lang_dir = get_language_dir(&language)?;
+    let tracker = tracker_lock(&language_key(&lang_dir));
+    let _tracker_guard = tracker.lock().await;
+    let lock = language_lock(&lang_dir);
+    let _guard = lock.lock().unwrap_or_else(|e| e.into_inner());
+
+    let mut vocab = read_vocabulary(&lang_dir)?;
+    let entry = find_word_mut(&mut vocab, &word)
+        .ok_or_else(|| AppError::NotFound(format!("Word '{}' not found", word)))?;
+
+    let notes = match entry.get("notes").and_then(|v| v.as_str()) {
+        Some(existing) if !existing.trim().is_empty() => format!("{}\n{}", existing, note),
+        _ => note.to_string(),
+    };
+    entry["notes"] = json!(notes);
+
+    Ok(write_json_atomic(
+        &lang_dir.join("vocabulary.json"),
+        &vocab,
+    )?)
+}
+
+/// Adds a word to vocabulary.json, or updates it if an entry with the same
+/// `word` exists. `metadata` (meaning, romanization, notes, ...) is merged into
+/// the entry: existing fields keep their position, new ones are appended.
+/// New words start with the same SM-2 defaults the tracker uses. Returns the
+/// stored entry.
+#[tauri::command]
+async fn add_vocab_word(
+    language: String,
+    word: String,
+    metadata: serde_json::Map<String, Value>,
+) -> Result<Value, AppError> {
+    let word = word.trim();
+    if word.is_empty() {
+        return Err(AppError::InvalidInput("Word cannot be empty".to_string()));
+    }
+    if metadata.contains_key("word") {
+        return Err(AppError::InvalidInput(
+            "Pass the word itself as `word`, not in metadata".to_string(),
+        ));
+    }
+
+    let lang_dir = get_language_dir(&language)?;
+    let tracker = tracker_lock(&language_key(&lang_dir));
+    let _tracker_guard = tracker.lock().await;
+    let lock = language_lock(&lang_dir);
+    let _guard = lock.lock().unwrap_or_else(|e| e.into_inner());
+
+    let mut vocab = read_vocabulary(&lang_dir)?;
+    let entry = match find_word_mut(&mut vocab, word) {
+        Some(entry) => {
+            let fields = entry
+                .as_object_mut()
+                .ok_or_else(|| format!("Vocabulary entry for '{}' is not an object", word))?;
+            fields.extend(metadata);
+            entry.clone()
+        }
+        None => {
+            let today = Local::now().date_naive();
+            let mut item = sm2::VocabItem {
+                word: word.to_string(),
+                ease: sm2::DEFAULT_EASE,
+                interval: 1,
+                repetitions: 1,
+                next_review: Some((today + Days::new(1)).format(DATE_FORMAT).to_string()),
+                extra: serde_json::Map::new(),
+            };
+            item.extra.insert(
+                "added".to_string(),
+                json!(today.format(DATE_FORMAT).to_string()),
+            );
+            let mut entry = serde_json::to_value(&item)
+                .map_err(|e| format!("Failed to serialize entry: {}", e))?;
+            if let Some(fields) = entry.as_object_mut() {
+                fields.extend(metadata);
+            }
+            vocab
+                .get_mut("words")
+                .and_then(|w| w.as_array_mut())
+                .ok_or("vocabulary.json has no words array")?
+                .push(entry.clone());
+            entry
+        }
+    };
+
+    // Reject metadata that breaks the scheduling fields before it hits disk.
+    serde_json::from_value::<sm2::VocabItem>(entry.clone())
+        .map_err(|e| AppError::InvalidInput(format!("Invalid vocabulary entry: {}", e)))?;
+
+    write_json_atomic(&lang_dir.join("vocabulary.json"), &vocab)?;
+    Ok(entry)
+}
+
+/// Removes a word from vocabulary.json.
+#[tauri::command]
+async fn remove_vocab_word(language: String, word: String) -> Result<(), AppError> {
+    let lang_dir = get_language_dir(&language)?;
+    let tracker = tracker_lock(&language_key(&lang_dir));
+    let _tracker_guard = tracker.lock().await;
+    let lock = language_lock(&lang_dir);
+    let _guard = lock.lock().unwrap_or_else(|e| e.into_inner());
+
+    let mut vocab = read_vocabulary(&lang_dir)?;
+    let words = vocab
+        .get_mut("words")
+        .and_then(|w| w.as_array_mut())
+        .ok_or("vocabulary.json has no words array")?;
+    let before = words.len();
+    words.retain(|w| w.get("word").and_then(|v| v.as_str()) != Some(word.as_str()));
+    if words.len() == before {
+        return Err(AppError::NotFound(format!("Word '{}' not found", word)));
+    }
+
+    Ok(write_json_atomic(
+        &lang_dir.join("vocabulary.json"),
+        &vocab,
+    )?)
+}
+
+/// Returns grammar.json after checking it against the schema, waiting for any
+/// running tracker first.
+#[tauri::command]
+async fn get_grammar(language: String) -> Result<Grammar, AppError> {
+    let lang_dir = get_language_dir(&language)?;
+    let lock = tracker_lock(&language_key(&lang_dir));
+    let _guard = lock.lock().await;
+    read_validated_grammar(&lang_dir)
+}
+
+/// Returns one grammar rule, matching `rule` case-insensitively, or None if
+/// there is no such rule.
+#[tauri::command]
+async fn get_grammar_rule(language: String, rule: String) -> Result<Option<GrammarRule>, AppError> {
+    let lang_dir = get_language_dir(&language)?;
+    let lock = tracker_lock(&language_key(&lang_dir));
+    let _guard = lock.lock().await;
+
+    let grammar = read_grammar(&lang_dir)?;
+    find_entry_case_insensitive(grammar_rules(&grammar), "rule", &rule)
+        .map(|(index, entry)| {
+            serde_json::from_value(entry.clone()).map_err(|e| AppError::InvalidData {
+                message: format!("Invalid grammar rule '{}': {}", rule, e),
+                entry: Some(index),
+            })
+        })
+        .transpose()
+}
+
+/// Adds a grammar rule, or updates `stars` and `correct_streak` of the rule
+/// with the same `rule` text. `stars` is clamped to 1-5. Other fields of an
+/// existing rule are left as they are. Returns the stored rule.
+#[tauri::command]
+async fn upsert_grammar_rule(
+    language: String,
+    rule: String,
+    stars: u8,
+    correct_streak: u32,
+) -> Result<GrammarRule, AppError> {
+    let rule = rule.trim();
+    if rule.is_empty() {
+        return Err(AppError::InvalidInput("Rule cannot be empty".to_string()));
+    }
+    let stars = stars.clamp(MIN_GRAMMAR_STARS, MAX_GRAMMAR_STARS);
+
+    let lang_dir = get_language_dir(&language)?;
+    let tracker = tracker_lock(&language_key(&lang_dir));
+    let _tracker_guard = tracker.lock().await;
+    let lock = language_lock(&lang_dir);
+    let _guard = lock.lock().unwrap_or_else(|e| e.into_inner());
+
+    let mut grammar = read_grammar(&lang_dir)?;
+    let rules = rules_mut(&mut grammar)?;
+    let existing = rules
+        .iter_mut()
+        .find(|r| r.get("rule").and_then(|v| v.as_str()) == Some(rule));
+    let entry = match existing {
+        Some(entry) => {
+            // Assign in place so the entry keeps its field order.
+            entry["stars"] = json!(stars);
+            entry["correct_streak"] = json!(correct_streak);
+            entry.clone()
+        }
+        None => {
+            let new_rule = GrammarRule {
+                rule: rule.to_string(),
+                stars: stars.into(),
+                correct_streak: correct_streak.into(),
+                extra: serde_json::Map::new(),
+            };
+            let entry = serde_json::to_value(&new_rule)
+                .map_err(|e| format!("Failed to serialize rule: {}", e))?;
+            rules.push(entry.clone());
+            entry
+        }
+    };
+    let stored: GrammarRule = serde_json::from_value(entry)
+        .map_err(|e| format!("Invalid grammar rule '{}': {}", rule, e))?;
+
+    write_json_atomic(&lang_dir.join("grammar.json"), &grammar)?;
+    Ok(stored)
+}
+
+/// Removes a grammar rule by its `rule` text.
+#[tauri::command]
+async fn remove_grammar_rule(language: String, rule: String) -> Result<(), AppError> {
+    let lang_dir = get_language_dir(&language)?;
+    let tracker = tracker_lock(&language_key(&lang_dir));
+    let _tracker_guard = tracker.lock().await;
+    let lock = language_lock(&lang_dir);
+    let _guard = lock.lock().unwrap_or_else(|e| e.into_inner());
+
+    let mut grammar = read_grammar(&lang_dir)?;
+    let rules = rules_mut(&mut grammar)?;
+    let before = rules.len();
+    rules.retain(|r| r.get("rule").and_then(|v| v.as_str()) != Some(rule.as_str()));
+    if rules.len() == before {
+        return Err(AppError::NotFound(format!("Rule '{}' not found", rule)));
+    }
+
+    Ok(write_json_atomic(&lang_dir.join("grammar.json"), &grammar)?)
+}
+
+/// Groups grammar rules by their `category` field and returns the count per
+/// category, largest first. Rules without a category count as "uncategorized".
+#[tauri::command]
+fn get_grammar_categories(language: String) -> Result<Vec<Value>, AppError> {
+    let grammar = read_grammar(&get_language_dir(&language)?)?;
+
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for rule in grammar_rules(&grammar) {
+        *counts.entry(rule_category(rule)).or_default() += 1;
+    }
+
+    let mut categories: Vec<_> = counts.into_iter().collect();
+    categories.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+
+    Ok(categories
+        .into_iter()
+        .map(|(category, count)| json!({ "category": category, "count": count }))
+        .collect())
+}
+
+/// Returns vocabulary entries whose `added` date falls within `start..=end`.
+/// Entries without an `added` date are excluded.
+#[tauri::command]
+fn get_words_added_between(
+    language: String,
+    start: String,
+    end: String,
+) -> Result<Vec<Value>, AppError> {
+    let start = parse_date(&start).map_err(AppError::InvalidInput)?;
+    let end = parse_date(&end).map_err(AppError::InvalidInput)?;
+    if start > end {
+        return Err(AppError::InvalidInput(
+            "Start date must not be after end date".to_string(),
+        ));
+    }
+
+    let vocab = read_vocabulary(&get_language_dir(&language)?)?;
+
+    Ok(vocabulary_words(&vocab)
+        .iter()
+        .filter(|w| word_added_date(w).is_some_and(|d| d >= start && d <= end))
+        .cloned()
+        .collect())
+}
+
+#[derive(Serialize)]
+struct WeeklyCount {
+    /// Monday of the ISO week, as YYYY-MM-DD.
+    week_start: String,
+    count: usize,
+}
+
+/// Monday of the ISO week containing `date`.
+fn iso_week_start(date: NaiveDate) -> NaiveDate {
+    date - Days::new(u64::from(date.weekday().num_days_from_monday()))
+}
+
+/// Words learned per ISO week, oldest first, with empty weeks in between
+/// included as zeros. A word counts in the week of its `added` date; words
+/// from before dates were stamped count in the week the language was started.
+/// Archived mature words are included.
+#[tauri::command]
+fn get_vocab_growth(language: String) -> Result<Vec<WeeklyCount>, AppError> {
+    let lang_dir = get_language_dir(&language)?;
+    let vocab = read_vocabulary(&lang_dir)?;
+    let mature = read_mature_vocabulary(&lang_dir)?;
+    let started = read_config(&lang_dir)
+        .and_then(|c| parse_date(&c.started))
+        .ok();
+
+    let dates = vocabulary_words(&vocab)
+        .iter()
+        .chain(vocabulary_words(&mature))
+        .filter_map(|word| word_added_date(word).or(started));
+    Ok(weekly_counts(dates))
+}
+
+/// Counts `dates` per ISO week, oldest week first, with a zero for every
+/// empty week between the first and the last.
+fn weekly_counts(dates: impl IntoIterator<Item = NaiveDate>) -> Vec<WeeklyCount> {
+    let mut weeks: BTreeMap<NaiveDate, usize> = BTreeMap::new();
+    for date in dates {
+        *weeks.entry(iso_week_start(date)).or_default() += 1;
+    }
+
+    let (Some(&first), Some(&last)) = (weeks.keys().next(), weeks.keys().next_back()) else {
+        return vec![];
+    };
+    let mut series = Vec::new();
+    let mut week = first;
+    while week <= last {
+        series.push(WeeklyCount {
+            week_start: week.format(DATE_FORMAT).to_string(),
+            count: weeks.get(&week).copied().unwrap_or(0),
+        });
+        week = week + Days::new(7);
+    }
+    series
+}
+
+/// Returns up to `limit` words due for review, most overdue first. Words that
+/// were never scheduled come first; suspended words are left out.
+#[tauri::command]
+fn get_due_words(language: String, limit: usize) -> Result<Vec<Value>, AppError> {
+    let lang_dir = get_language_dir(&language)?;
+    let vocab = read_merged_vocabulary(&lang_dir)?;
+    let today = Local::now().date_naive();
+
+    let mut due: Vec<&Value> = vocabulary_words(&vocab)
+        .iter()
+        .filter(|w| is_word_due(w, today))
+        .filter(|w| {
+            !w.get("suspended")
+                .and_then(|v| v.as_bool())
+                .unwrap_or(false)
+        })
+        .collect();
+    due.sort_by_key(|w| {
+        w.get("next_review")
+            .and_then(|v| v.as_str())
+            .and_then(|s| parse_date(s).ok())
+    });
+
+    Ok(due.into_iter().take(limit).cloned().collect())
+}
+
+/// Records a flashcard review with SM-2 quality 0-5 and persists the new schedule.
+#[tauri::command]
+async fn record_review(
+    language: String,
+    word: String,
+    quality: u8,
+) -> Result<ReviewResult, AppError> {
+    grade_vocabulary_word(language, word, quality).await
+}
+
+/// Returns words that were added more than `never_reviewed_after_days` ago but
+/// have not been reinforced since. Words without an `added` date are excluded.
+#[tauri::command]
+fn get_never_reviewed(language: String) -> Result<Vec<Value>, AppError> {
+    let lang_dir = get_language_dir(&language)?;
+    let vocab = read_vocabulary(&lang_dir)?;
+
+    let min_age = read_user_preferences(&lang_dir)
+        .never_reviewed_after_days
+        .unwrap_or(DEFAULT_NEVER_REVIEWED_DAYS);
+    let cutoff = Local::now().date_naive() - chrono::Days::new(min_age);
+
+    Ok(vocabulary_words(&vocab)
+        .iter()
+        .filter(|w| is_never_reviewed(w) && word_added_date(w).is_some_and(|d| d < cutoff))
+        .cloned()
+        .collect())
+}
+
+/// Grades a review of `word` using SM-2 (0 = blackout, 5 = perfect recall)
+/// and schedules its next review.
+#[tauri::command]
+async fn grade_vocabulary_word(
+    language: String,
+    word: String,
+    grade: u8,
+) -> Result<ReviewResult, AppError> {
+    if grade > 5 {
+        return Err(AppError::InvalidInput(format!(
+            "Invalid grade {}. Must be between 0 and 5",
+            grade
+        )));
+    }
+
+    let lang_dir = get_language_dir(&language)?;
+    let tracker = tracker_lock(&language_key(&lang_dir));
+    let _tracker_guard = tracker.lock().await;
+    let lock = language_lock(&lang_dir);
+    let _guard = lock.lock().unwrap_or_else(|e| e.into_inner());
+
+    let mut vocab = read_vocabulary(&lang_dir)?;
+    track_shared_word(&lang_dir, &mut vocab, &word)?;
+    let entry = find_word_mut(&mut vocab, &word)
+        .ok_or_else(|| AppError::NotFound(format!("Word '{}' not found", word)))?;
+
+    let item = sm2::review_entry(entry, grade)?;
+
+    write_json_atomic(&lang_dir.join("vocabulary.json"), &vocab)?;
+
+    Ok(ReviewResult {
+        interval: item.interval,
+        next_review: item.next_review.unwrap_or_default(),
+    })
+}
+
+#[tauri::command]
+fn get_language_stats(language: String) -> Result<LanguageDashboard, AppError> {
+    let lang_dir = get_language_dir(&language)?;
+    if !lang_dir.exists() {
+        return Err(AppError::NotFound(format!(
+            "Language '{}' does not exist",
+            language
+        )));
+    }
+    Ok(compute_language_dashboard(&lang_dir))
+}
+
+#[tauri::command]
+fn list_languages(include_archived: Option<bool>) -> Result<Vec<String>, AppError> {
+    collect_languages(include_archived.unwrap_or(false))
+}
+
+fn collect_languages(include_archived: bool) -> Result<Vec<String>, AppError> {
+    let data_dir = get_data_dir()?;
+
+    if !data_dir.exists() {
+        return Ok(vec![]);
+    }
+
+    let mut languages = Vec::new();
+    let entries =
+        fs::read_dir(&data_dir).map_err(|e| format!("Failed to read data directory: {}", e))?;
+
+    for entry_result in entries {
+        let entry = match entry_result {
+            Ok(e) => e,
+            Err(e) => {
+                warn!("[list_languages] Error reading directory entry: {}", e);
+                continue;
+            }
+        };
+        if entry.path().is_dir() {
+            if let Some(name) = entry.file_name().to_str() {
+                // Hidden dirs are scratch space (e.g. import staging), not languages
+                if !name.starts_with('.') {
+                    let config = read_config(&entry.path()).ok();
+                    if !include_archived && config.as_ref().is_some_and(|c| c.archived) {
+                        continue;
+                    }
+                    // Folders predating config.json fall back to the folder name
+                    let display = config
+                        .map(|c| normalize_language_name(&c.language))
+                        .filter(|n| !n.is_empty())
+                        .unwrap_or_else(|| title_case(name));
+                    languages.push(display);
+                }
+            }
+        }
+    }
+
+    Ok(languages)
+}
+
+/// Hides a language from `list_languages` without touching its data; it can
+/// still be opened by name.
+#[tauri::command]
+fn archive_language(language: String) -> Result<(), AppError> {
+    set_language_archived(&language, true)
+}
+
+#[tauri::command]
+fn unarchive_language(language: String) -> Result<(), AppError> {
+    set_language_archived(&language, false)
+}
+
+fn set_language_archived(language: &str, archived: bool) -> Result<(), AppError> {
+    let lang_dir = get_language_dir(language)?;
+    if !lang_dir.exists() {
+        return Err(AppError::NotFound(format!(
+            "Language '{}' does not exist",
+            language
+        )));
+    }
+
+    let lock = language_lock(&lang_dir);
+    let _guard = lock.lock().unwrap_or_else(|e| e.into_inner());
+
+    let mut config = read_config(&lang_dir)?;
+    config.archived = archived;
+    Ok(write_json_atomic(&lang_dir.join("config.json"), &config)?)
+}
+
+/// Returns every language with its due-card count. Languages whose vocabulary
+/// can't be read are reported with an `error` field instead of failing the call.
+#[tauri::command]
+fn get_languages_with_due() -> Result<Vec<Value>, AppError> {
+    let today = Local::now().date_naive();
+
+    Ok(collect_languages(false)?
+        .into_iter()
+        .map(|language| {
+            let vocab = get_language_dir(&language)
+                .map_err(String::from)
+                .and_then(|dir| read_merged_vocabulary(&dir));
+            match vocab {
+                Ok(vocab) => json!({
+                    "language": language,
+                    "due": count_due_words(&vocab, today),
+                }),
+                Err(e) => json!({
+                    "language": language,
+                    "error": e,
+                }),
+            }
+        })
+        .collect())
+}
+
+/// One home-screen row. Counts are left out if the vocabulary can't be read,
+/// with the reason in `error`.
+#[derive(Serialize)]
+struct LanguageOverview {
+    language: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    total_words: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    due_today: Option<usize>,
+    /// Date of the newest chat session, or the start date if there is none.
+    last_active: Option<String>,
+    archived: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+fn language_overview(language: String, today: NaiveDate) -> LanguageOverview {
+    let mut overview = LanguageOverview {
+        language,
+        total_words: None,
+        due_today: None,
+        last_active: None,
+        archived: false,
+        error: None,
+    };
+    let lang_dir = match get_language_dir(&overview.language) {
+        Ok(dir) => dir,
+        Err(e) => {
+            overview.error = Some(e.to_string());
+            return overview;
+        }
+    };
+
+    match read_merged_vocabulary(&lang_dir) {
+        Ok(vocab) => {
+            overview.total_words = Some(vocabulary_words(&vocab).len());
+            overview.due_today = Some(count_due_words(&vocab, today));
+        }
+        Err(e) => {
+            warn!("[Overview] {}: {}", lang_dir.display(), e);
+            overview.error = Some(e);
+        }
+    }
+
+    let newest_session = get_claude_project_dir(&lang_dir)
+        .ok()
+        .and_then(|dir| list_jsonl_files(&dir).pop())
+        .map(|(_, modified)| {
+            DateTime::<Local>::from(modified)
+                .format(DATE_FORMAT)
+                .to_string()
+        });
+    let config = read_config(&lang_dir).ok();
+    overview.archived = config.as_ref().is_some_and(|c| c.archived);
+    overview.last_active = newest_session.or_else(|| config.map(|c| c.started));
+    overview
+}
+
+/// Returns every language with its word count, due-today count and last
+/// activity date, for the home screen.
+#[tauri::command]
+fn get_languages_overview(
+    include_archived: Option<bool>,
+) -> Result<Vec<LanguageOverview>, AppError> {
+    let today = Local::now().date_naive();
+    Ok(list_languages(include_archived)?
+        .into_iter()
+        .map(|language| language_overview(language, today))
+        .collect())
+}
+
+/// Returns every language with its statistics, ranked by a composite progress
+/// score. Languages that can't be read are listed last with an `error` field.
+#[tauri::command]
+fn get_all_languages_summary() -> Result<Vec<Value>, AppError> {
+    let mut ranked = Vec::new();
+    let mut failed = Vec::new();
+
+    for language in collect_languages(false)? {
+        let stats = get_language_dir(&language)
+            .map_err(String::from)
+            .and_then(|dir| compute_language_stats(&dir));
+        match stats {
+            Ok(stats) => ranked.push((language, stats)),
+            Err(e) => failed.push(json!({ "language": language, "error": e })),
+        }
+    }
+
+    ranked.sort_by_key(|(_, stats)| Reverse(stats.score()));
+
+    Ok(ranked
+        .into_iter()
+        .map(|(language, stats)| {
+            json!({
+                "language": language,
+                "score": stats.score(),
+                "stats": stats,
+            })
+        })
+        .chain(failed)
+        .collect())
+}
+
+/// Reports how far tracking lags behind the conversation: learner messages
+/// sent after the last successful tracker run count as pending.
+#[tauri::command]
+fn get_tracking_lag(language: String) -> Result<Value, AppError> {
+    let lang_dir = get_language_dir(&language)?;
+    let last_success = read_last_tracker_success(&lang_dir);
+    let timestamps = collect_user_message_timestamps(&get_claude_project_dir(&lang_dir)?);
+
+    let pending: Vec<_> = timestamps
+        .iter()
+        .filter(|ts| last_success.is_none_or(|last| **ts > last))
+        .collect();
+    let lag_seconds = pending
+        .first()
+        .map(|oldest| (Local::now() - **oldest).num_seconds());
+
+    Ok(json!({
+        "last_tracked": last_success.map(|ts| ts.to_rfc3339()),
+        "latest_message": timestamps.last().map(|ts| ts.to_rfc3339()),
+        "pending_messages": pending.len(),
+        "lag_seconds": lag_seconds,
+    }))
+}
+
+/// Empties the tracker's working directory without touching the learner's
+/// data files. `state.json` is kept unless `full` is set.
+#[tauri::command]
+fn clean_tracker(language: String, full: bool) -> Result<(), AppError> {
+    let tracker_dir = get_language_dir(&language)?.join(TRACKER_DIR);
+
+    if tracker_dir.exists() {
+        let entries = fs::read_dir(&tracker_dir)
+            .map_err(|e| format!("Failed to read tracker directory: {}", e))?;
+
+        for entry in entries {
+            let entry = entry.map_err(|e| format!("Failed to read tracker entry: {}", e))?;
+            if !full && entry.file_name() == TRACKER_STATE_FILE {
+                continue;
+            }
+
+            let path = entry.path();
+            let removed = if path.is_dir() {
+                fs::remove_dir_all(&path)
+            } else {
+                fs::remove_file(&path)
+            };
+            removed.map_err(|e| format!("Failed to remove {}: {}", path.display(), e))?;
+        }
+    }
+
+    fs::create_dir_all(&tracker_dir)
+        .map_err(|e| format!("Failed to create tracker directory: {}", e))?;
+    Ok(())
+}
+
+/// Returns the resolved path of the Claude CLI the agents will run.
+#[tauri::command]
+fn get_claude_bin() -> Result<String, AppError> {
+    Ok(resolve_claude_bin()?.to_string_lossy().to_string())
+}
+
+/// Runs `claude --version` to tell whether the CLI is usable, killing it if it
+/// doesn't answer within `timeout_secs`. Never fails: problems are reported
+/// as `installed: false`.
+#[tauri::command]
+async fn check_claude(timeout_secs: u64) -> ClaudeStatus {
+    let bin = match resolve_claude_bin() {
+        Ok(bin) => bin,
+        Err(e) => {
+            warn!("[check_claude] {}", e);
+            return ClaudeStatus {
+                installed: false,
+                version: None,
+                path: expand_tilde(configured_claude_bin())
+                    .to_string_lossy()
+                    .to_string(),
+            };
+        }
+    };
+    let path = bin.to_string_lossy().to_string();
+    let not_installed = |path: String| ClaudeStatus {
+        installed: false,
+        version: None,
+        path,
+    };
+
+    let mut cmd = Command::new(&bin);
+    cmd.arg("--version")
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null());
+    hide_console_window(&mut cmd);
+    let mut child = match cmd.spawn() {
+        Ok(child) => child,
+        Err(e) => {
+            warn!("[check_claude] Failed to run {}: {}", path, e);
+            return not_installed(path);
+        }
+    };
+
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(timeout_secs.max(1));
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) if tokio::time::Instant::now() >= deadline => {
+                warn!("[check_claude] {} --version timed out", path);
+                let _ = child.kill();
+                let _ = child.wait();
+                return not_installed(path);
+            }
+            Ok(None) => tokio::time::sleep(Duration::from_millis(50)).await,
+            Err(e) => {
+                warn!("[check_claude] Failed to wait for {}: {}", path, e);
+                return not_installed(path);
+            }
+        }
+    };
+    if !status.success() {
+        warn!("[check_claude] {} --version exited with {}", path, status);
+        return not_installed(path);
+    }
+
+    let mut stdout = String::new();
+    if let Some(mut out) = child.stdout.take() {
+        let _ = out.read_to_string(&mut stdout);
+    }
+    ClaudeStatus {
+        installed: true,
+        version: parse_claude_version(&stdout),
+        path,
+    }
+}
+
+#[derive(Serialize)]
+struct DataDirStatus {
+    path: String,
+    writable: bool,
+    /// Why the directory isn't writable.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// Reports whether the data directory can be written, so the UI can warn
+/// before the learner tries to add a language.
+#[tauri::command]
+fn get_data_dir_status() -> Result<DataDirStatus, AppError> {
+    let path = get_data_dir()?.to_string_lossy().to_string();
+    Ok(match ensure_data_dir_writable() {
+        Ok(_) => DataDirStatus {
+            path,
+            writable: true,
+            error: None,
+        },
+        Err(e) => DataDirStatus {
+            path,
+            writable: false,
+            error: Some(e.to_string()),
+        },
+    })
+}
+
+/// Where language data is stored, for display in the UI.
+#[tauri::command]
+fn get_data_root() -> Result<String, AppError> {
+    Ok(get_data_dir()?.to_string_lossy().to_string())
+}
+
+/// Returns the folder holding the app's log files (one per day), so users can
+/// find them to attach to a bug report.
+#[tauri::command]
+fn get_log_path() -> Result<String, AppError> {
+    Ok(get_log_dir()?.to_string_lossy().to_string())
+}
+
+/// Lists the available Claude profiles: "default" plus every `~/.claude-<name>`
+/// directory.
+#[tauri::command]
+fn list_claude_profiles() -> Result<Vec<String>, AppError> {
+    let home = dirs::home_dir().ok_or("Failed to get home directory")?;
+    let entries =
+        fs::read_dir(&home).map_err(|e| format!("Failed to read home directory: {}", e))?;
+
+    let mut profiles: Vec<String> = entries
+        .filter_map(|e| e.ok())
+        .filter(|e| e.path().is_dir())
+        .filter_map(|e| {
+            let name = e
+                .file_name()
+                .to_str()?
+                .strip_prefix(CLAUDE_PROFILE_PREFIX)?
+                .to_string();
+            (!name.is_empty() && name != DEFAULT_CLAUDE_PROFILE).then_some(name)
+        })
+        .collect();
+    profiles.sort();
+    profiles.insert(0, DEFAULT_CLAUDE_PROFILE.to_string());
+
+    Ok(profiles)
+}
+
+#[tauri::command]
+fn get_claude_profile() -> Result<String, AppError> {
+    Ok(read_settings()?
+        .claude_profile
+        .unwrap_or_else(|| DEFAULT_CLAUDE_PROFILE.to_string()))
+}
+
+/// Selects the Claude profile used by the responder and tracker.
+/// Pass "default" to go back to the CLI's own configuration.
+#[tauri::command]
+fn set_claude_profile(name: String) -> Result<(), AppError> {
+    let profile = if name == DEFAULT_CLAUDE_PROFILE {
+        None
+    } else {
+        if !name
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+        {
+            return Err(AppError::InvalidName(
+                "Profile name can only contain letters, numbers, hyphens, and underscores"
+                    .to_string(),
+            ));
+        }
+        if !claude_profile_dir(&name)?.is_dir() {
+            return Err(AppError::NotFound(format!(
+                "Claude profile '{}' does not exist",
+                name
+            )));
+        }
+        Some(name)
+    };
+
+    let mut settings = read_settings()?;
+    settings.claude_profile = profile;
+    Ok(write_settings(&settings)?)
+}
+
+/// Re-renders CLAUDE.md from the current template and language table, keeping
+/// anything below the `<!-- USER NOTES -->` line. The old file is copied to
+/// `.backups` first. Returns the path of the file that was rewritten.
+#[tauri::command]
+async fn regenerate_tutor_instructions(language: String) -> Result<String, AppError> {
+    let lang_dir = get_language_dir(&language)?;
+    if !lang_dir.exists() {
+        return Err(AppError::NotFound(format!(
+            "Language '{}' does not exist",
+            language
+        )));
+    }
+
+    let tracker = tracker_lock(&language_key(&lang_dir));
+    let _tracker_guard = tracker.lock().await;
+    let lock = language_lock(&lang_dir);
+    let _guard = lock.lock().unwrap_or_else(|e| e.into_inner());
+
+    let name = read_config(&lang_dir)?.language;
+    backup_language_files(&lang_dir, &["CLAUDE.md"])?;
+    write_tutor_instructions(&lang_dir, &name, &get_language_info(&name))?;
+
+    Ok(lang_dir.join("CLAUDE.md").to_string_lossy().to_string())
+}
+
+/// Largest CLAUDE.md `set_tutor_instructions` accepts.
+const MAX_TUTOR_INSTRUCTIONS_BYTES: usize = 256 * 1024;
+
+#[tauri::command]
+fn get_tutor_instructions(language: String) -> Result<String, AppError> {
+    let lang_dir = get_language_dir(&language)?;
+    if !lang_dir.exists() {
+        return Err(AppError::NotFound(format!(
+            "Language '{}' does not exist",
+            language
+        )));
+    }
+    Ok(fs::read_to_string(lang_dir.join("CLAUDE.md"))
+        .map_err(|e| format!("Failed to read CLAUDE.md: {}", e))?)
+}
+
+/// Replaces a language's CLAUDE.md with hand-edited content, backing up the
+/// previous version first. Returns the backup folder. Waits for a running
+/// tracker so the file doesn't change under it.
+#[tauri::command]
+async fn set_tutor_instructions(language: String, content: String) -> Result<String, AppError> {
+    if content.trim().is_empty() {
+        return Err(AppError::InvalidInput(
+            "Tutor instructions cannot be empty".to_string(),
+        ));
+    }
+    if content.len() > MAX_TUTOR_INSTRUCTIONS_BYTES {
+        return Err(AppError::InvalidInput(format!(
+            "Tutor instructions too large ({} bytes). Maximum is {} bytes.",
+            content.len(),
+            MAX_TUTOR_INSTRUCTIONS_BYTES
+        )));
+    }
+
+    let lang_dir = get_language_dir(&language)?;
+    if !lang_dir.exists() {
+        return Err(AppError::NotFound(format!(
+            "Language '{}' does not exist",
+            language
+        )));
+    }
+
+    let tracker = tracker_lock(&language_key(&lang_dir));
+    let _tracker_guard = tracker.lock().await;
+    let lock = language_lock(&lang_dir);
+    let _guard = lock.lock().unwrap_or_else(|e| e.into_inner());
+
+    let backup_dir = backup_language_files(&lang_dir, &["CLAUDE.md"])?;
+    write_language_file(&lang_dir, "CLAUDE.md", &content)?;
+
+    Ok(backup_dir.to_string_lossy().to_string())
+}
+
+/// Files holding learning progress, cleared by `reset_progress`.
+const PROGRESS_FILES: &[&str] = &["vocabulary.json", "grammar.json", MATURE_VOCABULARY_FILE];
+
+/// Starts a language over: vocabulary and grammar go back to their empty
+/// templates (archived mature words are dropped too), while config.json,
+/// user-overrides.json and CLAUDE.md are kept. The old files are copied to
+/// `.backups` first; returns that backup folder.
+#[tauri::command]
+async fn reset_progress(language: String) -> Result<String, AppError> {
+    let lang_dir = get_language_dir(&language)?;
+    if !lang_dir.exists() {
+        return Err(AppError::NotFound(format!(
+            "Language '{}' does not exist",
+            language
+        )));
+    }
+
+    let tracker = tracker_lock(&language_key(&lang_dir));
+    let _tracker_guard = tracker.lock().await;
+    let lock = language_lock(&lang_dir);
+    let _guard = lock.lock().unwrap_or_else(|e| e.into_inner());
+
+    // Keep the name the files were created with, which may differ in case
+    // from the one passed in.
+    let name = read_vocabulary(&lang_dir)
+        .ok()
+        .and_then(|v| v.get("language")?.as_str().map(str::to_string))
+        .or_else(|| read_config(&lang_dir).ok().map(|c| c.language))
+        .unwrap_or_else(|| language.clone());
+
+    let backup_dir = backup_language_files(&lang_dir, PROGRESS_FILES)?;
+
+    let vocab = VOCABULARY_TEMPLATE.replace("{{LANGUAGE_NAME}}", &name);
+    let grammar = GRAMMAR_TEMPLATE.replace("{{LANGUAGE_NAME}}", &name);
+    for (filename, content) in [("vocabulary.json", vocab), ("grammar.json", grammar)] {
+        let value: Value = serde_json::from_str(&content)
+            .map_err(|e| format!("Invalid {} template: {}", filename, e))?;
+        write_json_atomic(&lang_dir.join(filename), &value)?;
+    }
+    let mature = lang_dir.join(MATURE_VOCABULARY_FILE);
+    if mature.exists() {
+        fs::remove_file(&mature)
+            .map_err(|e| format!("Failed to remove {}: {}", MATURE_VOCABULARY_FILE, e))?;
+    }
+
+    Ok(backup_dir.to_string_lossy().to_string())
+}
+
+/// Lists the files saved in a language's `.backups` folder, newest first.
+#[tauri::command]
+fn list_backups(language: String) -> Result<Vec<BackupEntry>, AppError> {
+    let lang_dir = get_language_dir(&language)?;
+    if !lang_dir.exists() {
+        return Err(AppError::NotFound(format!(
+            "Language '{}' does not exist",
+            language
+        )));
+    }
+    Ok(list_backup_entries(&lang_dir)?)
+}
+
+/// Puts a backed-up file (a `path` from `list_backups`) back in place after
+/// checking it parses. The current file is backed up first; returns that
+/// backup folder.
+#[tauri::command]
+async fn restore_backup(language: String, path: String) -> Result<String, AppError> {
+    let lang_dir = get_language_dir(&language)?;
+    if !lang_dir.exists() {
+        return Err(AppError::NotFound(format!(
+            "Language '{}' does not exist",
+            language
+        )));
+    }
+    let (backup, file) = resolve_backup_path(&lang_dir, &path)?;
+    validate_backup(&backup, file)?;
+    let content = fs::read(&backup).map_err(|e| format!("Failed to read backup: {}", e))?;
+
+    let tracker = tracker_lock(&language_key(&lang_dir));
+    let _tracker_guard = tracker.lock().await;
+    let lock = language_lock(&lang_dir);
+    let _guard = lock.lock().unwrap_or_else(|e| e.into_inner());
+
+    let backup_dir = backup_language_files(&lang_dir, &[file])?;
+    write_atomic(&lang_dir.join(file), &content)
+        .map_err(|e| format!("Failed to restore {}: {}", file, e))?;
+    info!("[backups] Restored {} from {}", file, path);
+
+    Ok(backup_dir.to_string_lossy().to_string())
+}
+
+/// Moves a language into the trash, from where `restore_language` can bring it
+/// back until it's purged.
+#[tauri::command]
+async fn delete_language(language: String) -> Result<String, AppError> {
+    let lang_dir = get_language_dir(&language)?;
+
+    if !lang_dir.exists() {
+        return Err(AppError::NotFound(format!(
+            "Language '{}' does not exist",
+            language
+        )));
+    }
+
+    let tracker = tracker_lock(&language_key(&lang_dir));
+    let _tracker_guard = tracker.lock().await;
+    let lock = language_lock(&lang_dir);
+    let _guard = lock.lock().unwrap_or_else(|e| e.into_inner());
+    move_to_trash(&lang_dir)?;
+    Ok(format!("Moved {} to the trash", language))
+}
+
+/// Folds `source`'s vocabulary and grammar into `target`, then moves `source`
+/// to the trash. A word or rule both have keeps whichever copy is further
+/// along. The target's config, preferences, tutor instructions and chat
+/// history are kept; the source's stay in the trash with it.
+#[tauri::command]
+async fn merge_languages(source: String, target: String) -> Result<MergeSummary, AppError> {
+    let source_dir = get_language_dir(&source)?;
+    let target_dir = get_language_dir(&target)?;
+    for (language, dir) in [(&source, &source_dir), (&target, &target_dir)] {
+        if !dir.exists() {
+            return Err(AppError::NotFound(format!(
+                "Language '{}' does not exist",
+                language
+            )));
+        }
+    }
+    let source_key = language_key(&source_dir);
+    let target_key = language_key(&target_dir);
+    if source_key == target_key {
+        return Err(AppError::InvalidInput(
+            "Can't merge a language into itself".to_string(),
+        ));
+    }
+
+    // Always lock in the same order so two opposite merges can't deadlock
+    let mut keys = [&source_key, &target_key];
+    keys.sort();
+    let first_tracker = tracker_lock(keys[0]);
+    let second_tracker = tracker_lock(keys[1]);
+    let _first_tracker_guard = first_tracker.lock().await;
+    let _second_tracker_guard = second_tracker.lock().await;
+    let (first_dir, second_dir) = if keys[0] == &source_key {
+        (&source_dir, &target_dir)
+    } else {
+        (&target_dir, &source_dir)
+    };
+    let first_lock = language_lock(first_dir);
+    let second_lock = language_lock(second_dir);
+    let _first_guard = first_lock.lock().unwrap_or_else(|e| e.into_inner());
+    let _second_guard = second_lock.lock().unwrap_or_else(|e| e.into_inner());
+
+    // Read everything up front so a bad source file stops the merge before
+    // anything is written
+    let mut vocab = read_vocabulary(&target_dir)?;
+    let mut grammar = read_grammar(&target_dir)?;
+    let mut mature = read_mature_vocabulary(&target_dir)?;
+    let source_vocab = read_vocabulary(&source_dir)?;
+    let source_grammar = read_grammar(&source_dir)?;
+    let source_mature = read_mature_vocabulary(&source_dir)?;
+
+    let none = HashSet::new();
+    let mut words = merge_entries(
+        entries_mut(&mut mature, "words")?,
+        take_entries(source_mature, "words"),
+        "word",
+        VOCAB_PROGRESS_FIELDS,
+        &none,
+    );
+    // A word already archived as mature stays there only
+    let archived: HashSet<String> = vocabulary_words(&mature)
+        .iter()
+        .filter_map(|w| Some(w.get("word")?.as_str()?.to_string()))
+        .collect();
+    words += merge_entries(
+        entries_mut(&mut vocab, "words")?,
+        take_entries(source_vocab, "words"),
+        "word",
+        VOCAB_PROGRESS_FIELDS,
+        &archived,
+    );
+    let rules = merge_entries(
+        entries_mut(&mut grammar, "rules")?,
+        take_entries(source_grammar, "rules"),
+        "rule",
+        GRAMMAR_PROGRESS_FIELDS,
+        &none,
+    );
+
+    let backup_dir = backup_language_files(&target_dir, PROGRESS_FILES)?;
+    if !vocabulary_words(&mature).is_empty() {
+        mature["language"] = vocab.get("language").cloned().unwrap_or(Value::Null);
+        write_json_atomic(&target_dir.join(MATURE_VOCABULARY_FILE), &mature)?;
+    }
+    write_json_atomic(&target_dir.join("vocabulary.json"), &vocab)?;
+    write_json_atomic(&target_dir.join("grammar.json"), &grammar)?;
+
+    move_to_trash(&source_dir)?;
+    info!(
+        "[merge] Merged {} into {}: {} words, {} rules",
+        source, target, words, rules
+    );
+
+    Ok(MergeSummary {
+        words,
+        rules,
+        backup_dir: backup_dir.to_string_lossy().to_string(),
+    })
+}
+
+/// Brings back the most recently deleted copy of a language. Chat history
+/// comes back with it, since Claude's sessions stay keyed to the original path.
+#[tauri::command]
+fn restore_language(language: String) -> Result<String, AppError> {
+    let lang_dir = get_language_dir(&language)?;
+    if lang_dir.exists() {
+        return Err(AppError::AlreadyExists(format!(
+            "Language '{}' already exists",
+            language
+        )));
+    }
+
+    let key = language_key(&lang_dir);
+    let (entry, _) = list_trash()?
+        .into_iter()
+        .rfind(|(_, name)| *name == key)
+        .ok_or_else(|| AppError::NotFound(format!("'{}' is not in the trash", language)))?;
+    fs::rename(&entry, &lang_dir).map_err(|e| format!("Failed to restore language: {}", e))?;
+
+    Ok(format!("Restored {}", language))
+}
+
+/// Permanently deletes every trashed language. Returns how many were removed.
+#[tauri::command]
+fn empty_trash() -> Result<usize, AppError> {
+    let trashed = list_trash()?;
+    for (path, _) in &trashed {
+        fs::remove_dir_all(path)
+            .map_err(|e| format!("Failed to delete {}: {}", path.display(), e))?;
+    }
+    Ok(trashed.len())
+}
+
+#[tauri::command]
+fn get_chat_history(
+    cache: State<'_, ChatHistoryCache>,
+    language: String,
+) -> Result<Vec<ChatMessage>, AppError> {
+    Ok(read_chat_history_page(&cache, &language, 0, usize::MAX)?.messages)
+}
+
+/// Every message of every Claude session of a language, oldest first, each
+/// tagged with its session. Unlike `get_chat_history` this reads the session
+/// files themselves rather than the app's chat log.
+#[tauri::command]
+fn get_full_history(
+    cache: State<'_, ChatHistoryCache>,
+    language: String,
+) -> Result<Vec<TaggedChatMessage>, AppError> {
+    let lang_dir = get_language_dir(&language)?;
+    if !lang_dir.exists() {
+        return Err(AppError::NotFound(format!(
+            "Language '{}' does not exist",
+            language
+        )));
+    }
+    let project_dir = get_claude_project_dir(&lang_dir)?;
+    Ok(read_all_chat_messages(&project_dir, &cache).messages)
+}
+
+/// One Claude session file of a language.
+#[derive(Serialize)]
+struct SessionSummary {
+    session_id: String,
+    /// Time of the first message, when the file records one.
+    started: Option<String>,
+    last_active: Option<String>,
+    message_count: usize,
+    /// Whether the next message continues this session.
+    current: bool,
+}
+
+/// Path of a session's file, checking the id and that the file exists.
+fn session_file_path(lang_dir: &Path, session_id: &str) -> Result<PathBuf, AppError> {
+    if !is_valid_session_id(session_id) {
+        return Err(AppError::InvalidInput(format!(
+            "Invalid session id '{}'",
+            session_id
+        )));
+    }
+    let path = get_claude_project_dir(lang_dir)?.join(format!("{}.jsonl", session_id));
+    if !path.is_file() {
+        return Err(AppError::NotFound(format!(
+            "Session '{}' does not exist",
+            session_id
+        )));
+    }
+    Ok(path)
+}
+
+/// Lists a language's Claude sessions, most recently active first.
+#[tauri::command]
+fn list_sessions(
+    cache: State<'_, ChatHistoryCache>,
+    language: String,
+) -> Result<Vec<SessionSummary>, AppError> {
+    let lang_dir = get_language_dir(&language)?;
+    if !lang_dir.exists() {
+        return Err(AppError::NotFound(format!(
+            "Language '{}' does not exist",
+            language
+        )));
+    }
+    let project_dir = get_claude_project_dir(&lang_dir)?;
+    let current = if should_continue_session(&lang_dir) {
+        current_session_file(&lang_dir)
+    } else {
+        None
+    };
+
+    let mut sessions = Vec::new();
+    for (path, _) in list_jsonl_files(&project_dir).into_iter().rev() {
+        let Some(session_id) = path.file_stem().and_then(|s| s.to_str()) else {
+            continue;
+        };
+        let parsed = match cache.messages(&path) {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                warn!("[Sessions] Skipping {}: {}", path.display(), e);
+                continue;
+            }
+        };
+        let timestamp =
+            |msg: Option<&SessionMessage>| msg.and_then(|m| m.timestamp).map(|t| t.to_rfc3339());
+        sessions.push(SessionSummary {
+            session_id: session_id.to_string(),
+            started: timestamp(parsed.messages.first()),
+            last_active: timestamp(parsed.messages.last()),
+            message_count: parsed.messages.len(),
+            current: current.as_ref() == Some(&path),
+        });
+    }
+    Ok(sessions)
+}
+
+/// The messages of one session, oldest first.
+#[tauri::command]
+fn get_session_history(
+    cache: State<'_, ChatHistoryCache>,
+    language: String,
+    session_id: String,
+) -> Result<ChatHistoryPage, AppError> {
+    let path = session_file_path(&get_language_dir(&language)?, &session_id)?;
+    let parsed = cache.messages(&path)?;
+    Ok(ChatHistoryPage {
+        messages: parsed.messages.iter().map(|m| m.message.clone()).collect(),
+        total: parsed.messages.len(),
+        skipped_lines: parsed.skipped_lines,
+    })
+}
+
+/// Makes the next message continue `session_id`, replacing a pending
+/// `start_new_session`.
+#[tauri::command]
+fn switch_session(language: String, session_id: String) -> Result<(), AppError> {
+    let lang_dir = get_language_dir(&language)?;
+    session_file_path(&lang_dir, &session_id)?;
+    let lock = language_lock(&lang_dir);
+    let _guard = lock.lock().unwrap_or_else(|e| e.into_inner());
+
+    set_active_session(&lang_dir, Some(&session_id))?;
+    clear_new_conversation_flag(&lang_dir);
+    Ok(())
+}
+
+/// Returns up to `limit` messages, skipping the `offset` most recent ones, so
+/// offset 0 is the latest page. Messages within a page are oldest first.
+#[tauri::command]
+fn get_chat_history_page(
+    cache: State<'_, ChatHistoryCache>,
+    language: String,
+    offset: usize,
+    limit: usize,
+) -> Result<ChatHistoryPage, AppError> {
+    read_chat_history_page(&cache, &language, offset, limit)
+}
+
+fn read_chat_history_page(
+    cache: &ChatHistoryCache,
+    language: &str,
+    offset: usize,
+    limit: usize,
+) -> Result<ChatHistoryPage, AppError> {
+    let lang_dir = get_language_dir(language)?;
+
+    if !lang_dir.exists() {
+        return Err(AppError::NotFound(format!(
+            "Language '{}' not set up",
+            language
+        )));
+    }
+
+    // Page in the database when possible, so older messages aren't loaded
+    let paged = open_chat_store(&lang_dir, cache).and_then(|store| {
+        let total = store.count()?;
+        Ok((total, store.page(offset, limit)?))
+    });
+    match paged {
+        Ok((total, messages)) => {
+            return Ok(ChatHistoryPage {
+                messages,
+                total,
+                skipped_lines: 0,
+            })
+        }
+        Err(e) => warn!("[Chat store] {}; reading session files instead", e),
+    }
+
+    let ParsedMessages {
+        mut messages,
+        skipped_lines,
+    } = read_session_messages(&lang_dir, cache)?;
+
+    let total = messages.len();
+    let end = total.saturating_sub(offset);
+    let start = end.saturating_sub(limit);
+    messages.truncate(end);
+    messages.drain(..start);
+
+    Ok(ChatHistoryPage {
+        messages,
+        total,
+        skipped_lines,
+    })
+}
+
+/// Finds chat messages containing `query`, ignoring case. `role` ("user" or
+/// "assistant") restricts the search to one side of the conversation. With
+/// `sessions` true the Claude session files are searched instead of the chat
+/// log, and each hit names its session; `index` is then a position in
+/// `get_full_history`.
+#[tauri::command]
+fn search_chat(
+    cache: State<'_, ChatHistoryCache>,
+    language: String,
+    query: String,
+    role: Option<String>,
+    sessions: Option<bool>,
+) -> Result<Vec<ChatSearchHit>, AppError> {
+    if query.trim().is_empty() {
+        return Err(AppError::InvalidInput(
+            "Search query cannot be empty".to_string(),
+        ));
+    }
+    if let Some(role) = role.as_deref() {
+        if role != "user" && role != "assistant" {
+            return Err(AppError::InvalidInput(format!(
+                "Invalid role '{}'. Must be user or assistant",
+                role
+            )));
+        }
+    }
+
+    let messages: Vec<(ChatMessage, Option<String>)> = if sessions.unwrap_or(false) {
+        get_full_history(cache, language)?
+            .into_iter()
+            .map(|m| (m.message, Some(m.session_id)))
+            .collect()
+    } else {
+        read_chat_history_page(&cache, &language, 0, usize::MAX)?
+            .messages
+            .into_iter()
+            .map(|m| (m, None))
+            .collect()
+    };
+    let neighbour = |index: Option<usize>| {
+        let (msg, _) = messages.get(index?)?;
+        Some(ChatMessage {
+            content: truncate_preview(&msg.content, SEARCH_NEIGHBOUR_PREVIEW_CHARS),
+            ..msg.clone()
+        })
+    };
+
+    Ok(messages
+        .iter()
+        .enumerate()
+        .filter(|(_, (msg, _))| role.as_deref().is_none_or(|r| msg.role == r))
+        .filter_map(|(index, (message, session_id))| {
+            let matches = find_case_insensitive(&message.content, query.trim());
+            if matches.is_empty() {
+                return None;
+            }
+            let (snippet, highlights) = build_snippet(&message.content, &matches);
+            Some(ChatSearchHit {
+                index,
+                message: message.clone(),
+                snippet,
+                highlights,
+                previous: neighbour(index.checked_sub(1)),
+                next: neighbour(Some(index + 1)),
+                session_id: session_id.clone(),
+            })
+        })
+        .collect())
+}
+
+#[tauri::command]
+fn get_chat_previews(
+    cache: State<'_, ChatHistoryCache>,
+    language: String,
+    max_chars: usize,
+) -> Result<Vec<ChatMessage>, AppError> {
+    Ok(read_chat_history_page(&cache, &language, 0, usize::MAX)?
+        .messages
+        .into_iter()
+        .map(|msg| ChatMessage {
+            content: truncate_preview(&msg.content, max_chars),
+            ..msg
+        })
+        .collect())
+}
+
+#[cfg_attr(mobile, tauri::mobile_entry_point)]
+pub fn run() {
+    if let Some(root) = env::var_os(DATA_DIR_ENV).filter(|r| !r.is_empty()) {
+        set_data_root(Some(PathBuf::from(root)));
+    }
+    let prepared = prepare_data_dir();
+    let logging = init_logging();
+    if let Err(e) = prepared {
+        error!("[Data dir] {}", e);
+    } else if let Err(e) = ensure_data_dir_writable() {
+        error!("[Data dir] {}", e);
+    }
+    if let Err(e) = logging {
+        error!("[Logging] File logging disabled: {}", e);
+    }
+
+    tauri::Builder::default()
+        .plugin(tauri_plugin_shell::init())
+        .plugin(tauri_plugin_dialog::init())
+        .manage::<SharedAgent>(Arc::new(ClaudeAgent))
+        .manage(ChatHistoryCache::default())
+        .invoke_handler(tauri::generate_handler![
             bootstrap_language,
+            preview_bootstrap,
+            check_rendered_files,
+            diagnose,
             send_message,
+            cancel_message,
+            regenerate_response,
+            edit_and_resend,
+            is_session_stale,
+            is_first_session,
+            will_continue_session,
+            start_new_session,
+            get_mode,
+            set_mode,
+            set_model,
+            set_agent_args,
+            set_show_romanization,
+            get_user_preferences,
+            get_config,
+            get_language_settings,
+            set_user_preferences,
+            get_cold_start_greeting,
+            set_greeting,
             get_vocabulary,
+            get_vocab_word,
+            get_merged_vocabulary,
+            check_vocabulary_size,
+            archive_mature_words,
+            export_flashcards_html,
+            export_language,
+            export_transcript,
+            import_language,
+            add_word_note,
+            add_vocab_word,
+            remove_vocab_word,
             get_grammar,
+            get_grammar_rule,
+            get_grammar_categories,
+            upsert_grammar_rule,
+            remove_grammar_rule,
+            get_words_added_between,
+            get_vocab_growth,
+            get_due_words,
+            record_review,
+            get_never_reviewed,
+            suggest_next_words,
+            summarize_session,
+            regenerate_romanization,
+            grade_vocabulary_word,
+            get_language_stats,
             list_languages,
+            archive_language,
+            unarchive_language,
+            get_languages_with_due,
+            get_languages_overview,
+            get_all_languages_summary,
+            get_tracking_lag,
+            clean_tracker,
+            get_claude_bin,
+            check_claude,
+            get_log_path,
+            get_data_root,
+            get_data_dir_status,
+            list_claude_profiles,
+            get_claude_profile,
+            set_claude_profile,
+            rename_language,
+            reset_progress,
+            regenerate_tutor_instructions,
+            get_tutor_instructions,
+            set_tutor_instructions,
             delete_language,
-            get_chat_history
+            restore_language,
+            merge_languages,
+            list_backups,
+            restore_backup,
+            empty_trash,
+            get_chat_history,
+            get_chat_history_page,
+            get_full_history,
+            list_sessions,
+            get_session_history,
+            switch_session,
+            get_chat_previews,
+            search_chat
         ])
         .run(tauri::generate_context!())
         .unwrap_or_else(|e| {
-            eprintln!("Failed to start application: {}", e);
+            error!("Failed to start application: {}", e);
             std::process::exit(1);
         });
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+
+    /// Points the data root and Claude config dir at a per-process temp dir,
+    /// so tests never touch the real ones.
+    fn test_root() -> &'static Path {
+        static ROOT: OnceLock<PathBuf> = OnceLock::new();
+        ROOT.get_or_init(|| {
+            let root = env::temp_dir().join(format!("ysfl-tests-{}", std::process::id()));
+            let _ = fs::remove_dir_all(&root);
+            fs::create_dir_all(root.join("data")).unwrap();
+            fs::create_dir_all(root.join("claude")).unwrap();
+            set_data_root(Some(root.join("data")));
+            env::set_var(CLAUDE_CONFIG_DIR_ENV, root.join("claude"));
+            root
+        })
+    }
+
+    fn unique(hint: &str) -> String {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        format!("{} {}", hint, NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+
+    /// An empty directory of its own under the test root.
+    fn scratch_dir(hint: &str) -> PathBuf {
+        let dir = test_root().join("scratch").join(unique(hint));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Creates a language with empty vocabulary and grammar files, returning
+    /// its name and folder.
+    fn test_language(hint: &str) -> (String, PathBuf) {
+        test_root();
+        let language = unique(hint);
+        let lang_dir = get_language_dir(&language).unwrap();
+        fs::create_dir_all(&lang_dir).unwrap();
+        write_json_atomic(
+            &lang_dir.join("vocabulary.json"),
+            &json!({ "language": language, "words": [] }),
+        )
+        .unwrap();
+        write_json_atomic(
+            &lang_dir.join("grammar.json"),
+            &json!({ "language": language, "rules": [] }),
+        )
+        .unwrap();
+        (language, lang_dir)
+    }
+
+    /// One session-file line as Claude writes it, without the newline.
+    fn session_line(role: &str, text: &str, uuid: &str) -> String {
+        let content = if role == "user" {
+            json!(text)
+        } else {
+            json!([{ "type": "text", "text": text }])
+        };
+        json!({
+            "type": role,
+            "uuid": uuid,
+            "timestamp": "2024-05-01T09:00:00Z",
+            "message": { "role": role, "content": content },
+        })
+        .to_string()
+    }
+
+    /// Writes a session file into the Claude project dir of `lang_dir`.
+    fn write_session(lang_dir: &Path, id: &str, lines: &[String]) {
+        let project_dir = get_claude_project_dir(lang_dir).unwrap();
+        fs::create_dir_all(&project_dir).unwrap();
+        fs::write(
+            project_dir.join(format!("{}.jsonl", id)),
+            lines.join("\n") + "\n",
+        )
+        .unwrap();
+    }
+
+    fn contents(messages: &[SessionMessage]) -> Vec<&str> {
+        messages
+            .iter()
+            .map(|m| m.message.content.as_str())
+            .collect()
+    }
+
+    fn word_list(value: &Value) -> Vec<String> {
+        vocabulary_words(value)
+            .iter()
+            .filter_map(|w| Some(w.get("word")?.as_str()?.to_string()))
+            .collect()
+    }
+
+    // Interval and ease transitions are covered by the tests in `sm2`; these
+    // check the command around them.
+    #[tokio::test]
+    async fn grade_vocabulary_word_schedules_the_next_review() {
+        let (language, lang_dir) = test_language("Grade");
+        write_json_atomic(
+            &lang_dir.join("vocabulary.json"),
+            &json!({
+                "language": language,
+                "words": [{ "word": "물", "ease": 2.5, "interval": 1, "repetitions": 1 }],
+            }),
+        )
+        .unwrap();
+
+        let result = grade_vocabulary_word(language, "물".to_string(), 5)
+            .await
+            .unwrap();
+        assert_eq!(result.interval, 6);
+        let expected = Local::now().date_naive() + Days::new(6);
+        assert_eq!(result.next_review, expected.format(DATE_FORMAT).to_string());
+
+        let vocab = read_vocabulary(&lang_dir).unwrap();
+        assert_eq!(vocabulary_words(&vocab)[0]["repetitions"], 2);
+    }
+
+    #[tokio::test]
+    async fn grade_vocabulary_word_rejects_grades_above_five() {
+        let (language, _) = test_language("Grade Range");
+        let err = grade_vocabulary_word(language, "물".to_string(), 6)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, AppError::InvalidInput(_)));
+    }
+
+    #[tokio::test]
+    async fn grade_vocabulary_word_reports_an_unknown_word() {
+        let (language, _) = test_language("Grade Missing");
+        let err = grade_vocabulary_word(language, "없다".to_string(), 4)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, AppError::NotFound(_)));
+    }
+
+    #[test]
+    fn truncate_preview_cuts_on_char_boundaries() {
+        assert_eq!(truncate_preview("日本語を勉強しています", 3), "日本語…");
+        assert_eq!(truncate_preview("🍣🍜🍙", 2), "🍣🍜…");
+        assert_eq!(truncate_preview("🍣🍜🍙", 3), "🍣🍜🍙");
+        assert_eq!(truncate_preview("안녕", 10), "안녕");
+    }
+
+    #[test]
+    fn resolve_absolute_path_handles_a_missing_directory() {
+        let dir = scratch_dir("Resolve");
+        let missing = dir.join("not yet").join("created");
+        assert_eq!(
+            resolve_absolute_path(&missing).unwrap(),
+            dir.canonicalize().unwrap().join("not yet").join("created")
+        );
+
+        let roundabout = dir.join("gone").join("..").join(".").join("created");
+        assert_eq!(
+            resolve_absolute_path(&roundabout).unwrap(),
+            dir.canonicalize().unwrap().join("created")
+        );
+    }
+
+    #[test]
+    fn strip_extended_path_prefix_handles_each_form() {
+        assert_eq!(strip_extended_path_prefix(r"\\?\C:\foo"), r"C:\foo");
+        assert_eq!(
+            strip_extended_path_prefix(r"\\?\UNC\server\share\foo"),
+            r"\\server\share\foo"
+        );
+        assert_eq!(strip_extended_path_prefix(r"C:\foo"), r"C:\foo");
+        assert_eq!(
+            strip_extended_path_prefix(r"\\server\share"),
+            r"\\server\share"
+        );
+    }
+
+    #[test]
+    fn merge_vocabulary_lets_the_learner_override_the_deck() {
+        let shared = [
+            json!({ "word": "casa", "meaning": "house", "romanization": "casa" }),
+            json!({ "word": "mesa", "meaning": "table" }),
+            json!({ "word": "casa", "meaning": "duplicate" }),
+        ];
+        let user = [
+            json!({ "word": "casa", "meaning": "home", "repetitions": 3 }),
+            json!({ "word": "silla", "meaning": "chair" }),
+        ];
+
+        assert_eq!(
+            merge_vocabulary(&shared, &user),
+            [
+                json!({
+                    "word": "casa",
+                    "meaning": "home",
+                    "romanization": "casa",
+                    "repetitions": 3,
+                    "shared": true,
+                }),
+                json!({ "word": "mesa", "meaning": "table", "shared": true }),
+                json!({ "word": "silla", "meaning": "chair" }),
+            ]
+        );
+    }
+
+    #[test]
+    fn read_merged_vocabulary_overlays_the_shared_deck() {
+        let (language, lang_dir) = test_language("Shared Deck");
+        assert_eq!(
+            read_merged_vocabulary(&lang_dir).unwrap(),
+            read_vocabulary(&lang_dir).unwrap()
+        );
+
+        write_json_atomic(
+            &lang_dir.join(SHARED_VOCABULARY_FILE),
+            &json!({ "language": language, "words": [{ "word": "libro", "meaning": "book" }] }),
+        )
+        .unwrap();
+        write_json_atomic(
+            &lang_dir.join("vocabulary.json"),
+            &json!({
+                "language": language,
+                "words": [{ "word": "libro", "interval": 6 }, { "word": "pluma" }],
+            }),
+        )
+        .unwrap();
+
+        let merged = read_merged_vocabulary(&lang_dir).unwrap();
+        assert_eq!(word_list(&merged), ["libro", "pluma"]);
+        assert_eq!(vocabulary_words(&merged)[0]["meaning"], "book");
+        assert_eq!(vocabulary_words(&merged)[0]["interval"], 6);
+        // The learner's file is left as it was
+        assert_eq!(
+            word_list(&read_vocabulary(&lang_dir).unwrap()),
+            ["libro", "pluma"]
+        );
+        assert!(vocabulary_words(&read_vocabulary(&lang_dir).unwrap())[0]
+            .get("meaning")
+            .is_none());
+    }
+
+    #[test]
+    fn a_moved_language_does_not_continue_another_session() {
+        let (language, lang_dir) = test_language("Session");
+        let line = json!({
+            "type": "user",
+            "uuid": "a",
+            "timestamp": Local::now().to_rfc3339(),
+            "message": { "role": "user", "content": "안녕하세요" },
+        });
+        write_session(&lang_dir, "11111111-aaaa", &[line.to_string()]);
+        assert!(has_session(&lang_dir));
+        assert!(will_continue_session(language).unwrap());
+
+        // The session stays filed under the old path
+        let moved = unique("Session Moved");
+        let moved_dir = get_language_dir(&moved).unwrap();
+        fs::rename(&lang_dir, &moved_dir).unwrap();
+        assert!(!has_session(&moved_dir));
+        assert!(!will_continue_session(moved).unwrap());
+    }
+
+    #[test]
+    fn encode_project_path_handles_windows_paths() {
+        assert_eq!(
+            encode_project_path(r"C:\Users\foo\bar", true),
+            "C--Users-foo-bar"
+        );
+        assert_eq!(
+            encode_project_path(r"\\?\C:\Users\foo\bar", true),
+            "C--Users-foo-bar"
+        );
+        assert_eq!(
+            encode_project_path(r"\\server\share\foo", true),
+            "--server-share-foo"
+        );
+    }
+
+    #[test]
+    fn encode_project_path_handles_unix_paths() {
+        // macOS
+        assert_eq!(
+            encode_project_path("/Users/foo/Library/Application Support/ysfl/korean", false),
+            "-Users-foo-Library-Application-Support-ysfl-korean"
+        );
+        // Linux
+        assert_eq!(
+            encode_project_path("/home/foo/.local/share/ysfl/old norse", false),
+            "-home-foo--local-share-ysfl-old-norse"
+        );
+        assert_eq!(
+            encode_project_path("/home/foo/lang/data/日本語", false),
+            "-home-foo-lang-data----"
+        );
+    }
+
+    #[test]
+    fn get_claude_project_dir_lives_under_the_config_dir() {
+        let dir = scratch_dir("Project Dir");
+        let project_dir = get_claude_project_dir(&dir).unwrap();
+        assert_eq!(
+            project_dir,
+            claude_config_dir()
+                .unwrap()
+                .join("projects")
+                .join(encode_project_dir_name(&dir.canonicalize().unwrap()))
+        );
+        assert!(project_dir.starts_with(test_root().join("claude")));
+    }
+
+    #[tokio::test]
+    async fn import_language_overwrite_replaces_the_folder_and_cleans_up() {
+        let (language, lang_dir) = test_language("Import");
+        fs::write(
+            lang_dir.join("config.json"),
+            json!({
+                "language": language,
+                "native_script": "Latin",
+                "romanization": "none",
+                "started": "2024-05-01",
+            })
+            .to_string(),
+        )
+        .unwrap();
+        let dest = scratch_dir("Import Archive");
+        let zip_path =
+            export_language(language.clone(), dest.to_string_lossy().into_owned()).unwrap();
+
+        fs::write(lang_dir.join("notes.md"), "written after the export").unwrap();
+        assert!(matches!(
+            import_language(zip_path.clone(), false).await,
+            Err(AppError::AlreadyExists(_))
+        ));
+        assert_eq!(import_language(zip_path, true).await.unwrap(), language);
+
+        assert!(lang_dir.join("config.json").exists());
+        assert!(!lang_dir.join("notes.md").exists());
+        let data_dir = lang_dir.parent().unwrap();
+        let dir_name = language_key(&lang_dir);
+        assert!(!data_dir.join(format!(".import-{}", dir_name)).exists());
+        assert!(!data_dir.join(format!(".replaced-{}", dir_name)).exists());
+    }
+
+    fn user_json(content: Value) -> Value {
+        json!({ "type": "user", "message": { "role": "user", "content": content } })
+    }
+
+    #[test]
+    fn extract_user_message_reads_a_plain_string() {
+        assert_eq!(
+            extract_user_message(&user_json(json!("Bonjour"))).as_deref(),
+            Some("Bonjour")
+        );
+    }
+
+    #[test]
+    fn extract_user_message_keeps_text_next_to_tool_results() {
+        let json = user_json(json!([
+            { "type": "tool_result", "tool_use_id": "t1", "content": "file contents" },
+            { "type": "text", "text": "Merci" },
+        ]));
+        assert_eq!(extract_user_message(&json).as_deref(), Some("Merci"));
+    }
+
+    #[test]
+    fn extract_user_message_skips_tool_results_alone() {
+        let json = user_json(json!([
+            { "type": "tool_result", "tool_use_id": "t1", "content": "file contents" },
+        ]));
+        assert_eq!(extract_user_message(&json), None);
+    }
+
+    #[test]
+    fn extract_user_message_ignores_other_roles() {
+        let json = json!({
+            "type": "assistant",
+            "message": { "role": "assistant", "content": "Bonjour" },
+        });
+        assert_eq!(extract_user_message(&json), None);
+    }
+
+    fn assistant_json(content: Value) -> Value {
+        json!({ "type": "assistant", "message": { "role": "assistant", "content": content } })
+    }
+
+    #[test]
+    fn extract_assistant_message_reads_a_single_block() {
+        let json = assistant_json(json!([{ "type": "text", "text": "Hallo!" }]));
+        assert_eq!(extract_assistant_message(&json).as_deref(), Some("Hallo!"));
+    }
+
+    #[test]
+    fn extract_assistant_message_joins_every_text_block() {
+        let json = assistant_json(json!([
+            { "type": "text", "text": "Erstens" },
+            { "type": "text", "text": "Zweitens" },
+        ]));
+        assert_eq!(
+            extract_assistant_message(&json).as_deref(),
+            Some("Erstens\nZweitens")
+        );
+    }
+
+    #[test]
+    fn extract_assistant_message_skips_tool_use_between_text() {
+        let json = assistant_json(json!([
+            { "type": "text", "text": "Moment" },
+            { "type": "tool_use", "id": "t1", "name": "Read", "input": {} },
+            { "type": "text", "text": "Fertig" },
+        ]));
+        assert_eq!(
+            extract_assistant_message(&json).as_deref(),
+            Some("Moment\nFertig")
+        );
+    }
+
+    #[test]
+    fn extract_assistant_message_without_text_is_none() {
+        let json = assistant_json(json!([
+            { "type": "tool_use", "id": "t1", "name": "Read", "input": {} },
+        ]));
+        assert_eq!(extract_assistant_message(&json), None);
+    }
+
+    #[test]
+    fn title_case_capitalizes_each_word() {
+        assert_eq!(title_case("korean"), "Korean");
+        assert_eq!(title_case("old norse"), "Old Norse");
+        assert_eq!(title_case("serbo-croatian"), "Serbo-croatian");
+        assert_eq!(title_case(""), "");
+        assert_eq!(title_case("ñandeva guaraní"), "Ñandeva Guaraní");
+        assert_eq!(title_case("日本語"), "日本語");
+    }
+
+    #[tokio::test]
+    async fn rust_edits_wait_for_a_running_tracker() {
+        let (language, lang_dir) = test_language("Tracker Race");
+        write_json_atomic(
+            &lang_dir.join("vocabulary.json"),
+            &json!({
+                "language": language,
+                "words": [{ "word": "old", "interval": ARCHIVE_INTERVAL_DAYS }],
+            }),
+        )
+        .unwrap();
+
+        // Stands in for a tracker run: read, think for a while, write back.
+        let (started_tx, started_rx) = tokio::sync::oneshot::channel();
+        let tracker = {
+            let lang_dir = lang_dir.clone();
+            tokio::spawn(async move {
+                let lock = tracker_lock(&language_key(&lang_dir));
+                let _guard = lock.lock().await;
+                let mut vocab = read_vocabulary(&lang_dir).unwrap();
+                started_tx.send(()).unwrap();
+                tokio::time::sleep(Duration::from_millis(200)).await;
+                entries_mut(&mut vocab, "words")
+                    .unwrap()
+                    .push(json!({ "word": "new" }));
+                write_json_atomic(&lang_dir.join("vocabulary.json"), &vocab).unwrap();
+            })
+        };
+
+        started_rx.await.unwrap();
+        let archive = tokio::spawn(archive_mature_words(language.clone()));
+        tracker.await.unwrap();
+        assert_eq!(archive.await.unwrap().unwrap(), 1);
+
+        let vocab = read_vocabulary(&lang_dir).unwrap();
+        assert_eq!(word_list(&vocab), ["new"]);
+        let mature = read_mature_vocabulary(&lang_dir).unwrap();
+        assert_eq!(word_list(&mature), ["old"]);
+    }
+
+    /// Stands in for claude: records each call and answers at once, unless
+    /// told to hang until cancelled or to fail tracking.
+    #[derive(Default)]
+    struct MockAgent {
+        calls: Mutex<Vec<String>>,
+        hang: bool,
+        fail_tracking: bool,
+        /// Added to the vocabulary by `track`.
+        tracked_word: Option<&'static str>,
+    }
+
+    impl MockAgent {
+        fn calls(&self) -> Vec<String> {
+            self.calls.lock().unwrap().clone()
+        }
+    }
+
+    impl Agent for MockAgent {
+        fn respond<'a>(
+            &'a self,
+            _dir: &'a Path,
+            message: &'a str,
+            options: ResponderOptions,
+        ) -> AgentFuture<'a, Result<String, AppError>> {
+            self.calls
+                .lock()
+                .unwrap()
+                .push(format!("respond {}", message));
+            Box::pin(async move {
+                if self.hang {
+                    std::future::pending::<()>().await;
+                }
+                let reply = format!("re: {}", message);
+                emit_responder_event(&options.events, RESPONDER_CHUNK_EVENT, "", &reply);
+                Ok(reply)
+            })
+        }
+
+        fn track<'a>(
+            &'a self,
+            dir: &'a Path,
+            message: &'a str,
+        ) -> AgentFuture<'a, Result<(), AppError>> {
+            self.calls
+                .lock()
+                .unwrap()
+                .push(format!("track {}", message));
+            Box::pin(async move {
+                if self.hang {
+                    std::future::pending::<()>().await;
+                }
+                if self.fail_tracking {
+                    return Err(AppError::Claude("Claude error: boom".to_string()));
+                }
+                if let Some(word) = self.tracked_word {
+                    add_word_file(dir, word);
+                }
+                Ok(())
+            })
+        }
+    }
+
+    fn add_word_file(lang_dir: &Path, word: &str) {
+        let mut vocab = read_vocabulary(lang_dir).unwrap();
+        entries_mut(&mut vocab, "words")
+            .unwrap()
+            .push(json!({ "word": word }));
+        write_json_atomic(&lang_dir.join("vocabulary.json"), &vocab).unwrap();
+    }
+
+    /// An `EventSink` that keeps the names of the events sent to it.
+    fn recorded_events() -> (EventSink, Arc<Mutex<Vec<String>>>) {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let sink: EventSink = {
+            let seen = Arc::clone(&seen);
+            Arc::new(move |event: &str, _payload: Value| {
+                seen.lock().unwrap().push(event.to_string())
+            })
+        };
+        (sink, seen)
+    }
+
+    /// A test language with the overrides file `send_message` reads.
+    fn chat_language(hint: &str) -> (String, PathBuf) {
+        let (language, lang_dir) = test_language(hint);
+        fs::write(lang_dir.join("user-overrides.json"), "{}").unwrap();
+        (language, lang_dir)
+    }
+
+    /// Polls until `done` holds, failing the test after a few seconds.
+    async fn eventually(mut done: impl FnMut() -> bool) {
+        for _ in 0..200 {
+            if done() {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        panic!("condition not reached");
+    }
+
+    #[tokio::test]
+    async fn send_message_replies_records_and_tracks_in_the_background() {
+        let (language, lang_dir) = chat_language("Send");
+        let agent = Arc::new(MockAgent {
+            tracked_word: Some("gato"),
+            ..MockAgent::default()
+        });
+        let (events, seen) = recorded_events();
+        let cache = ChatHistoryCache::default();
+
+        let sent = send_message_with(
+            events,
+            agent.clone(),
+            &cache,
+            "hola".to_string(),
+            language.clone(),
+            true,
+            false,
+        )
+        .await
+        .unwrap();
+        assert_eq!(sent.reply, "re: hola");
+        assert!(sent.tracking.is_none());
+
+        eventually(|| seen.lock().unwrap().iter().any(|e| e == TRACKER_DONE_EVENT)).await;
+        let mut calls = agent.calls();
+        calls.sort();
+        assert_eq!(calls, ["respond hola", "track hola"]);
+        assert!(seen
+            .lock()
+            .unwrap()
+            .contains(&RESPONDER_CHUNK_EVENT.to_string()));
+        assert_eq!(word_list(&read_vocabulary(&lang_dir).unwrap()), ["gato"]);
+
+        let history = load_chat_messages(&lang_dir, &cache).unwrap().messages;
+        let logged: Vec<_> = history.iter().map(|m| m.content.as_str()).collect();
+        assert_eq!(logged, ["hola", "re: hola"]);
+    }
+
+    #[tokio::test]
+    async fn sync_tracking_reports_only_this_runs_changes() {
+        let (language, lang_dir) = chat_language("Send Sync");
+        let agent = Arc::new(MockAgent {
+            tracked_word: Some("perro"),
+            ..MockAgent::default()
+        });
+        let (events, _) = recorded_events();
+
+        // An earlier tracker still finishing: its edit mustn't count as ours.
+        let lock = tracker_lock(&language_key(&lang_dir));
+        let earlier = lock.lock().await;
+        let send = tokio::spawn(async move {
+            let cache = ChatHistoryCache::default();
+            send_message_with(
+                events,
+                agent,
+                &cache,
+                "hola".to_string(),
+                language,
+                true,
+                true,
+            )
+            .await
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        add_word_file(&lang_dir, "gato");
+        drop(earlier);
+
+        let sent = send.await.unwrap().unwrap();
+        assert!(sent.tracking_error.is_none());
+        let tracking = sent.tracking.unwrap();
+        assert_eq!(tracking.words_added, ["perro"]);
+        assert!(tracking.words_updated.is_empty());
+    }
+
+    #[tokio::test]
+    async fn sync_tracking_returns_the_reply_when_the_tracker_fails() {
+        let (language, _) = chat_language("Send Fail");
+        let agent = Arc::new(MockAgent {
+            fail_tracking: true,
+            ..MockAgent::default()
+        });
+        let (events, seen) = recorded_events();
+
+        let sent = send_message_with(
+            events,
+            agent,
+            &ChatHistoryCache::default(),
+            "hola".to_string(),
+            language,
+            true,
+            true,
+        )
+        .await
+        .unwrap();
+        assert_eq!(sent.reply, "re: hola");
+        assert!(sent.tracking.is_none());
+        assert!(matches!(sent.tracking_error, Some(AppError::Claude(_))));
+        assert!(seen
+            .lock()
+            .unwrap()
+            .contains(&TRACKER_ERROR_EVENT.to_string()));
+    }
+
+    #[tokio::test]
+    async fn cancel_message_stops_the_reply_and_the_tracker() {
+        let (language, _) = chat_language("Send Cancel");
+        let agent = Arc::new(MockAgent {
+            hang: true,
+            ..MockAgent::default()
+        });
+        let (events, seen) = recorded_events();
+
+        let send = {
+            let agent = agent.clone();
+            let language = language.clone();
+            tokio::spawn(async move {
+                let cache = ChatHistoryCache::default();
+                send_message_with(
+                    events,
+                    agent,
+                    &cache,
+                    "hola".to_string(),
+                    language,
+                    true,
+                    false,
+                )
+                .await
+            })
+        };
+        eventually(|| agent.calls().len() == 2).await;
+        assert!(cancel_message(language.clone()).unwrap());
+
+        assert!(matches!(send.await.unwrap(), Err(AppError::Cancelled(_))));
+        let seen = seen.lock().unwrap();
+        assert!(seen.contains(&RESPONDER_CANCELLED_EVENT.to_string()));
+        assert!(seen.contains(&TRACKER_ERROR_EVENT.to_string()));
+        assert!(!cancel_message(language).unwrap());
+    }
+
+    // The file opens with a 64 GiB hole (sparse, so it takes no disk space)
+    // holding no newline. Reading it front to back would try to buffer it as
+    // one line; the tail only ever touches the last few chunks.
+    #[cfg(unix)]
+    #[test]
+    fn tail_chat_messages_reads_only_the_end_of_a_large_file() {
+        let path = scratch_dir("Tail").join("session.jsonl");
+        let mut file = File::create(&path).unwrap();
+        file.set_len(64 << 30).unwrap();
+        file.seek(SeekFrom::End(0)).unwrap();
+        file.write_all(b"\n").unwrap();
+        let mut written = 0;
+        let mut i = 0;
+        while written < 3 * TAIL_MIN_FILE_BYTES {
+            let role = if i % 2 == 0 { "user" } else { "assistant" };
+            let line = session_line(role, &format!("message {}", i), &format!("uuid-{}", i));
+            writeln!(file, "{}", line).unwrap();
+            written += line.len() as u64 + 1;
+            i += 1;
+        }
+        drop(file);
+
+        let messages = tail_chat_messages(&path, 3).unwrap();
+        let expected: Vec<String> = (i - 3..i).map(|n| format!("message {}", n)).collect();
+        assert_eq!(contents(&messages), expected);
+        assert_eq!(
+            messages[2].uuid.as_deref(),
+            Some(format!("uuid-{}", i - 1).as_str())
+        );
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn tail_chat_messages_matches_a_full_parse_for_small_files() {
+        let path = scratch_dir("Tail Small").join("session.jsonl");
+        let lines: Vec<String> = (0..6)
+            .map(|i| session_line("user", &format!("message {}", i), &format!("uuid-{}", i)))
+            .collect();
+        fs::write(&path, lines.join("\n") + "\n").unwrap();
+
+        let messages = tail_chat_messages(&path, 2).unwrap();
+        assert_eq!(contents(&messages), ["message 4", "message 5"]);
+    }
+
+    #[test]
+    fn chat_history_cache_reparses_a_changed_file() {
+        let path = scratch_dir("Cache").join("session.jsonl");
+        let cache = ChatHistoryCache::default();
+
+        fs::write(&path, session_line("user", "first", "a") + "\n").unwrap();
+        let first = cache.messages(&path).unwrap();
+        assert_eq!(contents(&first.messages), ["first"]);
+        assert!(Arc::ptr_eq(&first, &cache.messages(&path).unwrap()));
+
+        let rewritten = [
+            session_line("user", "second", "b"),
+            session_line("assistant", "reply", "c"),
+        ];
+        fs::write(&path, rewritten.join("\n") + "\n").unwrap();
+        let later = SystemTime::now() + Duration::from_secs(60);
+        File::options()
+            .write(true)
+            .open(&path)
+            .and_then(|file| file.set_modified(later))
+            .unwrap();
+
+        let second = cache.messages(&path).unwrap();
+        assert_eq!(contents(&second.messages), ["second", "reply"]);
+    }
+
+    #[tokio::test]
+    async fn upsert_grammar_rule_inserts_a_new_rule() {
+        let (language, lang_dir) = test_language("Grammar Insert");
+        let stored = upsert_grammar_rule(language, "Verb-final order".to_string(), 2, 1)
+            .await
+            .unwrap();
+        assert_eq!(stored.rule, "Verb-final order");
+        assert_eq!(stored.stars.as_u64(), Some(2));
+
+        let grammar = read_grammar(&lang_dir).unwrap();
+        assert_eq!(grammar_rules(&grammar).len(), 1);
+    }
+
+    #[tokio::test]
+    async fn upsert_grammar_rule_updates_progress_and_keeps_other_fields() {
+        let (language, lang_dir) = test_language("Grammar Update");
+        write_json_atomic(
+            &lang_dir.join("grammar.json"),
+            &json!({
+                "language": language,
+                "rules": [{
+                    "rule": "Topic marker",
+                    "category": "particles",
+                    "stars": 1,
+                    "correct_streak": 0,
+                    "example": "저는",
+                }],
+            }),
+        )
+        .unwrap();
+
+        upsert_grammar_rule(language, "Topic marker".to_string(), 4, 3)
+            .await
+            .unwrap();
+
+        let grammar = read_grammar(&lang_dir).unwrap();
+        assert_eq!(
+            grammar_rules(&grammar),
+            [json!({
+                "rule": "Topic marker",
+                "category": "particles",
+                "stars": 4,
+                "correct_streak": 3,
+                "example": "저는",
+            })]
+        );
+    }
+
+    #[tokio::test]
+    async fn upsert_grammar_rule_clamps_stars() {
+        let (language, _) = test_language("Grammar Clamp");
+        let low = upsert_grammar_rule(language.clone(), "Low".to_string(), 0, 0)
+            .await
+            .unwrap();
+        assert_eq!(low.stars.as_u64(), Some(MIN_GRAMMAR_STARS.into()));
+        let high = upsert_grammar_rule(language, "High".to_string(), 9, 0)
+            .await
+            .unwrap();
+        assert_eq!(high.stars.as_u64(), Some(MAX_GRAMMAR_STARS.into()));
+    }
+
+    #[tokio::test]
+    async fn remove_grammar_rule_reports_a_missing_rule() {
+        let (language, _) = test_language("Grammar Remove");
+        upsert_grammar_rule(language.clone(), "Kept".to_string(), 3, 0)
+            .await
+            .unwrap();
+
+        let err = remove_grammar_rule(language.clone(), "Missing".to_string())
+            .await
+            .unwrap_err();
+        assert!(matches!(err, AppError::NotFound(_)));
+        remove_grammar_rule(language, "Kept".to_string())
+            .await
+            .unwrap();
+    }
+
+    #[test]
+    fn language_dir_name_folds_case_and_whitespace() {
+        assert_eq!(language_dir_name("Korean"), language_dir_name(" korean "));
+        assert_eq!(language_dir_name("Old  Norse"), "old norse");
+        assert_eq!(language_dir_name("\tOld\nNorse "), "old norse");
+        assert_ne!(
+            language_dir_name("Old Norse"),
+            language_dir_name("OldNorse")
+        );
+    }
+
+    #[test]
+    fn bootstrap_language_rejects_a_case_or_whitespace_variant() {
+        test_root();
+        let language = unique("Bootstrap");
+        let created = bootstrap_language(language.clone()).unwrap();
+        assert_eq!(created.language, language);
+
+        let variant = format!("  {}  ", language.to_uppercase().replace(' ', "   "));
+        let err = bootstrap_language(variant).unwrap_err();
+        assert!(matches!(err, AppError::AlreadyExists(_)));
+    }
+
+    #[test]
+    fn export_language_names_the_archive_after_the_folder() {
+        let (language, lang_dir) = test_language("Export");
+        let dest = scratch_dir("Export Dest");
+        let spaced = format!("  {}  ", language.to_uppercase().replace(' ', "  "));
+
+        let path = export_language(spaced, dest.to_string_lossy().into_owned()).unwrap();
+        assert_eq!(
+            PathBuf::from(path),
+            dest.join(format!("{}.zip", language_key(&lang_dir)))
+        );
+    }
+
+    fn claude_error(stderr: &str) -> AppError {
+        AppError::Claude(format!("Claude error: {}", stderr))
+    }
+
+    #[test]
+    fn transient_claude_errors_are_retried() {
+        for stderr in [
+            r#"API Error: 529 {"type":"error","error":{"type":"overloaded_error"}}"#,
+            r#"API Error: 429 {"type":"error","error":{"type":"rate_limit_error"}}"#,
+            "Request failed with status code 503",
+            "HTTP 502 Bad Gateway",
+            "Rate limit reached, try again later",
+            "read ECONNRESET",
+            "getaddrinfo EAI_AGAIN api.anthropic.com",
+        ] {
+            assert!(
+                is_transient_claude_error(&claude_error(stderr)),
+                "{}",
+                stderr
+            );
+        }
+    }
+
+    #[test]
+    fn status_like_numbers_elsewhere_are_not_transient() {
+        for stderr in [
+            "Prompt is too long: 204291 tokens > 200000 maximum",
+            "Invalid model claude-3-5-sonnet-20240503",
+            "Unexpected token at line 429",
+            "unknown option '--network'",
+            "API Error: 400 invalid_request_error",
+        ] {
+            assert!(
+                !is_transient_claude_error(&claude_error(stderr)),
+                "{}",
+                stderr
+            );
+        }
+    }
+
+    #[test]
+    fn only_failed_claude_runs_are_transient() {
+        assert!(!is_transient_claude_error(&claude_error(
+            "bash: claude: command not found (status 503)"
+        )));
+        assert!(!is_transient_claude_error(&AppError::Timeout(
+            "Responder timed out after 503s".to_string()
+        )));
+        assert!(!is_transient_claude_error(&AppError::ClaudeUnavailable(
+            "Failed to run claude: network error".to_string()
+        )));
+    }
+
+    #[test]
+    fn validate_language_name_accepts_ordinary_punctuation() {
+        for name in [
+            "Old Norse",
+            "N'Ko",
+            "Serbo-Croatian",
+            "old_norse",
+            "Ελληνικά",
+        ] {
+            assert!(validate_language_name(name).is_ok(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn validate_language_name_rejects_unsafe_names() {
+        assert!(matches!(
+            validate_language_name(".hidden"),
+            Err(AppError::InvalidNameEdges(_))
+        ));
+        assert!(matches!(
+            validate_language_name("korean."),
+            Err(AppError::InvalidNameEdges(_))
+        ));
+        for name in ["a/b", "a\\b", "C:korean"] {
+            assert!(
+                matches!(
+                    validate_language_name(name),
+                    Err(AppError::InvalidNameChars(_))
+                ),
+                "{}",
+                name
+            );
+        }
+        assert!(matches!(
+            validate_language_name("a..b"),
+            Err(AppError::InvalidNameChars(_))
+        ));
+        assert!(matches!(
+            validate_language_name("nul.txt"),
+            Err(AppError::ReservedName(_))
+        ));
+    }
+
+    #[test]
+    fn validate_language_name_limits_length_in_characters() {
+        let longest = "語".repeat(MAX_LANGUAGE_NAME_CHARS);
+        assert!(validate_language_name(&longest).is_ok());
+        assert!(matches!(
+            validate_language_name(&format!("{}語", longest)),
+            Err(AppError::InvalidName(_))
+        ));
+        assert!(matches!(
+            validate_language_name(""),
+            Err(AppError::InvalidName(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn a_deleted_language_with_underscores_can_be_restored() {
+        test_root();
+        let language = unique("Old_Norse");
+        bootstrap_language(language.clone()).unwrap();
+        let lang_dir = get_language_dir(&language).unwrap();
+
+        delete_language(language.clone()).await.unwrap();
+        assert!(!lang_dir.exists());
+        assert!(list_trash()
+            .unwrap()
+            .iter()
+            .any(|(_, name)| *name == language_key(&lang_dir)));
+
+        restore_language(language).unwrap();
+        assert!(lang_dir.join("vocabulary.json").exists());
+    }
+
+    #[test]
+    fn is_first_session_without_a_project_dir() {
+        let (_, lang_dir) = test_language("First None");
+        assert!(!get_claude_project_dir(&lang_dir).unwrap().exists());
+        assert!(is_first_session_at(&lang_dir, &ChatHistoryCache::default()).unwrap());
+    }
+
+    #[test]
+    fn is_first_session_with_an_empty_project_dir() {
+        let (_, lang_dir) = test_language("First Empty");
+        fs::create_dir_all(get_claude_project_dir(&lang_dir).unwrap()).unwrap();
+        assert!(is_first_session_at(&lang_dir, &ChatHistoryCache::default()).unwrap());
+    }
+
+    #[test]
+    fn is_first_session_with_an_existing_session() {
+        let (_, lang_dir) = test_language("First Existing");
+        write_session(
+            &lang_dir,
+            "22222222-bbbb",
+            &[
+                session_line("user", "hola", "a"),
+                session_line("assistant", "¡Hola!", "b"),
+            ],
+        );
+        assert!(!is_first_session_at(&lang_dir, &ChatHistoryCache::default()).unwrap());
+    }
+
+    #[test]
+    fn parse_chat_messages_counts_a_truncated_last_line() {
+        let path = scratch_dir("Truncated").join("session.jsonl");
+        let last = session_line("assistant", "cut off", "b");
+        let content = format!(
+            "{}\n{}",
+            session_line("user", "kept", "a"),
+            &last[..last.len() / 2]
+        );
+        fs::write(&path, content).unwrap();
+
+        let parsed = parse_chat_messages_from_jsonl(&path).unwrap();
+        assert_eq!(contents(&parsed.messages), ["kept"]);
+        assert_eq!(parsed.skipped_lines, 1);
+    }
+
+    #[test]
+    fn parse_chat_messages_counts_malformed_lines() {
+        let path = scratch_dir("Malformed").join("session.jsonl");
+        let content = format!(
+            "{}\nnot json\n\n{}\n",
+            session_line("user", "one", "a"),
+            session_line("assistant", "two", "b")
+        );
+        fs::write(&path, content).unwrap();
+
+        let parsed = parse_chat_messages_from_jsonl(&path).unwrap();
+        assert_eq!(contents(&parsed.messages), ["one", "two"]);
+        assert_eq!(parsed.skipped_lines, 1);
+    }
+
+    #[tokio::test]
+    async fn acquire_send_slot_queues_or_reports_busy() {
+        let language = unique("Send Slot");
+        let first = acquire_send_slot(&language, true).await.unwrap();
+
+        let err = acquire_send_slot(&language, false).await.unwrap_err();
+        assert!(matches!(err, AppError::Busy(_)));
+
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let mut waiters = Vec::new();
+        for label in ["second", "third"] {
+            let (language, order) = (language.clone(), Arc::clone(&order));
+            waiters.push(tokio::spawn(async move {
+                let _slot = acquire_send_slot(&language, true).await.unwrap();
+                order.lock().unwrap().push(label);
+            }));
+            // Let the waiter queue up before the next one starts
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert!(order.lock().unwrap().is_empty());
+
+        drop(first);
+        for waiter in waiters {
+            waiter.await.unwrap();
+        }
+        assert_eq!(*order.lock().unwrap(), ["second", "third"]);
+        assert!(acquire_send_slot(&language, false).await.is_ok());
+    }
+
+    fn date(s: &str) -> NaiveDate {
+        parse_date(s).unwrap()
+    }
+
+    fn week_counts(series: &[WeeklyCount]) -> Vec<(&str, usize)> {
+        series
+            .iter()
+            .map(|w| (w.week_start.as_str(), w.count))
+            .collect()
+    }
+
+    #[test]
+    fn weekly_counts_of_nothing_is_empty() {
+        assert!(weekly_counts(Vec::<NaiveDate>::new()).is_empty());
+    }
+
+    #[test]
+    fn weekly_counts_groups_a_week_under_its_monday() {
+        // 2024-03-04 is a Monday, 2024-03-10 the Sunday after it
+        let series = weekly_counts([date("2024-03-04"), date("2024-03-07"), date("2024-03-10")]);
+        assert_eq!(week_counts(&series), [("2024-03-04", 3)]);
+    }
+
+    #[test]
+    fn weekly_counts_fills_empty_weeks_with_zeros() {
+        let series = weekly_counts([date("2024-03-20"), date("2024-03-05"), date("2024-03-06")]);
+        assert_eq!(
+            week_counts(&series),
+            [("2024-03-04", 2), ("2024-03-11", 0), ("2024-03-18", 1)]
+        );
+    }
+
+    #[test]
+    fn weekly_counts_crosses_a_year_boundary() {
+        let series = weekly_counts([date("2024-12-31"), date("2025-01-08")]);
+        assert_eq!(week_counts(&series), [("2024-12-30", 1), ("2025-01-06", 1)]);
+    }
+
+    #[test]
+    fn merge_entries_keeps_the_further_along_copy() {
+        let mut target = vec![
+            json!({ "word": "gato", "repetitions": 4, "interval": 20 }),
+            json!({ "word": "perro", "repetitions": 1, "interval": 1 }),
+        ];
+        let source = vec![
+            json!({ "word": "gato", "repetitions": 2, "interval": 6 }),
+            json!({ "word": "perro", "repetitions": 3, "interval": 15 }),
+            json!({ "word": "pájaro", "repetitions": 1, "interval": 1 }),
+            json!({ "word": "pez", "repetitions": 9, "interval": 200 }),
+        ];
+        let skip = HashSet::from(["pez".to_string()]);
+
+        let changed = merge_entries(&mut target, source, "word", VOCAB_PROGRESS_FIELDS, &skip);
+        assert_eq!(changed, 2);
+        assert_eq!(
+            target,
+            [
+                json!({ "word": "gato", "repetitions": 4, "interval": 20 }),
+                json!({ "word": "perro", "repetitions": 3, "interval": 15 }),
+                json!({ "word": "pájaro", "repetitions": 1, "interval": 1 }),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn merge_languages_combines_progress_without_duplicating_mature_words() {
+        let (source, source_dir) = test_language("Merge Source");
+        let (target, target_dir) = test_language("Merge Target");
+        write_json_atomic(
+            &source_dir.join("vocabulary.json"),
+            &json!({
+                "language": source,
+                "words": [
+                    { "word": "agua", "repetitions": 5, "interval": 30, "ease": 2.5 },
+                    { "word": "sol", "repetitions": 1, "interval": 1, "ease": 2.5 },
+                ],
+            }),
+        )
+        .unwrap();
+        write_json_atomic(
+            &source_dir.join("grammar.json"),
+            &json!({
+                "language": source,
+                "rules": [{ "rule": "ser vs estar", "stars": 4, "correct_streak": 2 }],
+            }),
+        )
+        .unwrap();
+        write_json_atomic(
+            &target_dir.join("vocabulary.json"),
+            &json!({
+                "language": target,
+                "words": [{ "word": "agua", "repetitions": 1, "interval": 1, "ease": 2.5 }],
+            }),
+        )
+        .unwrap();
+        write_json_atomic(
+            &target_dir.join("grammar.json"),
+            &json!({
+                "language": target,
+                "rules": [{ "rule": "ser vs estar", "stars": 2, "correct_streak": 0 }],
+            }),
+        )
+        .unwrap();
+        write_json_atomic(
+            &target_dir.join(MATURE_VOCABULARY_FILE),
+            &json!({
+                "language": target,
+                "words": [{ "word": "sol", "repetitions": 8, "interval": 200, "ease": 2.5 }],
+            }),
+        )
+        .unwrap();
+
+        let summary = merge_languages(source, target).await.unwrap();
+        assert_eq!((summary.words, summary.rules), (1, 1));
+        assert!(!source_dir.exists());
+
+        let vocab = read_vocabulary(&target_dir).unwrap();
+        assert_eq!(word_list(&vocab), ["agua"]);
+        assert_eq!(vocabulary_words(&vocab)[0]["repetitions"], 5);
+        let mature = read_mature_vocabulary(&target_dir).unwrap();
+        assert_eq!(word_list(&mature), ["sol"]);
+        assert_eq!(vocabulary_words(&mature)[0]["interval"], 200);
+        let grammar = read_grammar(&target_dir).unwrap();
+        assert_eq!(grammar_rules(&grammar)[0]["stars"], 4);
+    }
+
+    #[test]
+    fn write_atomic_ignores_a_stale_temp_file() {
+        let dir = scratch_dir("Atomic Stale");
+        let path = dir.join("vocabulary.json");
+        fs::write(&path, "old").unwrap();
+        // What a write killed before its rename leaves behind
+        fs::write(dir.join("vocabulary.json.tmp"), "half wri").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "old");
+
+        write_atomic(&path, b"new").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+        assert!(!dir.join("vocabulary.json.tmp").exists());
+    }
+
+    #[test]
+    fn write_atomic_leaves_the_target_intact_when_the_write_fails() {
+        let dir = scratch_dir("Atomic Fail");
+        let path = dir.join("grammar.json");
+        fs::write(&path, "old").unwrap();
+        // A directory in the temp file's place makes creating it fail
+        fs::create_dir(dir.join("grammar.json.tmp")).unwrap();
+
+        assert!(write_atomic(&path, b"new").is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "old");
+    }
+}
@@ -0,0 +1,183 @@
+//! SM-2 spaced repetition scheduling.
+//!
+//! The tracker only reports which words the learner used; every change to
+//! `ease`, `interval`, `repetitions` and `next_review` goes through `review`
+//! so scheduling is deterministic rather than left to the LLM.
+
+use chrono::{Days, Local, NaiveDate};
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::{Map, Value};
+
+use crate::DATE_FORMAT;
+
+pub const MIN_EASE: f64 = 1.3;
+pub const DEFAULT_EASE: f64 = 2.5;
+
+/// Quality assigned when the tracker sees the learner use a known word correctly.
+pub const QUALITY_USED_CORRECTLY: u8 = 4;
+
+/// A vocabulary.json entry with its scheduling fields typed. Any other fields
+/// (meaning, notes, added, ...) are kept in `extra` and written back unchanged.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct VocabItem {
+    pub word: String,
+    #[serde(default = "default_ease")]
+    pub ease: f64,
+    #[serde(default, deserialize_with = "lenient_u32")]
+    pub interval: u32,
+    #[serde(default, deserialize_with = "lenient_u32")]
+    pub repetitions: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_review: Option<String>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+fn default_ease() -> f64 {
+    DEFAULT_EASE
+}
+
+/// Accepts whole numbers written as floats (e.g. `6.0`), which the LLM
+/// tracker has been known to produce.
+fn lenient_u32<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u32, D::Error> {
+    let n = f64::deserialize(deserializer)?;
+    Ok(n.round().clamp(0.0, f64::from(u32::MAX)) as u32)
+}
+
+/// Applies one review with quality 0-5 (0 = blackout, 5 = perfect recall),
+/// scheduling the next review from today.
+pub fn review(item: &mut VocabItem, quality: u8) {
+    review_on(item, quality, Local::now().date_naive());
+}
+
+/// Standard SM-2 update:
+/// - the ease factor moves by `0.1 - (5 - q) * (0.08 + (5 - q) * 0.02)` and
+///   never drops below 1.3
+/// - quality below 3 resets repetitions and the interval to 1 day
+/// - otherwise the interval goes 1, 6, then previous interval × previous ease
+pub fn review_on(item: &mut VocabItem, quality: u8, today: NaiveDate) {
+    let quality = quality.min(5);
+    let q = f64::from(5 - quality);
+    let previous_ease = item.ease;
+
+    item.ease = (previous_ease + (0.1 - q * (0.08 + q * 0.02))).max(MIN_EASE);
+
+    if quality < 3 {
+        item.repetitions = 0;
+        item.interval = 1;
+    } else {
+        item.interval = match item.repetitions {
+            0 => 1,
+            1 => 6,
+            _ => (f64::from(item.interval) * previous_ease).round() as u32,
+        };
+        item.repetitions += 1;
+    }
+
+    item.next_review = Some(
+        (today + Days::new(u64::from(item.interval)))
+            .format(DATE_FORMAT)
+            .to_string(),
+    );
+}
+
+/// Reviews a raw vocabulary.json entry in place.
+pub fn review_entry(entry: &mut Value, quality: u8) -> Result<VocabItem, String> {
+    let mut item: VocabItem = serde_json::from_value(entry.clone())
+        .map_err(|e| format!("Invalid vocabulary entry: {}", e))?;
+    review(&mut item, quality);
+    *entry =
+        serde_json::to_value(&item).map_err(|e| format!("Failed to serialize entry: {}", e))?;
+    Ok(item)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item() -> VocabItem {
+        serde_json::from_value(serde_json::json!({ "word": "你好" })).unwrap()
+    }
+
+    fn day(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, DATE_FORMAT).unwrap()
+    }
+
+    #[test]
+    fn intervals_go_one_six_then_ease_times_interval() {
+        let mut item = item();
+        let today = day("2024-01-01");
+
+        review_on(&mut item, 4, today);
+        assert_eq!((item.repetitions, item.interval), (1, 1));
+        review_on(&mut item, 4, today);
+        assert_eq!((item.repetitions, item.interval), (2, 6));
+
+        // Quality 4 leaves the ease unchanged, so the third interval is 6 × 2.5
+        assert!((item.ease - DEFAULT_EASE).abs() < 1e-9);
+        review_on(&mut item, 4, today);
+        assert_eq!((item.repetitions, item.interval), (3, 15));
+    }
+
+    #[test]
+    fn third_interval_uses_previous_ease() {
+        let mut item = item();
+        item.repetitions = 2;
+        item.interval = 6;
+        item.ease = 2.0;
+        review_on(&mut item, 5, day("2024-01-01"));
+        assert_eq!(item.interval, 12);
+        assert!((item.ease - 2.1).abs() < 1e-9);
+    }
+
+    #[test]
+    fn failing_quality_resets_repetitions_and_interval() {
+        let mut item = item();
+        item.repetitions = 5;
+        item.interval = 40;
+        review_on(&mut item, 2, day("2024-01-01"));
+        assert_eq!((item.repetitions, item.interval), (0, 1));
+        assert_eq!(item.next_review.as_deref(), Some("2024-01-02"));
+    }
+
+    #[test]
+    fn ease_never_drops_below_floor() {
+        let mut item = item();
+        item.ease = 1.35;
+        review_on(&mut item, 0, day("2024-01-01"));
+        assert_eq!(item.ease, MIN_EASE);
+        review_on(&mut item, 0, day("2024-01-01"));
+        assert_eq!(item.ease, MIN_EASE);
+    }
+
+    #[test]
+    fn quality_above_five_is_clamped() {
+        let mut clamped = item();
+        let mut perfect = item();
+        review_on(&mut clamped, 9, day("2024-01-01"));
+        review_on(&mut perfect, 5, day("2024-01-01"));
+        assert_eq!(clamped.ease, perfect.ease);
+        assert_eq!(clamped.interval, perfect.interval);
+    }
+
+    #[test]
+    fn next_review_is_today_plus_interval() {
+        let mut item = item();
+        item.repetitions = 1;
+        review_on(&mut item, 4, day("2024-02-27"));
+        assert_eq!(item.interval, 6);
+        assert_eq!(item.next_review.as_deref(), Some("2024-03-04"));
+    }
+
+    #[test]
+    fn lenient_u32_accepts_whole_floats() {
+        let item: VocabItem = serde_json::from_value(serde_json::json!({
+            "word": "x",
+            "interval": 6.0,
+            "repetitions": 2
+        }))
+        .unwrap();
+        assert_eq!(item.interval, 6);
+        assert_eq!(item.repetitions, 2);
+    }
+}
@@ -1,5 +1,6 @@
 import { useState, useEffect, useRef } from "react";
 import { invoke } from "@tauri-apps/api/core";
+import { save } from "@tauri-apps/plugin-dialog";
 import ReactMarkdown from "react-markdown";
 import { getErrorMessage, capitalize } from "../utils/strings";
 
@@ -7,6 +8,7 @@ type Message = {
   id: string;
   role: "user" | "assistant";
   content: string;
+  timestamp?: string;
 };
 
 function generateMessageId(): string {
@@ -24,6 +26,7 @@ export default function ChatView({ language, onBack }: Props) {
   const [isLoading, setIsLoading] = useState(false);
   const [isLoadingHistory, setIsLoadingHistory] = useState(false);
   const [historyError, setHistoryError] = useState<string | null>(null);
+  const [exportError, setExportError] = useState<string | null>(null);
   const chatContainerRef = useRef<HTMLDivElement>(null);
 
   useEffect(() => {
@@ -40,8 +43,8 @@ export default function ChatView({ language, onBack }: Props) {
     setIsLoadingHistory(true);
     setHistoryError(null);
     try {
-      const history = await invoke<Omit<Message, "id">[]>("get_chat_history", { language });
-      setMessages(history.map(msg => ({ ...msg, id: generateMessageId() })));
+      const history = await invoke<(Omit<Message, "id"> & { id?: string })[]>("get_chat_history", { language });
+      setMessages(history.map(msg => ({ ...msg, id: msg.id ?? generateMessageId() })));
     } catch (error) {
       console.error("Failed to load chat history:", error);
       setHistoryError(getErrorMessage(error));
@@ -60,11 +63,11 @@ export default function ChatView({ language, onBack }: Props) {
     setIsLoading(true);
 
     try {
-      const response = await invoke<string>("send_message", {
+      const { reply } = await invoke<{ reply: string }>("send_message", {
         message: userMessage,
         language,
       });
-      setMessages((prev) => [...prev, { id: generateMessageId(), role: "assistant", content: response }]);
+      setMessages((prev) => [...prev, { id: generateMessageId(), role: "assistant", content: reply }]);
     } catch (error) {
       setMessages((prev) => [
         ...prev,
@@ -75,6 +78,25 @@ export default function ChatView({ language, onBack }: Props) {
     }
   }
 
+  async function exportTranscript() {
+    const destPath = await save({
+      defaultPath: `${language}-transcript.md`,
+      filters: [
+        { name: "Markdown", extensions: ["md"] },
+        { name: "HTML", extensions: ["html"] },
+      ],
+    });
+    if (!destPath) return;
+
+    setExportError(null);
+    try {
+      const format = destPath.toLowerCase().endsWith(".html") ? "html" : "markdown";
+      await invoke<string>("export_transcript", { language, format, destPath });
+    } catch (error) {
+      setExportError(getErrorMessage(error));
+    }
+  }
+
   const displayName = capitalize(language);
 
   function handleKeyDown(e: React.KeyboardEvent<HTMLTextAreaElement>) {
@@ -88,10 +110,14 @@ export default function ChatView({ language, onBack }: Props) {
     <div className="container">
       <header>
         <h1>Learning {displayName}</h1>
+        <button className="back-btn" onClick={exportTranscript} disabled={messages.length === 0}>
+          Export
+        </button>
         <button className="back-btn" onClick={onBack}>
           Change Language
         </button>
       </header>
+      {exportError && <p className="hint error">Export failed: {exportError}</p>}
 
       <div className="chat-container" ref={chatContainerRef}>
         {isLoadingHistory && (
@@ -1,6 +1,24 @@
+/** Error shape returned by every backend command. */
+export type AppError = {
+  code: string;
+  message: string;
+  /** Index of the offending entry for `invalid_data` errors. */
+  entry?: number;
+};
+
+export function isAppError(error: unknown): error is AppError {
+  return (
+    typeof error === "object" &&
+    error !== null &&
+    typeof (error as AppError).code === "string" &&
+    typeof (error as AppError).message === "string"
+  );
+}
+
 export function getErrorMessage(error: unknown): string {
   if (error instanceof Error) return error.message;
   if (typeof error === "string") return error;
+  if (isAppError(error)) return error.message;
   return "Unknown error";
 }
 
@@ -10,7 +28,7 @@ export function capitalize(str: string): string {
 }
 
 export function normalizeLanguage(language: string): string {
-  return language.toLowerCase();
+  return language.trim().split(/\s+/).join(" ").toLowerCase();
 }
 
 export function isLanguageInList(language: string, list: string[]): boolean {